@@ -1,61 +1,405 @@
 //! ARP 协议实现
 //!
 //! ARP（Address Resolution Protocol）用于将 IP 地址解析为 MAC 地址
-use tracing::info;
+use tracing::{debug, info, warn};
+
+pub mod acd;
+
+use acd::{Acd, AddressState, Conflict};
 
 use crate::error::{Result, StackError};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::Hash;
 use std::net::Ipv4Addr;
 use std::time::{Duration, Instant};
 
 const ARP_PACKET_MIN_LEN: usize = 28;
 
+/// 两次 ARP 请求（邻居请求）之间的间隔（RETRANS_TIMER）
+const ARP_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 最多发送的 ARP 请求（组播邻居请求）次数，之后认为主机不可达
+/// （对应 Linux 的 mcast_solicit 和 RFC 4861 的 MAX_MULTICAST_SOLICIT）
+const ARP_MAX_REQUESTS: u32 = 3;
+
+/// 每个等待解析的地址最多缓存的包数量，超出时丢弃最旧的包
+const ARP_PENDING_QUEUE_LEN: usize = 16;
+
+const BROADCAST_MAC: MacAddr = [0xff; 6];
+
 pub type MacAddr = [u8; 6];
 
+/// 邻居可达性确认后保持 REACHABLE 的时间（RFC 4861 REACHABLE_TIME）
+const REACHABLE_TIME: Duration = Duration::from_secs(30);
+
+/// 进入 DELAY 后等待上层确认的时间，超时开始探测（DELAY_FIRST_PROBE_TIME）
+const DELAY_FIRST_PROBE_TIME: Duration = Duration::from_secs(5);
+
+/// PROBE 状态下最多发送的单播探测次数（MAX_UNICAST_SOLICIT）
+const MAX_UNICAST_PROBES: u32 = 3;
+
+/// STALE 表项超过这个时间没有使用就删除
+const STALE_GC_TIME: Duration = Duration::from_secs(300);
+
+/// 缓存表项数量上限，超出时淘汰最久未使用的表项
+const DEFAULT_MAX_ENTRIES: usize = 512;
+
+/// 最多保留的未取出事件数量，超出时丢弃最旧的事件
+const ARP_EVENT_QUEUE_LEN: usize = 64;
+
+/// 邻居状态（RFC 4861 第 7.3.2 节）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NeighborState {
+    Incomplete, // 正在解析，还不知道 MAC
+    Reachable,  // 最近确认过可达
+    Stale,      // 可达性未知，可以继续使用，发送时进入 DELAY
+    Delay,      // 等待上层确认
+    Probe,      // 正在发送单播请求确认可达性
+    Permanent,  // 静态表项，不会过期，也不会被 ARP 报文修改
+}
+
+impl fmt::Display for NeighborState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            NeighborState::Incomplete => "INCOMPLETE",
+            NeighborState::Reachable => "REACHABLE",
+            NeighborState::Stale => "STALE",
+            NeighborState::Delay => "DELAY",
+            NeighborState::Probe => "PROBE",
+            NeighborState::Permanent => "PERMANENT",
+        };
+        f.write_str(name)
+    }
+}
+
+/// 邻居表的一行，显示格式与 `ip neigh show` 相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Neighbor<A = Ipv4Addr> {
+    pub ip: A,
+    pub mac: Option<MacAddr>, // INCOMPLETE 状态还没有 MAC
+    pub state: NeighborState,
+}
+
+impl<A: fmt::Display> fmt::Display for Neighbor<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.ip)?;
+        if let Some(mac) = self.mac {
+            write!(
+                f,
+                " lladdr {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+                mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+            )?;
+        }
+        write!(f, " {}", self.state)
+    }
+}
+
+/// 从 ARP 报文学习地址的策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ArpPolicy {
+    /// 只从我们请求的响应和目标是本机的请求中学习，
+    /// 已知地址的 MAC 只有在我们请求的响应中才能修改
+    #[default]
+    Strict,
+    /// 从看到的每个 ARP 报文学习（RFC 826 的原始行为）
+    Permissive,
+}
+
+/// ARP 模块产生的事件，供上层记录或告警
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArpEvent {
+    /// 已知 IP 声称了不同的 MAC，accepted 表示是否按策略更新了缓存
+    MacChanged {
+        ip: Ipv4Addr,
+        old_mac: MacAddr,
+        new_mac: MacAddr,
+        accepted: bool,
+    },
+    /// 其他主机在使用本机地址，defended 表示是否发送了通告保护地址，
+    /// 为 false 时已放弃该地址（RFC 5227）
+    AddressConflict {
+        ip: Ipv4Addr,
+        mac: MacAddr,
+        defended: bool,
+    },
+}
+
+/// 邻居表项
+#[derive(Debug, Clone)]
+struct NeighborEntry {
+    mac: MacAddr,
+    state: NeighborState,
+    updated: Instant,   // 进入当前状态（或上次探测）的时间
+    last_used: Instant, // 上次用于发送的时间，用于 LRU 淘汰
+    probes: u32,        // PROBE 状态下已发送的探测次数
+}
+
+/// 正在解析的地址（INCOMPLETE 状态）
+#[derive(Debug)]
+struct PendingResolution {
+    packets: VecDeque<Vec<u8>>, // 等待发送的包
+    requests: u32,              // 已发送的请求次数
+    next_request: Instant,      // 下一次发送请求的时间
+}
+
+/// 邻居缓存，IPv4 的 ARP 和 IPv6 的邻居发现共用同一套状态机和等待解析的队列
 #[derive(Debug)]
-pub struct ArpCache {
-    entries: HashMap<Ipv4Addr, (MacAddr, Instant)>,
-    timeout: Duration,
+pub struct NeighborCache<A> {
+    entries: HashMap<A, NeighborEntry>,
+    pending: HashMap<A, PendingResolution>,
+    timeout: Duration,  // REACHABLE 状态的有效期
+    max_entries: usize, // 缓存表项和正在解析的地址各自的上限
 }
 
-impl ArpCache {
+pub type ArpCache = NeighborCache<Ipv4Addr>;
+
+impl<A: Copy + Eq + Hash + fmt::Display> NeighborCache<A> {
     pub fn new(timeout: Duration) -> Self {
         Self {
             entries: HashMap::new(),
+            pending: HashMap::new(),
             timeout,
+            max_entries: DEFAULT_MAX_ENTRIES,
         }
     }
 
-    // 更新缓存
-    pub fn insert(&mut self, ip: Ipv4Addr, mac: MacAddr) {
-        self.entries.insert(ip, (mac, Instant::now()));
-        info!("ARP cache insert: {} -> {:02x?}", ip, mac);
+    pub fn set_max_entries(&mut self, max_entries: usize) {
+        self.max_entries = max_entries.max(1);
+        while self.entries.len() > self.max_entries {
+            self.evict_lru();
+        }
     }
 
-    // 查找mac地址
-    pub fn loopup(&mut self, ip: &Ipv4Addr) -> Option<MacAddr> {
-        if let Some((mac, timestamp)) = self.entries.get(ip) {
-            if timestamp.elapsed() < self.timeout {
-                // 缓存命中
-                info!("ARP cache hit: {} -> {:02x?}", ip, mac);
-                return Some(*mac);
-            } else {
-                // 缓存过期
-                info!("ARP cache expired: {}", ip);
-                self.entries.remove(ip);
-            }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // 更新缓存，收到对方的 ARP 响应说明它确实可达
+    pub fn insert(&mut self, ip: A, mac: MacAddr, now: Instant) {
+        self.update(ip, mac, NeighborState::Reachable, now);
+        info!("Neighbor cache insert: {} -> {:02x?}", ip, mac);
+    }
+
+    /// 从对方的 ARP 请求中学到地址：新表项或 MAC 变化时进入 STALE，
+    /// MAC 不变时保持原状态（RFC 4861 第 7.2.3 节）
+    pub fn learn(&mut self, ip: A, mac: MacAddr, now: Instant) {
+        if self.entries.get(&ip).is_some_and(|entry| entry.mac == mac) {
+            return;
         }
-        None
+        self.update(ip, mac, NeighborState::Stale, now);
+        debug!("Neighbor cache learn: {} -> {:02x?}", ip, mac);
     }
-    // 清理过期缓存
-    pub fn clean_up(&mut self) {
-        self.entries
-            .retain(|_, (_, timestamp)| timestamp.elapsed() < self.timeout);
+
+    fn update(&mut self, ip: A, mac: MacAddr, state: NeighborState, now: Instant) {
+        if !self.entries.contains_key(&ip) && self.entries.len() >= self.max_entries {
+            self.evict_lru();
+        }
+        let entry = self.entries.entry(ip).or_insert(NeighborEntry {
+            mac,
+            state,
+            updated: now,
+            last_used: now,
+            probes: 0,
+        });
+        entry.mac = mac;
+        entry.state = state;
+        entry.updated = now;
+        entry.probes = 0;
+    }
+
+    /// 添加静态表项，静态表项不参与 LRU 淘汰
+    pub fn insert_static(&mut self, ip: A, mac: MacAddr, now: Instant) {
+        self.update(ip, mac, NeighborState::Permanent, now);
+        info!("Neighbor cache insert static: {} -> {:02x?}", ip, mac);
+    }
+
+    /// 查看缓存的 MAC，不改变表项状态
+    pub fn mac(&self, ip: &A) -> Option<MacAddr> {
+        self.entries.get(ip).map(|entry| entry.mac)
+    }
+
+    fn evict_lru(&mut self) {
+        let lru = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.state != NeighborState::Permanent)
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(ip, _)| *ip);
+        if let Some(ip) = lru {
+            debug!("Neighbor cache full, evict {}", ip);
+            self.entries.remove(&ip);
+        }
+    }
+
+    // 查找mac地址，用于发送时 STALE 表项进入 DELAY 等待确认
+    pub fn loopup(&mut self, ip: &A, now: Instant) -> Option<MacAddr> {
+        let timeout = self.timeout;
+        let entry = self.entries.get_mut(ip)?;
+        if entry.state == NeighborState::Reachable && now.duration_since(entry.updated) >= timeout {
+            entry.state = NeighborState::Stale;
+            entry.updated = now;
+        }
+        if entry.state == NeighborState::Stale {
+            debug!("Neighbor {} stale, wait for confirmation", ip);
+            entry.state = NeighborState::Delay;
+            entry.updated = now;
+        }
+        entry.last_used = now;
+        Some(entry.mac)
+    }
+
+    /// 上层（例如 TCP 收到新数据的 ACK）确认邻居可达
+    pub fn confirm(&mut self, ip: &A, now: Instant) {
+        if let Some(entry) = self.entries.get_mut(ip)
+            && entry.state != NeighborState::Permanent
+        {
+            entry.state = NeighborState::Reachable;
+            entry.updated = now;
+            entry.probes = 0;
+        }
+    }
+
+    pub fn state(&self, ip: &A) -> Option<NeighborState> {
+        self.entries.get(ip).map(|entry| entry.state)
+    }
+
+    /// 推进状态机，返回需要发送单播探测的邻居；探测次数用完的表项被删除
+    pub fn poll(&mut self, now: Instant) -> Vec<(A, MacAddr)> {
+        let mut probes = Vec::new();
+        let timeout = self.timeout;
+        self.entries.retain(|ip, entry| {
+            let elapsed = now.duration_since(entry.updated);
+            match entry.state {
+                NeighborState::Reachable if elapsed >= timeout => {
+                    entry.state = NeighborState::Stale;
+                    entry.updated = now;
+                }
+                NeighborState::Delay if elapsed >= DELAY_FIRST_PROBE_TIME => {
+                    entry.state = NeighborState::Probe;
+                    entry.updated = now;
+                    entry.probes = 1;
+                    probes.push((*ip, entry.mac));
+                }
+                NeighborState::Probe if elapsed >= ARP_RETRY_INTERVAL => {
+                    if entry.probes >= MAX_UNICAST_PROBES {
+                        info!("Neighbor {} unreachable", ip);
+                        return false;
+                    }
+                    entry.probes += 1;
+                    entry.updated = now;
+                    probes.push((*ip, entry.mac));
+                }
+                _ => {}
+            }
+            true
+        });
+        self.clean_up(now);
+        probes
+    }
+
+    // 清理长时间没有使用的 STALE 表项
+    pub fn clean_up(&mut self, now: Instant) {
+        self.entries.retain(|_, entry| {
+            entry.state != NeighborState::Stale
+                || now.duration_since(entry.last_used) < STALE_GC_TIME
+        });
     }
 
     // 删除缓存
-    pub fn remove(&mut self, ip: &Ipv4Addr) -> Option<MacAddr> {
-        self.entries.remove(ip).map(|(mac, _)| mac)
+    pub fn remove(&mut self, ip: &A) -> Option<MacAddr> {
+        self.entries.remove(ip).map(|entry| entry.mac)
+    }
+
+    /// 删除所有动态表项和正在进行的解析，静态表项保留（同 `ip neigh flush`）
+    pub fn flush(&mut self) {
+        self.entries
+            .retain(|_, entry| entry.state == NeighborState::Permanent);
+        self.pending.clear();
+    }
+
+    /// 地址是否正在解析
+    pub fn is_pending(&self, ip: &A) -> bool {
+        self.pending.contains_key(ip)
+    }
+
+    /// 正在解析的地址
+    pub fn pending(&self) -> impl Iterator<Item = A> + '_ {
+        self.pending.keys().copied()
+    }
+
+    /// 把包放进 ip 的等待队列，队列满时丢弃最旧的包；
+    /// 正在解析的地址太多时丢弃这个包并返回 false
+    pub fn queue(&mut self, ip: A, packet: Vec<u8>, now: Instant) -> bool {
+        if !self.pending.contains_key(&ip) && self.pending.len() >= self.max_entries {
+            debug!("Too many unresolved neighbors, drop packet to {}", ip);
+            return false;
+        }
+        let pending = self.pending.entry(ip).or_insert_with(|| {
+            info!("Neighbor resolving {}", ip);
+            PendingResolution {
+                packets: VecDeque::new(),
+                requests: 0,
+                next_request: now,
+            }
+        });
+        if pending.packets.len() >= ARP_PENDING_QUEUE_LEN {
+            pending.packets.pop_front();
+        }
+        pending.packets.push_back(packet);
+        true
+    }
+
+    /// 还没有为 ip 发送过请求时记录第一个请求，返回 true 表示调用者要立即发送
+    pub fn start_resolution(&mut self, ip: &A, now: Instant) -> bool {
+        match self.pending.get_mut(ip) {
+            Some(pending) if pending.requests == 0 => {
+                pending.requests = 1;
+                pending.next_request = now + ARP_RETRY_INTERVAL;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 解析完成或放弃解析，取出等待的包
+    pub fn take_pending(&mut self, ip: &A) -> Option<Vec<Vec<u8>>> {
+        self.pending
+            .remove(ip)
+            .map(|pending| pending.packets.into())
+    }
+
+    /// 推进解析定时器：返回需要重发广播请求的地址，以及请求次数用完、解析失败而丢弃的包
+    pub fn poll_pending(&mut self, now: Instant) -> (Vec<A>, Vec<Vec<u8>>) {
+        let mut retries = Vec::new();
+        let mut failed = Vec::new();
+        self.pending.retain(|ip, pending| {
+            if now < pending.next_request {
+                return true;
+            }
+            if pending.requests >= ARP_MAX_REQUESTS {
+                info!("Neighbor resolution of {} failed", ip);
+                failed.extend(pending.packets.drain(..));
+                return false;
+            }
+            pending.requests += 1;
+            pending.next_request = now + ARP_RETRY_INTERVAL;
+            retries.push(*ip);
+            true
+        });
+        (retries, failed)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Neighbor<A>> + '_ {
+        self.entries.iter().map(|(ip, entry)| Neighbor {
+            ip: *ip,
+            mac: Some(entry.mac),
+            state: entry.state,
+        })
     }
 }
 
@@ -64,21 +408,160 @@ pub struct ArpModule {
     cache: ArpCache,
     our_ip: Ipv4Addr,
     our_mac: MacAddr,
+    outgoing: Vec<(MacAddr, ArpPacket)>, // 待发送的 ARP 请求及目标 MAC
+    resolved: Vec<(MacAddr, Vec<u8>)>,   // 解析完成、可以发送的包
+    unreachable: Vec<Vec<u8>>,           // 解析失败而丢弃的包
+    policy: ArpPolicy,
+    events: VecDeque<ArpEvent>,
+    acd: Acd,                     // 本机地址的冲突检测
+    proxies: Vec<(Ipv4Addr, u8)>, // 代答 ARP 的网段（网络地址, 前缀长度）
 }
 
 impl ArpModule {
     pub fn new(our_ip: Ipv4Addr, our_mac: MacAddr) -> Self {
         Self {
-            cache: ArpCache::new(Duration::from_secs(300)),
+            acd: Acd::bound(our_ip, our_mac, Instant::now()),
+            cache: ArpCache::new(REACHABLE_TIME),
             our_ip,
             our_mac,
+            outgoing: Vec::new(),
+            resolved: Vec::new(),
+            unreachable: Vec::new(),
+            policy: ArpPolicy::default(),
+            proxies: Vec::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn our_ip(&self) -> Ipv4Addr {
+        self.our_ip
+    }
+
+    /// 修改本机地址，探测通过后才会使用新地址
+    pub fn set_ip(&mut self, ip: Ipv4Addr, now: Instant) {
+        self.our_ip = ip;
+        self.probe_address(now);
+    }
+
+    /// 接口启用时对当前地址做冲突检测，然后发送免费 ARP 通告
+    pub fn probe_address(&mut self, now: Instant) {
+        info!("ARP probing {}", self.our_ip);
+        self.acd = Acd::probe(self.our_ip, self.our_mac, now);
+    }
+
+    pub fn address_state(&self) -> AddressState {
+        self.acd.state()
+    }
+
+    pub fn set_policy(&mut self, policy: ArpPolicy) {
+        self.policy = policy;
+    }
+
+    /// 添加静态表项，之后的 ARP 报文无法修改它
+    pub fn add_static(&mut self, ip: Ipv4Addr, mac: MacAddr, now: Instant) {
+        self.cache.insert_static(ip, mac, now);
+    }
+
+    /// 删除邻居表项，同时丢弃正在等待解析的包
+    pub fn delete(&mut self, ip: Ipv4Addr) -> bool {
+        let removed = self.cache.remove(&ip).is_some();
+        self.cache.take_pending(&ip).is_some() || removed
+    }
+
+    /// 删除所有动态表项和正在进行的解析
+    pub fn flush(&mut self) {
+        self.cache.flush();
+    }
+
+    /// 按 IP 排序的邻居表，包括正在解析的地址
+    pub fn neighbors(&self) -> Vec<Neighbor> {
+        let mut neighbors: Vec<Neighbor> = self.cache.iter().collect();
+        neighbors.extend(self.cache.pending().map(|ip| Neighbor {
+            ip,
+            mac: None,
+            state: NeighborState::Incomplete,
+        }));
+        neighbors.sort_by_key(|neighbor| neighbor.ip);
+        neighbors
+    }
+
+    /// 为 network/prefix_len 网段内的主机代答 ARP 请求（RFC 1027）
+    pub fn add_proxy(&mut self, network: Ipv4Addr, prefix_len: u8) {
+        let prefix_len = prefix_len.min(32);
+        let network = Ipv4Addr::from(u32::from(network) & prefix_mask(prefix_len));
+        if !self.proxies.contains(&(network, prefix_len)) {
+            self.proxies.push((network, prefix_len));
         }
     }
 
-    pub fn handle_packet(&mut self, arp: &ArpPacket) -> Option<Vec<u8>> {
-        self.cache.insert(arp.sender_ip, arp.sender_mac);
+    pub fn remove_proxy(&mut self, network: Ipv4Addr, prefix_len: u8) -> bool {
+        let prefix_len = prefix_len.min(32);
+        let network = Ipv4Addr::from(u32::from(network) & prefix_mask(prefix_len));
+        let len = self.proxies.len();
+        self.proxies
+            .retain(|&(n, p)| (n, p) != (network, prefix_len));
+        self.proxies.len() != len
+    }
+
+    pub fn proxies(&self) -> &[(Ipv4Addr, u8)] {
+        &self.proxies
+    }
+
+    fn is_proxied(&self, ip: Ipv4Addr) -> bool {
+        self.proxies.iter().any(|&(network, prefix_len)| {
+            u32::from(ip) & prefix_mask(prefix_len) == u32::from(network)
+        })
+    }
+
+    pub fn handle_packet(&mut self, arp: &ArpPacket, now: Instant) -> Option<Vec<u8>> {
+        if !arp.is_ipv4_over_ethernet() {
+            debug!("Unsupported ARP hardware/protocol type, skip");
+            return None;
+        }
+        if let Some(conflict) = self.acd.on_packet(arp, now) {
+            let defended = matches!(conflict, Conflict::Defended(_));
+            warn!(
+                "Address {} is used by {:02x?}, defended: {}",
+                self.our_ip, arp.sender_mac, defended
+            );
+            self.push_event(ArpEvent::AddressConflict {
+                ip: self.our_ip,
+                mac: arp.sender_mac,
+                defended,
+            });
+            if let Conflict::Defended(announcement) = conflict {
+                self.outgoing.push((BROADCAST_MAC, announcement));
+            }
+        }
         let arp_operation = ArpOperation::from_u16(arp.operation);
-        if arp_operation == Some(ArpOperation::Request) && arp.target_ip == self.our_ip {
+        if !self.learn(arp, arp_operation, now) {
+            return self.reply_to(arp, arp_operation);
+        }
+        // 地址解析完成，发送排队的包
+        if let Some(packets) = self.cache.take_pending(&arp.sender_ip) {
+            info!(
+                "ARP resolved {}, flush {} queued packets",
+                arp.sender_ip,
+                packets.len()
+            );
+            self.resolved
+                .extend(packets.into_iter().map(|packet| (arp.sender_mac, packet)));
+        }
+        self.reply_to(arp, arp_operation)
+    }
+
+    fn reply_to(&self, arp: &ArpPacket, arp_operation: Option<ArpOperation>) -> Option<Vec<u8>> {
+        if arp_operation != Some(ArpOperation::Request) {
+            return None;
+        }
+        // 地址还在探测或已经冲突时不回答
+        let for_us = arp.target_ip == self.our_ip && self.acd.is_usable();
+        // 代答网段内的主机，但不回答网段内主机之间的请求和免费 ARP
+        let proxied = arp.target_ip != self.our_ip
+            && arp.target_ip != arp.sender_ip
+            && self.is_proxied(arp.target_ip)
+            && !self.is_proxied(arp.sender_ip);
+        if for_us || proxied {
             let reply = ArpPacket::build_reply(arp, self.our_mac);
             return Some(reply.to_bytes());
         }
@@ -86,8 +569,160 @@ impl ArpModule {
         None
     }
 
-    pub fn resolve(&mut self, ip: Ipv4Addr) -> Option<MacAddr> {
-        self.cache.loopup(&ip)
+    /// 按策略从 ARP 报文学习发送方地址，返回是否接受了发送方的 MAC
+    fn learn(
+        &mut self,
+        arp: &ArpPacket,
+        arp_operation: Option<ArpOperation>,
+        now: Instant,
+    ) -> bool {
+        let (ip, mac) = (arp.sender_ip, arp.sender_mac);
+        // 发送方必须是单播地址，不能冒充本机
+        if ip.is_unspecified()
+            || ip.is_broadcast()
+            || ip.is_multicast()
+            || ip == self.our_ip
+            || mac[0] & 0x01 != 0
+        {
+            return false;
+        }
+        // 我们发出过请求（正在解析或探测）的响应
+        let solicited = arp_operation == Some(ArpOperation::Reply)
+            && arp.target_ip == self.our_ip
+            && matches!(
+                self.neighbor_state(ip),
+                Some(NeighborState::Incomplete | NeighborState::Probe)
+            );
+        let for_us = arp_operation == Some(ArpOperation::Request) && arp.target_ip == self.our_ip;
+        let permissive = self.policy == ArpPolicy::Permissive;
+
+        match self.cache.mac(&ip) {
+            Some(old_mac) if old_mac != mac => {
+                let accepted = self.cache.state(&ip) != Some(NeighborState::Permanent)
+                    && (permissive || solicited);
+                warn!(
+                    "ARP {} moved from {:02x?} to {:02x?}, accepted: {}",
+                    ip, old_mac, mac, accepted
+                );
+                self.push_event(ArpEvent::MacChanged {
+                    ip,
+                    old_mac,
+                    new_mac: mac,
+                    accepted,
+                });
+                if !accepted {
+                    return false;
+                }
+            }
+            Some(_) if self.cache.state(&ip) == Some(NeighborState::Permanent) => return true,
+            Some(_) => {}
+            None if !(permissive || solicited || for_us) => return false,
+            None => {}
+        }
+
+        if solicited || (permissive && arp_operation == Some(ArpOperation::Reply)) {
+            self.cache.insert(ip, mac, now);
+        } else {
+            self.cache.learn(ip, mac, now);
+        }
+        true
+    }
+
+    fn push_event(&mut self, event: ArpEvent) {
+        if self.events.len() >= ARP_EVENT_QUEUE_LEN {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// 取出尚未处理的事件
+    pub fn take_events(&mut self) -> Vec<ArpEvent> {
+        self.events.drain(..).collect()
+    }
+
+    pub fn resolve(&mut self, ip: Ipv4Addr, now: Instant) -> Option<MacAddr> {
+        self.cache.loopup(&ip, now)
+    }
+
+    /// 上层确认邻居可达，避免 STALE 表项进入探测
+    pub fn confirm(&mut self, ip: Ipv4Addr, now: Instant) {
+        self.cache.confirm(&ip, now);
+    }
+
+    pub fn neighbor_state(&self, ip: Ipv4Addr) -> Option<NeighborState> {
+        if self.cache.is_pending(&ip) {
+            return Some(NeighborState::Incomplete);
+        }
+        self.cache.state(&ip)
+    }
+
+    pub fn cache(&self) -> &ArpCache {
+        &self.cache
+    }
+
+    pub fn cache_mut(&mut self) -> &mut ArpCache {
+        &mut self.cache
+    }
+
+    /// 缓存里没有下一跳地址时，把包放进等待队列；
+    /// 第一次遇到这个地址时立即发送 ARP 请求
+    pub fn queue_packet(&mut self, next_hop: Ipv4Addr, packet: Vec<u8>, now: Instant) {
+        if !self.cache.queue(next_hop, packet, now) {
+            return;
+        }
+        // 地址不可用时等到 poll 再发送请求
+        if self.acd.is_usable() && self.cache.start_resolution(&next_hop, now) {
+            self.outgoing.push((
+                BROADCAST_MAC,
+                ArpPacket::build_request(self.our_mac, self.our_ip, next_hop),
+            ));
+        }
+    }
+
+    /// 处理定时器：未收到响应时重发广播请求，次数用完后丢弃排队的包；
+    /// 推进邻居状态机，对 PROBE 状态的邻居发送单播请求
+    pub fn poll(&mut self, now: Instant) {
+        if let Some(packet) = self.acd.poll(now) {
+            self.outgoing.push((BROADCAST_MAC, packet));
+        }
+        // 本机地址不可用时不能作为请求的发送方地址
+        if !self.acd.is_usable() {
+            return;
+        }
+        for (ip, mac) in self.cache.poll(now) {
+            self.outgoing
+                .push((mac, ArpPacket::build_request(self.our_mac, self.our_ip, ip)));
+        }
+        let (retries, failed) = self.cache.poll_pending(now);
+        for ip in retries {
+            self.outgoing.push((
+                BROADCAST_MAC,
+                ArpPacket::build_request(self.our_mac, self.our_ip, ip),
+            ));
+        }
+        self.unreachable.extend(failed);
+    }
+
+    /// 取出待发送的 ARP 请求及目标 MAC（解析用广播，探测用单播）
+    pub fn take_outgoing(&mut self) -> Vec<(MacAddr, ArpPacket)> {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    /// 取出已解析出下一跳 MAC 的包
+    pub fn take_resolved(&mut self) -> Vec<(MacAddr, Vec<u8>)> {
+        std::mem::take(&mut self.resolved)
+    }
+
+    /// 取出解析失败而丢弃的包，用于回复主机不可达
+    pub fn take_unreachable(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.unreachable)
+    }
+}
+
+fn prefix_mask(prefix_len: u8) -> u32 {
+    match prefix_len {
+        0 => 0,
+        len => u32::MAX << (32 - len.min(32) as u32),
     }
 }
 
@@ -170,6 +805,14 @@ impl ArpPacket {
         })
     }
 
+    /// 是否是以太网上的 IPv4 ARP 报文
+    pub fn is_ipv4_over_ethernet(&self) -> bool {
+        self.hardware_type == 1
+            && self.protocol_type == 0x0800
+            && self.hardware_len == 6
+            && self.procotol_len == 4
+    }
+
     // 构建Arp请求， Arp请求的目的是为了得到目标ip
     pub fn build_request(sender_mac: [u8; 6], sender_ip: Ipv4Addr, target_ip: Ipv4Addr) -> Self {
         Self {
@@ -200,6 +843,41 @@ impl ArpPacket {
         }
     }
 
+    // 构建RARP请求，查询自己的MAC对应的ip（RFC 903）
+    pub fn build_rarp_request(our_mac: [u8; 6]) -> Self {
+        Self {
+            hardware_type: 1,
+            protocol_type: 0x0800,
+            hardware_len: 6,
+            procotol_len: 4,
+            operation: ArpOperation::to_u16(ArpOperation::RArpRequest),
+            sender_mac: our_mac,
+            sender_ip: Ipv4Addr::UNSPECIFIED,
+            target_mac: our_mac, // 要查询的是自己的mac
+            target_ip: Ipv4Addr::UNSPECIFIED,
+        }
+    }
+
+    // 构建RARP响应，把查询的mac对应的ip填到目标ip
+    pub fn build_rarp_reply(
+        request: &ArpPacket,
+        server_mac: [u8; 6],
+        server_ip: Ipv4Addr,
+        assigned_ip: Ipv4Addr,
+    ) -> Self {
+        Self {
+            hardware_type: 1,
+            protocol_type: 0x0800,
+            hardware_len: 6,
+            procotol_len: 4,
+            operation: ArpOperation::to_u16(ArpOperation::RArpReply),
+            sender_mac: server_mac,
+            sender_ip: server_ip,
+            target_mac: request.target_mac,
+            target_ip: assigned_ip,
+        }
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::with_capacity(ARP_PACKET_MIN_LEN);
         bytes.extend_from_slice(&self.hardware_type.to_be_bytes());
@@ -214,3 +892,185 @@ impl ArpPacket {
         bytes
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUR_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 10, 2);
+    const OUR_MAC: MacAddr = [0x42; 6];
+    const PEER_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 10, 1);
+    const PEER_MAC: MacAddr = [0x02, 0, 0, 0, 0, 1];
+
+    #[test]
+    fn test_resolve_with_pending_queue() {
+        let now = Instant::now();
+        let mut arp = ArpModule::new(OUR_IP, OUR_MAC);
+        arp.queue_packet(PEER_IP, vec![1], now);
+        arp.queue_packet(PEER_IP, vec![2], now);
+        // 同一个地址只发送一次请求
+        let requests = arp.take_outgoing();
+        assert_eq!(requests.len(), 1);
+        let (dst_mac, request) = &requests[0];
+        assert_eq!(*dst_mac, BROADCAST_MAC);
+        assert_eq!(request.target_ip, PEER_IP);
+        assert_eq!(request.sender_ip, OUR_IP);
+
+        // 超时后重发请求
+        arp.poll(now + ARP_RETRY_INTERVAL);
+        assert_eq!(arp.take_outgoing().len(), 1);
+
+        // 收到响应后排队的包按顺序发出
+        let reply = ArpPacket::build_reply(request, PEER_MAC);
+        assert!(arp.handle_packet(&reply, now).is_none());
+        assert_eq!(
+            arp.take_resolved(),
+            vec![(PEER_MAC, vec![1]), (PEER_MAC, vec![2])]
+        );
+        assert_eq!(arp.resolve(PEER_IP, now), Some(PEER_MAC));
+        assert_eq!(arp.neighbor_state(PEER_IP), Some(NeighborState::Reachable));
+    }
+
+    #[test]
+    fn test_resolve_failure() {
+        let now = Instant::now();
+        let mut arp = ArpModule::new(OUR_IP, OUR_MAC);
+        arp.queue_packet(PEER_IP, vec![1], now);
+        for i in 1..=ARP_MAX_REQUESTS {
+            arp.poll(now + ARP_RETRY_INTERVAL * i);
+        }
+        assert_eq!(arp.take_outgoing().len(), ARP_MAX_REQUESTS as usize);
+        assert_eq!(arp.take_unreachable(), vec![vec![1]]);
+        assert!(arp.take_resolved().is_empty());
+    }
+
+    #[test]
+    fn test_neighbor_state_machine() {
+        let now = Instant::now();
+        let mut arp = ArpModule::new(OUR_IP, OUR_MAC);
+        let request = ArpPacket::build_request(PEER_MAC, PEER_IP, OUR_IP);
+        assert!(arp.handle_packet(&request, now).is_some());
+        assert_eq!(arp.neighbor_state(PEER_IP), Some(NeighborState::Stale));
+
+        // 发送时 STALE -> DELAY，上层确认后回到 REACHABLE
+        assert_eq!(arp.resolve(PEER_IP, now), Some(PEER_MAC));
+        assert_eq!(arp.neighbor_state(PEER_IP), Some(NeighborState::Delay));
+        arp.confirm(PEER_IP, now);
+        assert_eq!(arp.neighbor_state(PEER_IP), Some(NeighborState::Reachable));
+
+        // 可达时间过后再次使用，没有确认则开始单播探测，探测期间仍然可以发送
+        let later = now + REACHABLE_TIME;
+        assert_eq!(arp.resolve(PEER_IP, later), Some(PEER_MAC));
+        arp.poll(later + DELAY_FIRST_PROBE_TIME);
+        assert_eq!(arp.neighbor_state(PEER_IP), Some(NeighborState::Probe));
+        assert_eq!(arp.resolve(PEER_IP, later), Some(PEER_MAC));
+        let mut probe_time = later + DELAY_FIRST_PROBE_TIME;
+        for _ in 1..MAX_UNICAST_PROBES {
+            probe_time += ARP_RETRY_INTERVAL;
+            arp.poll(probe_time);
+        }
+        let probes = arp.take_outgoing();
+        assert_eq!(probes.len(), MAX_UNICAST_PROBES as usize);
+        assert!(probes.iter().all(|(mac, _)| *mac == PEER_MAC));
+        arp.poll(probe_time + ARP_RETRY_INTERVAL);
+        assert_eq!(arp.neighbor_state(PEER_IP), None);
+
+        // 表项数量达到上限时淘汰最久未使用的表项
+        let cache = arp.cache_mut();
+        cache.set_max_entries(2);
+        let ip = |i: u8| Ipv4Addr::new(10, 0, 0, i);
+        cache.insert(ip(1), PEER_MAC, now);
+        cache.insert(ip(2), PEER_MAC, now + Duration::from_secs(1));
+        cache.loopup(&ip(1), now + Duration::from_secs(2));
+        cache.insert(ip(3), PEER_MAC, now + Duration::from_secs(3));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.state(&ip(2)), None);
+        assert!(cache.state(&ip(1)).is_some());
+    }
+
+    #[test]
+    fn test_poisoning_protection() {
+        let now = Instant::now();
+        let attacker_mac: MacAddr = [0x02, 0, 0, 0, 0, 0x66];
+        let victim_ip = Ipv4Addr::new(192, 168, 10, 3);
+        // 发给本机的未经请求的 ARP 响应
+        let forged_reply = |sender_ip| ArpPacket {
+            operation: ArpOperation::to_u16(ArpOperation::Reply),
+            target_mac: OUR_MAC,
+            ..ArpPacket::build_request(attacker_mac, sender_ip, OUR_IP)
+        };
+        let mut arp = ArpModule::new(OUR_IP, OUR_MAC);
+
+        // 没有请求过的响应和不是发给本机的请求都不学习
+        arp.handle_packet(&forged_reply(victim_ip), now);
+        let other = ArpPacket::build_request(attacker_mac, victim_ip, PEER_IP);
+        arp.handle_packet(&other, now);
+        assert_eq!(arp.neighbor_state(victim_ip), None);
+
+        // 目标是本机的请求会学习；之后未经请求的响应不能修改 MAC
+        let request = ArpPacket::build_request(PEER_MAC, PEER_IP, OUR_IP);
+        assert!(arp.handle_packet(&request, now).is_some());
+        arp.handle_packet(&forged_reply(PEER_IP), now);
+        assert_eq!(arp.resolve(PEER_IP, now), Some(PEER_MAC));
+        assert_eq!(
+            arp.take_events(),
+            vec![ArpEvent::MacChanged {
+                ip: PEER_IP,
+                old_mac: PEER_MAC,
+                new_mac: attacker_mac,
+                accepted: false,
+            }]
+        );
+
+        // 宽松策略下接受变化，但静态表项始终不变
+        arp.set_policy(ArpPolicy::Permissive);
+        arp.add_static(victim_ip, PEER_MAC, now);
+        arp.handle_packet(&forged_reply(PEER_IP), now);
+        arp.handle_packet(&forged_reply(victim_ip), now);
+        assert_eq!(arp.resolve(PEER_IP, now), Some(attacker_mac));
+        assert_eq!(arp.resolve(victim_ip, now), Some(PEER_MAC));
+        let events = arp.take_events();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[1],
+            ArpEvent::MacChanged {
+                accepted: false,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_neighbor_table_and_proxy() {
+        let now = Instant::now();
+        let mut arp = ArpModule::new(OUR_IP, OUR_MAC);
+        arp.add_static(Ipv4Addr::new(192, 168, 10, 9), PEER_MAC, now);
+        let request = ArpPacket::build_request(PEER_MAC, PEER_IP, OUR_IP);
+        arp.handle_packet(&request, now);
+        arp.queue_packet(Ipv4Addr::new(192, 168, 10, 5), vec![1], now);
+
+        let table: Vec<String> = arp.neighbors().iter().map(|n| n.to_string()).collect();
+        assert_eq!(
+            table,
+            vec![
+                "192.168.10.1 lladdr 02:00:00:00:00:01 STALE",
+                "192.168.10.5 INCOMPLETE",
+                "192.168.10.9 lladdr 02:00:00:00:00:01 PERMANENT",
+            ]
+        );
+        arp.flush();
+        assert_eq!(arp.neighbors().len(), 1);
+        assert!(arp.delete(Ipv4Addr::new(192, 168, 10, 9)));
+        assert!(arp.neighbors().is_empty());
+
+        // 代答 10.0.0.0/8 网段，网段外的地址不回答
+        arp.add_proxy(Ipv4Addr::new(10, 1, 2, 3), 8);
+        assert_eq!(arp.proxies(), &[(Ipv4Addr::new(10, 0, 0, 0), 8)]);
+        let request = ArpPacket::build_request(PEER_MAC, PEER_IP, Ipv4Addr::new(10, 0, 0, 7));
+        let reply = ArpPacket::parse(&arp.handle_packet(&request, now).unwrap()).unwrap();
+        assert_eq!(reply.sender_ip, Ipv4Addr::new(10, 0, 0, 7));
+        assert_eq!(reply.sender_mac, OUR_MAC);
+        let request = ArpPacket::build_request(PEER_MAC, PEER_IP, Ipv4Addr::new(11, 0, 0, 7));
+        assert!(arp.handle_packet(&request, now).is_none());
+    }
+}
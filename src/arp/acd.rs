@@ -0,0 +1,201 @@
+//! 地址冲突检测（RFC 5227）
+//!
+//! 启用地址前先发送 ARP 探测，确认没有其他主机在使用，然后发送免费 ARP 通告；
+//! 使用过程中发现冲突时按第 2.4 节保护地址，短时间内再次冲突则放弃
+
+use std::hash::{BuildHasher, RandomState};
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use super::{ArpOperation, ArpPacket, MacAddr};
+
+const PROBE_WAIT: Duration = Duration::from_secs(1); // 第一次探测前的随机等待上限
+const PROBE_NUM: u32 = 3; // 探测次数
+const PROBE_MIN: Duration = Duration::from_secs(1); // 探测间隔下限
+const PROBE_MAX: Duration = Duration::from_secs(2); // 探测间隔上限
+const ANNOUNCE_WAIT: Duration = Duration::from_secs(2); // 最后一次探测后等待多久开始通告
+const ANNOUNCE_NUM: u32 = 2; // 通告次数
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(2); // 通告间隔
+const DEFEND_INTERVAL: Duration = Duration::from_secs(10); // 两次冲突间隔小于它时放弃地址
+
+/// 本机地址的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressState {
+    Probing,    // 正在探测，地址还不能使用
+    Announcing, // 探测通过，正在发送通告
+    Bound,      // 地址可以正常使用
+    Conflict,   // 发现冲突，已放弃地址
+}
+
+/// 冲突的处理结果
+#[derive(Debug)]
+pub enum Conflict {
+    Defended(ArpPacket), // 广播通告保护地址
+    Lost,                // 放弃地址
+}
+
+#[derive(Debug)]
+pub struct Acd {
+    ip: Ipv4Addr,
+    mac: MacAddr,
+    state: AddressState,
+    sent: u32,                    // 当前阶段已发送的探测或通告数量
+    next: Instant,                // 下一次发送的时间
+    last_defend: Option<Instant>, // 上次保护地址的时间
+    random: RandomState,
+}
+
+impl Acd {
+    /// 地址直接可用，不做冲突检测
+    pub fn bound(ip: Ipv4Addr, mac: MacAddr, now: Instant) -> Self {
+        Self {
+            ip,
+            mac,
+            state: AddressState::Bound,
+            sent: 0,
+            next: now,
+            last_defend: None,
+            random: RandomState::new(),
+        }
+    }
+
+    /// 开始探测地址，等待 0 到 PROBE_WAIT 的随机时间后发送第一个探测
+    pub fn probe(ip: Ipv4Addr, mac: MacAddr, now: Instant) -> Self {
+        let mut acd = Self::bound(ip, mac, now);
+        acd.state = AddressState::Probing;
+        acd.next = now + acd.jitter(PROBE_WAIT, now);
+        acd
+    }
+
+    pub fn state(&self) -> AddressState {
+        self.state
+    }
+
+    /// 地址是否可以作为源地址使用
+    pub fn is_usable(&self) -> bool {
+        matches!(self.state, AddressState::Announcing | AddressState::Bound)
+    }
+
+    /// 推进定时器，返回需要广播的探测或通告
+    pub fn poll(&mut self, now: Instant) -> Option<ArpPacket> {
+        if now < self.next {
+            return None;
+        }
+        match self.state {
+            AddressState::Probing if self.sent < PROBE_NUM => {
+                self.sent += 1;
+                self.next = if self.sent == PROBE_NUM {
+                    now + ANNOUNCE_WAIT
+                } else {
+                    now + PROBE_MIN + self.jitter(PROBE_MAX - PROBE_MIN, now)
+                };
+                // 探测的发送方地址为 0.0.0.0，避免污染其他主机的缓存
+                Some(ArpPacket::build_request(
+                    self.mac,
+                    Ipv4Addr::UNSPECIFIED,
+                    self.ip,
+                ))
+            }
+            AddressState::Probing => {
+                self.state = AddressState::Announcing;
+                self.sent = 0;
+                self.poll(now)
+            }
+            AddressState::Announcing => {
+                self.sent += 1;
+                self.next = now + ANNOUNCE_INTERVAL;
+                if self.sent == ANNOUNCE_NUM {
+                    self.state = AddressState::Bound;
+                }
+                Some(self.announcement())
+            }
+            _ => None,
+        }
+    }
+
+    /// 检查收到的 ARP 报文是否与本机地址冲突（RFC 5227 第 2.1.1 节和第 2.4 节）
+    pub fn on_packet(&mut self, arp: &ArpPacket, now: Instant) -> Option<Conflict> {
+        if arp.sender_mac == self.mac {
+            return None;
+        }
+        match self.state {
+            AddressState::Probing => {
+                // 其他主机在使用这个地址，或者同时在探测它
+                let probing = ArpOperation::from_u16(arp.operation) == Some(ArpOperation::Request)
+                    && arp.sender_ip.is_unspecified()
+                    && arp.target_ip == self.ip;
+                if arp.sender_ip == self.ip || probing {
+                    self.state = AddressState::Conflict;
+                    return Some(Conflict::Lost);
+                }
+                None
+            }
+            AddressState::Announcing | AddressState::Bound if arp.sender_ip == self.ip => {
+                if self
+                    .last_defend
+                    .is_some_and(|last| now.duration_since(last) < DEFEND_INTERVAL)
+                {
+                    self.state = AddressState::Conflict;
+                    return Some(Conflict::Lost);
+                }
+                self.last_defend = Some(now);
+                Some(Conflict::Defended(self.announcement()))
+            }
+            _ => None,
+        }
+    }
+
+    /// 免费 ARP 通告：发送方和目标地址都是本机地址
+    fn announcement(&self) -> ArpPacket {
+        ArpPacket::build_request(self.mac, self.ip, self.ip)
+    }
+
+    fn jitter(&self, max: Duration, now: Instant) -> Duration {
+        let random = self.random.hash_one((now, self.sent)) % 1000;
+        max * random as u32 / 1000
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUR_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 10, 2);
+    const OUR_MAC: MacAddr = [0x42; 6];
+    const OTHER_MAC: MacAddr = [0x02, 0, 0, 0, 0, 1];
+
+    #[test]
+    fn test_probe_announce_and_defend() {
+        let mut now = Instant::now();
+        let mut acd = Acd::probe(OUR_IP, OUR_MAC, now);
+        assert!(!acd.is_usable());
+
+        let mut sent = Vec::new();
+        while acd.state() != AddressState::Bound {
+            now += Duration::from_secs(1);
+            sent.extend(acd.poll(now));
+        }
+        assert_eq!(sent.len(), (PROBE_NUM + ANNOUNCE_NUM) as usize);
+        assert!(sent[..3].iter().all(|p| p.sender_ip.is_unspecified()));
+        assert!(
+            sent[3..]
+                .iter()
+                .all(|p| p.sender_ip == OUR_IP && p.target_ip == OUR_IP)
+        );
+
+        // 第一次冲突时保护地址，10 秒内再次冲突则放弃
+        let other = ArpPacket::build_request(OTHER_MAC, OUR_IP, OUR_IP);
+        assert!(matches!(
+            acd.on_packet(&other, now),
+            Some(Conflict::Defended(_))
+        ));
+        now += Duration::from_secs(5);
+        assert!(matches!(acd.on_packet(&other, now), Some(Conflict::Lost)));
+        assert_eq!(acd.state(), AddressState::Conflict);
+
+        // 探测期间其他主机同时探测同一地址
+        let mut acd = Acd::probe(OUR_IP, OUR_MAC, now);
+        let probe = ArpPacket::build_request(OTHER_MAC, Ipv4Addr::UNSPECIFIED, OUR_IP);
+        assert!(matches!(acd.on_packet(&probe, now), Some(Conflict::Lost)));
+    }
+}
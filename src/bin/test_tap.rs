@@ -1,10 +1,9 @@
-use rust_tcpip::arp::{ArpModule, ArpPacket};
 use rust_tcpip::device::*;
-use rust_tcpip::ethernet::{EtherType, EthernetFrame, FramePayload};
-use rust_tcpip::icmp::{IcmpPacket, IcmpType};
-use rust_tcpip::ip::Ipv4Packet;
-use rust_tcpip::udp::UdpDatagram;
-use std::net::Ipv4Addr;
+use rust_tcpip::error::StackError;
+use rust_tcpip::socket::{SocketHandle, SocketType};
+use rust_tcpip::stack::Stack;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::Instant;
 use tracing::info;
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // 初始化日志
@@ -17,107 +16,58 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let our_mac = [66, 66, 66, 66, 66, 66];
     let netmask = Ipv4Addr::new(255, 255, 255, 0);
     device.set_ip(tap_ip, netmask)?;
+    device.set_nonblocking()?;
     info!("TAP device created and configured!");
-    let mut arp_module = ArpModule::new(our_ip, our_mac);
-    let mut buf = [0u8; 1500];
 
-    loop {
-        let read_size = device.recv(&mut buf)?;
-        info!("Received {} bytes", read_size);
+    // TAP 设备默认 MTU 1500
+    let interface = NetworkInterface::new(Box::new(device), our_ip, our_mac, netmask, 1500);
+    let mut stack = Stack::new(interface);
+    // 网段外的包都交给宿主机转发
+    stack.set_default_gateway(tap_ip);
+    stack.up(Instant::now());
+    let sockets = stack.sockets_mut();
 
-        let ethernet_frame = EthernetFrame::parse(&buf[..read_size])?;
-        info!("EtherType: {:?}", ethernet_frame.get_ether_type());
+    // TCP echo 服务
+    let tcp_listener = sockets.socket(SocketType::Tcp);
+    sockets.bind(tcp_listener, SocketAddrV4::new(our_ip, 8080))?;
+    sockets.listen(tcp_listener)?;
+    let mut tcp_clients: Vec<SocketHandle> = Vec::new();
 
-        match ethernet_frame.classify_payload() {
-            FramePayload::Arp(data) => {
-                let arp = ArpPacket::parse(&data)?;
-                if let Some(arp_response) = arp_module.handle_packet(&arp) {
-                    let response_frame = EthernetFrame::build(
-                        arp.sender_mac,
-                        our_mac,
-                        EtherType::to_u16(EtherType::ARP),
-                        arp_response,
-                    );
-                    device.send(&response_frame.to_bytes())?;
-                    info!("Send ARP reply");
-                }
-            }
-            FramePayload::Ipv4(data) => {
-                let ipv4 = Ipv4Packet::parse(&data)?;
-                info!(
-                    "Recving ipv4 Packet src_addr: {}",
-                    ipv4.src_addr.to_string()
-                );
-                info!(
-                    "Recving ipv4 Packet dst_addr: {}",
-                    ipv4.dst_addr.to_string()
-                );
-                info!("IP protocol: {}", ipv4.protocol);
-                // icmp protocol 1
-                if ipv4.protocol == 1 {
-                    info!("Received ICMP packet, parsing...");
-                    let icmp = IcmpPacket::parse(&ipv4.payload)?;
-                    info!("ICMP type: {}, code: {}", icmp.icmp_type, icmp.code);
-                    if IcmpType::from_u8(icmp.icmp_type) == Some(IcmpType::EchoRequest) {
-                        let icmp_reply = IcmpPacket::build_reply(&icmp);
-                        let icmp_bytes = icmp_reply.to_bytes();
-
-                        let ip_reply = Ipv4Packet::build(
-                            ipv4.dst_addr,
-                            ipv4.src_addr,
-                            ipv4.protocol,
-                            0,
-                            icmp_bytes,
-                        );
-                        let ipv4_bytes = ip_reply.to_bytes();
-
-                        let eth_frame = EthernetFrame::build(
-                            ethernet_frame.src_mac,
-                            ethernet_frame.dst_mac,
-                            EtherType::to_u16(EtherType::IPv4),
-                            ipv4_bytes,
-                        );
-                        device.send(&eth_frame.to_bytes())?;
-                        info!("Sending ping reply");
-                    }
-                }
-                // udp protocol 17
-                if ipv4.protocol == 17 {
-                    info!("Received UDP packet, parsing...");
-                    let udp = UdpDatagram::parse(&ipv4.payload)?;
-                    info!(
-                        "Udp from {}:{} to {}:{}, length: {}",
-                        ipv4.src_addr, udp.src_port, ipv4.dst_addr, udp.dst_port, udp.length
-                    );
-                    info!("Udp payload hex: {:02x?}", udp.payload);
-                    let payload_str = String::from_utf8_lossy(&udp.payload);
-                    info!("Udp payload str: {}", payload_str);
-
-                    let udp_echo = UdpDatagram::build_echo(&udp, ipv4.src_addr, ipv4.dst_addr);
-                    let udp_echo_bytes = udp_echo.to_bytes();
+    // UDP echo 服务
+    let udp_echo = sockets.socket(SocketType::Udp);
+    sockets.bind(udp_echo, SocketAddrV4::new(our_ip, 8888))?;
 
-                    let udp_reply = Ipv4Packet::build(
-                        ipv4.dst_addr,
-                        ipv4.src_addr,
-                        ipv4.protocol,
-                        0,
-                        udp_echo_bytes,
-                    );
-                    let ipv4_bytes = udp_reply.to_bytes();
+    stack.run(|sockets| {
+        // 把收到的数据报原样发回
+        let mut data = [0u8; 1500];
+        while let Ok((len, from)) = sockets.recvfrom(udp_echo, &mut data) {
+            info!("Udp payload str: {}", String::from_utf8_lossy(&data[..len]));
+            let _ = sockets.sendto(udp_echo, &data[..len], from);
+        }
 
-                    let eth_frame = EthernetFrame::build(
-                        ethernet_frame.src_mac,
-                        ethernet_frame.dst_mac,
-                        EtherType::to_u16(EtherType::IPv4),
-                        ipv4_bytes,
-                    );
-                    device.send(&eth_frame.to_bytes())?;
-                    info!("Sending udp echo");
-                }
+        while let Ok((client, peer)) = sockets.accept(tcp_listener) {
+            info!("Tcp connection accepted from {}", peer);
+            tcp_clients.push(client);
+        }
+        // 把收到的数据原样发回，对方关闭后我们也关闭
+        tcp_clients.retain(|&client| match sockets.recv(client, &mut data) {
+            Ok(0) => {
+                info!("Tcp connection {:?} closed", client);
+                let _ = sockets.close(client);
+                false
             }
-            _ => {
-                info!("Unknown packet");
+            Ok(len) => {
+                info!("Tcp payload str: {}", String::from_utf8_lossy(&data[..len]));
+                let _ = sockets.send(client, &data[..len]);
+                true
             }
-        }
-    }
+            Err(StackError::Io(_)) => true,
+            Err(e) => {
+                info!("Tcp connection {:?} failed: {}", client, e);
+                let _ = sockets.close(client);
+                false
+            }
+        });
+    })?;
+    Ok(())
 }
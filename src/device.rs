@@ -52,13 +52,19 @@ impl TapDevice {
         let iface = Iface::without_packet_info(name, Mode::Tap)?;
         Ok(Self { iface })
     }
+    /// 设置为非阻塞模式，没有数据时 recv 返回 WouldBlock，
+    /// 这样 `Stack::run` 在空闲时也能处理 TCP 定时器
+    pub fn set_nonblocking(&mut self) -> Result<()> {
+        self.iface.set_non_blocking()?;
+        Ok(())
+    }
     pub fn set_ip(&mut self, ip: Ipv4Addr, netmask: Ipv4Addr) -> Result<()> {
         let iface_name = self.iface.name();
 
         // 1. 配置ip命令
         // ip addr add 192.168.10.1/24 dev tap0
         let output = Command::new("ip")
-            .args(&[
+            .args([
                 "addr",
                 "add",
                 &format!("{}/{}", ip, netmask_to_prefix(netmask)),
@@ -68,16 +74,15 @@ impl TapDevice {
             .output()?;
 
         if !output.status.success() {
-            return Err(StackError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to set IP address"),
+            return Err(StackError::Io(std::io::Error::other(
+                "Failed to set IP address".to_string(),
             )));
         }
 
         // 2. 启动接口
         // ip set dev tap1 up
         Command::new("ip")
-            .args(&["link", "set", "dev", iface_name, "up"])
+            .args(["link", "set", "dev", iface_name, "up"])
             .output()?;
         Ok(())
     }
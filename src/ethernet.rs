@@ -11,6 +11,7 @@ const ETHER_MIN_BYTES: usize = 14;
 pub enum EtherType {
     IPv4 = 0x0800,
     ARP = 0x0806,
+    RARP = 0x8035,
     IPv6 = 0x86DD,
 }
 
@@ -19,6 +20,7 @@ impl EtherType {
         match value {
             0x0800 => Some(EtherType::IPv4),
             0x0806 => Some(EtherType::ARP),
+            0x8035 => Some(EtherType::RARP),
             0x86DD => Some(EtherType::IPv6),
             _ => None,
         }
@@ -26,6 +28,7 @@ impl EtherType {
     pub fn to_u16(ether_type: EtherType) -> u16 {
         match ether_type {
             EtherType::ARP => 0x0806,
+            EtherType::RARP => 0x8035,
             EtherType::IPv4 => 0x0800,
             EtherType::IPv6 => 0x86DD,
         }
@@ -35,6 +38,7 @@ impl EtherType {
 #[derive(Debug)]
 pub enum FramePayload {
     Arp(Vec<u8>),
+    Rarp(Vec<u8>),
     Ipv4(Vec<u8>),
     Ipv6(Vec<u8>),
     Unknown,
@@ -83,6 +87,7 @@ impl EthernetFrame {
     pub fn classify_payload(&self) -> FramePayload {
         match self.get_ether_type() {
             Some(EtherType::ARP) => FramePayload::Arp(self.payload.clone()),
+            Some(EtherType::RARP) => FramePayload::Rarp(self.payload.clone()),
             Some(EtherType::IPv4) => FramePayload::Ipv4(self.payload.clone()),
             Some(EtherType::IPv6) => FramePayload::Ipv6(self.payload.clone()),
             None => FramePayload::Unknown,
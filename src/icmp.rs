@@ -2,10 +2,16 @@
 //!
 //! ICMP（Internet Control Message Protocol）用于网络诊断和错误报告
 
+use std::net::Ipv4Addr;
+
 use crate::error::{Result, StackError};
+use crate::ip::Ipv4Packet;
 
 const ICMP_PACKET_MIN_LEN: usize = 8;
 
+/// 差错报文引用原始数据报 IP 头部之后的字节数（RFC 792）
+const ICMP_ERROR_QUOTE_LEN: usize = 8;
+
 /// ICMP 数据包结构
 #[derive(Debug)]
 pub struct IcmpPacket {
@@ -22,6 +28,7 @@ pub struct IcmpPacket {
 pub enum IcmpType {
     EchoReply = 0,              // Echo 响应（ping 回复）
     DestinationUnreachable = 3, // 目标不可达
+    Redirect = 5,               // 重定向
     TimeExceeded = 11,          // 超时（用于 traceroute）
     EchoRequest = 8,            // Echo 请求（ping）
 }
@@ -31,6 +38,7 @@ impl IcmpType {
         match icmp_type {
             IcmpType::EchoReply => 0,
             IcmpType::DestinationUnreachable => 3,
+            IcmpType::Redirect => 5,
             IcmpType::TimeExceeded => 11,
             IcmpType::EchoRequest => 8,
         }
@@ -39,11 +47,97 @@ impl IcmpType {
         match value {
             0 => Some(IcmpType::EchoReply),
             3 => Some(IcmpType::DestinationUnreachable),
+            5 => Some(IcmpType::Redirect),
             11 => Some(IcmpType::TimeExceeded),
             8 => Some(IcmpType::EchoRequest),
             _ => None,
         }
     }
+    /// 是否是差错报文（目标不可达、超时等）
+    pub fn is_error(value: u8) -> bool {
+        matches!(value, 3 | 4 | 5 | 11 | 12)
+    }
+}
+
+/// 目标不可达的代码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnreachableCode {
+    NetUnreachable = 0,      // 网络不可达
+    HostUnreachable = 1,     // 主机不可达
+    ProtocolUnreachable = 2, // 协议不可达
+    PortUnreachable = 3,     // 端口不可达
+    FragmentationNeeded = 4, // 需要分片但设置了 DF
+    SourceRouteFailed = 5,   // 源路由失败
+}
+
+impl UnreachableCode {
+    pub fn to_u8(code: UnreachableCode) -> u8 {
+        match code {
+            UnreachableCode::NetUnreachable => 0,
+            UnreachableCode::HostUnreachable => 1,
+            UnreachableCode::ProtocolUnreachable => 2,
+            UnreachableCode::PortUnreachable => 3,
+            UnreachableCode::FragmentationNeeded => 4,
+            UnreachableCode::SourceRouteFailed => 5,
+        }
+    }
+    pub fn from_u8(value: u8) -> Option<UnreachableCode> {
+        match value {
+            0 => Some(UnreachableCode::NetUnreachable),
+            1 => Some(UnreachableCode::HostUnreachable),
+            2 => Some(UnreachableCode::ProtocolUnreachable),
+            3 => Some(UnreachableCode::PortUnreachable),
+            4 => Some(UnreachableCode::FragmentationNeeded),
+            5 => Some(UnreachableCode::SourceRouteFailed),
+            _ => None,
+        }
+    }
+}
+
+/// 超时的代码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeExceededCode {
+    TtlExceeded = 0,        // 传输中 TTL 减为 0
+    FragmentReassembly = 1, // 分片重组超时
+}
+
+impl TimeExceededCode {
+    pub fn to_u8(code: TimeExceededCode) -> u8 {
+        match code {
+            TimeExceededCode::TtlExceeded => 0,
+            TimeExceededCode::FragmentReassembly => 1,
+        }
+    }
+    pub fn from_u8(value: u8) -> Option<TimeExceededCode> {
+        match value {
+            0 => Some(TimeExceededCode::TtlExceeded),
+            1 => Some(TimeExceededCode::FragmentReassembly),
+            _ => None,
+        }
+    }
+}
+
+/// 重定向的代码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectCode {
+    Network = 0, // 网络重定向
+    Host = 1,    // 主机重定向
+}
+
+impl RedirectCode {
+    pub fn to_u8(code: RedirectCode) -> u8 {
+        match code {
+            RedirectCode::Network => 0,
+            RedirectCode::Host => 1,
+        }
+    }
+    pub fn from_u8(value: u8) -> Option<RedirectCode> {
+        match value {
+            0 => Some(RedirectCode::Network),
+            1 => Some(RedirectCode::Host),
+            _ => None,
+        }
+    }
 }
 
 impl IcmpPacket {
@@ -79,6 +173,81 @@ impl IcmpPacket {
         }
     }
 
+    /// 构造差错报文，数据部分引用原始数据报的 IP 头部和之后的 8 字节
+    /// original 是收到的完整 IP 数据报（含选项）
+    pub fn build_error(icmp_type: IcmpType, code: u8, original: &[u8]) -> Self {
+        let header_len = original
+            .first()
+            .map_or(0, |byte| ((byte & 0x0F) as usize) * 4);
+        let quote_len = (header_len + ICMP_ERROR_QUOTE_LEN).min(original.len());
+        Self {
+            icmp_type: IcmpType::to_u8(icmp_type),
+            code,
+            checksum: 0,
+            identifier: 0, // 未使用，必须为 0
+            sequence: 0,
+            payload: original[..quote_len].to_vec(),
+        }
+    }
+
+    /// 构造目标不可达报文
+    pub fn build_unreachable(code: UnreachableCode, original: &[u8]) -> Self {
+        Self::build_error(
+            IcmpType::DestinationUnreachable,
+            UnreachableCode::to_u8(code),
+            original,
+        )
+    }
+
+    /// 构造超时报文
+    pub fn build_time_exceeded(code: TimeExceededCode, original: &[u8]) -> Self {
+        Self::build_error(
+            IcmpType::TimeExceeded,
+            TimeExceededCode::to_u8(code),
+            original,
+        )
+    }
+
+    /// 构造重定向报文，更好的网关地址放在标识符和序列号的位置
+    pub fn build_redirect(code: RedirectCode, gateway: Ipv4Addr, original: &[u8]) -> Self {
+        let mut icmp = Self::build_error(IcmpType::Redirect, RedirectCode::to_u8(code), original);
+        let octets = gateway.octets();
+        icmp.identifier = u16::from_be_bytes([octets[0], octets[1]]);
+        icmp.sequence = u16::from_be_bytes([octets[2], octets[3]]);
+        icmp
+    }
+
+    /// 构造需要分片但设置了 DF 的目标不可达报文，携带下一跳 MTU（RFC 1191）
+    pub fn build_fragmentation_needed(mtu: u16, original: &[u8]) -> Self {
+        let mut icmp = Self::build_unreachable(UnreachableCode::FragmentationNeeded, original);
+        icmp.sequence = mtu;
+        icmp
+    }
+
+    /// 是否允许为这个数据报回复差错报文（RFC 1122 第 3.2.2 节）：
+    /// 不回复 ICMP 差错、广播/多播、非首个分片以及源地址不是单播的数据报
+    pub fn may_send_error(packet: &Ipv4Packet) -> bool {
+        if packet.fragment_offset != 0 {
+            return false;
+        }
+        if packet.dst_addr.is_broadcast() || packet.dst_addr.is_multicast() {
+            return false;
+        }
+        let src = packet.src_addr;
+        if src.is_unspecified() || src.is_broadcast() || src.is_multicast() || src.is_loopback() {
+            return false;
+        }
+        if packet.protocol == 1
+            && packet
+                .payload
+                .first()
+                .is_some_and(|t| IcmpType::is_error(*t))
+        {
+            return false;
+        }
+        true
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::with_capacity(8 + self.payload.len());
         bytes.push(self.icmp_type);
@@ -88,27 +257,61 @@ impl IcmpPacket {
         bytes.extend_from_slice(&self.sequence.to_be_bytes());
         bytes.extend_from_slice(&self.payload);
         // 计算校验和
-        let checksum = Self::calculate_checksum(&bytes);
+        let checksum = internet_checksum(&bytes);
         bytes[2..4].copy_from_slice(&checksum.to_be_bytes());
 
         bytes
     }
+}
 
-    fn calculate_checksum(data: &[u8]) -> u16 {
-        let mut sum: u32 = 0;
+/// Internet 校验和（RFC 1071）：按 16 位反码求和后取反。
+/// IP 头部、ICMP、ICMPv6、TCP 和 UDP 共用；接收方对含校验和的数据计算结果为 0 表示正确
+pub(crate) fn internet_checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
 
-        for chunk in data.chunks(2) {
-            let word = if chunk.len() == 2 {
-                u16::from_be_bytes([chunk[0], chunk[1]]) as u32
-            } else {
-                (chunk[0] as u32) << 8
-            };
-            sum += word;
-        }
+    for chunk in data.chunks(2) {
+        let word = if chunk.len() == 2 {
+            u16::from_be_bytes([chunk[0], chunk[1]]) as u32
+        } else {
+            (chunk[0] as u32) << 8
+        };
+        sum += word;
+    }
 
-        while sum >> 16 != 0 {
-            sum = (sum & 0xFFFF) + (sum >> 16);
-        }
-        !sum as u16
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !sum as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_port_unreachable() {
+        let src = Ipv4Addr::new(192, 168, 10, 1);
+        let dst = Ipv4Addr::new(192, 168, 10, 2);
+        let udp = vec![
+            0x9c, 0x40, 0x00, 0x35, 0x00, 0x0d, 0x00, 0x00, b'h', b'e', b'l', b'l', b'o',
+        ];
+        let original = Ipv4Packet::build(src, dst, 17, 64, udp).to_bytes();
+        assert!(IcmpPacket::may_send_error(
+            &Ipv4Packet::parse(&original).unwrap()
+        ));
+
+        let icmp = IcmpPacket::build_unreachable(UnreachableCode::PortUnreachable, &original);
+        let bytes = icmp.to_bytes();
+        assert_eq!((bytes[0], bytes[1]), (3, 3));
+        assert_eq!(&bytes[4..8], &[0, 0, 0, 0]);
+        // IP 头部 20 字节 + UDP 头部 8 字节
+        assert_eq!(&bytes[8..], &original[..28]);
+        assert_eq!(internet_checksum(&bytes), 0);
+
+        // 不为 ICMP 差错报文或广播报文回复差错
+        let error = Ipv4Packet::build(dst, src, 1, 64, bytes);
+        assert!(!IcmpPacket::may_send_error(&error));
+        let broadcast = Ipv4Packet::build(src, Ipv4Addr::BROADCAST, 17, 64, vec![0; 8]);
+        assert!(!IcmpPacket::may_send_error(&broadcast));
     }
 }
@@ -0,0 +1,556 @@
+//! ICMPv6 协议实现（RFC 4443）和邻居发现报文（RFC 4861）
+//!
+//! ICMPv6 的头部格式和 ICMP 相同，直接复用 `IcmpPacket` 表示：
+//! 类型、代码、校验和之后的 4 字节放在 identifier 和 sequence 中。
+//! 区别在于校验和包括 IPv6 伪头部
+
+use std::net::Ipv6Addr;
+
+use crate::arp::MacAddr;
+use crate::error::{Result, StackError};
+use crate::icmp::IcmpPacket;
+use crate::ipv6::{IPV6_NEXT_ICMPV6, Ipv6Packet, pseudo_header_checksum};
+
+/// 差错报文最多引用的原始数据，保证整个报文不超过 IPv6 最小 MTU 1280（RFC 4443 第 2.4 节）
+const ICMPV6_ERROR_QUOTE_LEN: usize = 1280 - 40 - 8;
+
+/// NDP 报文的跳数限制必须是 255，证明发送方在同一链路上
+pub const NDP_HOP_LIMIT: u8 = 255;
+
+// NDP 选项类型
+const NDP_OPT_SOURCE_LLADDR: u8 = 1;
+const NDP_OPT_TARGET_LLADDR: u8 = 2;
+const NDP_OPT_PREFIX_INFO: u8 = 3;
+const NDP_OPT_MTU: u8 = 5;
+
+// 邻居通告的标志位（identifier 的高位）
+const NA_FLAG_ROUTER: u16 = 0x8000;
+const NA_FLAG_SOLICITED: u16 = 0x4000;
+const NA_FLAG_OVERRIDE: u16 = 0x2000;
+
+// 路由器通告的标志位
+const RA_FLAG_MANAGED: u8 = 0x80;
+const RA_FLAG_OTHER: u8 = 0x40;
+
+// 前缀信息的标志位
+const PREFIX_FLAG_ON_LINK: u8 = 0x80;
+const PREFIX_FLAG_AUTONOMOUS: u8 = 0x40;
+
+/// ICMPv6 消息类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Icmpv6Type {
+    DestinationUnreachable = 1,  // 目标不可达
+    PacketTooBig = 2,            // 包太大
+    TimeExceeded = 3,            // 超时
+    ParameterProblem = 4,        // 参数问题
+    EchoRequest = 128,           // Echo 请求
+    EchoReply = 129,             // Echo 响应
+    RouterSolicitation = 133,    // 路由器请求
+    RouterAdvertisement = 134,   // 路由器通告
+    NeighborSolicitation = 135,  // 邻居请求
+    NeighborAdvertisement = 136, // 邻居通告
+    Redirect = 137,              // 重定向
+}
+
+impl Icmpv6Type {
+    pub fn to_u8(icmp_type: Icmpv6Type) -> u8 {
+        match icmp_type {
+            Icmpv6Type::DestinationUnreachable => 1,
+            Icmpv6Type::PacketTooBig => 2,
+            Icmpv6Type::TimeExceeded => 3,
+            Icmpv6Type::ParameterProblem => 4,
+            Icmpv6Type::EchoRequest => 128,
+            Icmpv6Type::EchoReply => 129,
+            Icmpv6Type::RouterSolicitation => 133,
+            Icmpv6Type::RouterAdvertisement => 134,
+            Icmpv6Type::NeighborSolicitation => 135,
+            Icmpv6Type::NeighborAdvertisement => 136,
+            Icmpv6Type::Redirect => 137,
+        }
+    }
+    pub fn from_u8(value: u8) -> Option<Icmpv6Type> {
+        match value {
+            1 => Some(Icmpv6Type::DestinationUnreachable),
+            2 => Some(Icmpv6Type::PacketTooBig),
+            3 => Some(Icmpv6Type::TimeExceeded),
+            4 => Some(Icmpv6Type::ParameterProblem),
+            128 => Some(Icmpv6Type::EchoRequest),
+            129 => Some(Icmpv6Type::EchoReply),
+            133 => Some(Icmpv6Type::RouterSolicitation),
+            134 => Some(Icmpv6Type::RouterAdvertisement),
+            135 => Some(Icmpv6Type::NeighborSolicitation),
+            136 => Some(Icmpv6Type::NeighborAdvertisement),
+            137 => Some(Icmpv6Type::Redirect),
+            _ => None,
+        }
+    }
+    /// 类型小于 128 的是差错报文
+    pub fn is_error(value: u8) -> bool {
+        value < 128
+    }
+}
+
+/// 目标不可达的代码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Icmpv6UnreachableCode {
+    NoRoute = 0,            // 没有路由
+    AdminProhibited = 1,    // 管理禁止
+    BeyondScope = 2,        // 超出源地址范围
+    AddressUnreachable = 3, // 地址不可达（邻居解析失败）
+    PortUnreachable = 4,    // 端口不可达
+}
+
+impl Icmpv6UnreachableCode {
+    pub fn to_u8(code: Icmpv6UnreachableCode) -> u8 {
+        match code {
+            Icmpv6UnreachableCode::NoRoute => 0,
+            Icmpv6UnreachableCode::AdminProhibited => 1,
+            Icmpv6UnreachableCode::BeyondScope => 2,
+            Icmpv6UnreachableCode::AddressUnreachable => 3,
+            Icmpv6UnreachableCode::PortUnreachable => 4,
+        }
+    }
+    pub fn from_u8(value: u8) -> Option<Icmpv6UnreachableCode> {
+        match value {
+            0 => Some(Icmpv6UnreachableCode::NoRoute),
+            1 => Some(Icmpv6UnreachableCode::AdminProhibited),
+            2 => Some(Icmpv6UnreachableCode::BeyondScope),
+            3 => Some(Icmpv6UnreachableCode::AddressUnreachable),
+            4 => Some(Icmpv6UnreachableCode::PortUnreachable),
+            _ => None,
+        }
+    }
+}
+
+/// 参数问题的代码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterProblemCode {
+    ErroneousHeader = 0,        // 头部字段错误
+    UnrecognizedNextHeader = 1, // 不认识的 Next Header
+    UnrecognizedOption = 2,     // 不认识的 IPv6 选项
+}
+
+impl ParameterProblemCode {
+    pub fn to_u8(code: ParameterProblemCode) -> u8 {
+        match code {
+            ParameterProblemCode::ErroneousHeader => 0,
+            ParameterProblemCode::UnrecognizedNextHeader => 1,
+            ParameterProblemCode::UnrecognizedOption => 2,
+        }
+    }
+    pub fn from_u8(value: u8) -> Option<ParameterProblemCode> {
+        match value {
+            0 => Some(ParameterProblemCode::ErroneousHeader),
+            1 => Some(ParameterProblemCode::UnrecognizedNextHeader),
+            2 => Some(ParameterProblemCode::UnrecognizedOption),
+            _ => None,
+        }
+    }
+}
+
+impl IcmpPacket {
+    /// 序列化为 ICMPv6 报文，校验和包括 IPv6 伪头部
+    pub fn to_bytes_v6(&self, src_addr: Ipv6Addr, dst_addr: Ipv6Addr) -> Vec<u8> {
+        let mut bytes = self.to_bytes();
+        bytes[2..4].copy_from_slice(&[0, 0]);
+        let checksum = pseudo_header_checksum(src_addr, dst_addr, IPV6_NEXT_ICMPV6, &bytes);
+        bytes[2..4].copy_from_slice(&checksum.to_be_bytes());
+        bytes
+    }
+
+    /// 解析 ICMPv6 报文并校验包括伪头部的校验和
+    pub fn parse_v6(data: &[u8], src_addr: Ipv6Addr, dst_addr: Ipv6Addr) -> Result<Self> {
+        let icmp = Self::parse(data)?;
+        if pseudo_header_checksum(src_addr, dst_addr, IPV6_NEXT_ICMPV6, data) != 0 {
+            return Err(StackError::ChecksumMismatch(format!(
+                "Icmpv6 checksum {:#06x} mismatch",
+                icmp.checksum
+            )));
+        }
+        Ok(icmp)
+    }
+
+    /// 构造 ICMPv6 差错报文，parameter 是类型相关的 4 字节（MTU 或出错位置），
+    /// 数据部分尽可能多地引用原始数据报
+    pub fn build_error_v6(
+        icmp_type: Icmpv6Type,
+        code: u8,
+        parameter: u32,
+        original: &[u8],
+    ) -> Self {
+        let quote_len = original.len().min(ICMPV6_ERROR_QUOTE_LEN);
+        Self {
+            icmp_type: Icmpv6Type::to_u8(icmp_type),
+            code,
+            checksum: 0,
+            identifier: (parameter >> 16) as u16,
+            sequence: parameter as u16,
+            payload: original[..quote_len].to_vec(),
+        }
+    }
+
+    /// 是否允许为这个数据报回复差错报文 error（RFC 4443 第 2.4 节）：
+    /// 不回复 ICMPv6 差错、发往组播地址以及源地址不是单播的数据报。
+    /// 不认识的选项要求回复的参数问题例外，发往组播地址的包也回复
+    pub fn may_send_error_v6(packet: &Ipv6Packet, error: &IcmpPacket) -> bool {
+        let option_problem = Icmpv6Type::from_u8(error.icmp_type)
+            == Some(Icmpv6Type::ParameterProblem)
+            && ParameterProblemCode::from_u8(error.code)
+                == Some(ParameterProblemCode::UnrecognizedOption);
+        if packet.dst_addr.is_multicast() && !option_problem {
+            return false;
+        }
+        let src = packet.src_addr;
+        if src.is_unspecified() || src.is_multicast() || src.is_loopback() {
+            return false;
+        }
+        match packet.extensions() {
+            Ok(chain) if chain.protocol == IPV6_NEXT_ICMPV6 => !packet
+                .payload
+                .get(chain.offset)
+                .is_some_and(|t| Icmpv6Type::is_error(*t)),
+            _ => true,
+        }
+    }
+}
+
+/// 路由器通告中的前缀信息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixInfo {
+    pub prefix: Ipv6Addr,
+    pub prefix_len: u8,
+    pub on_link: bool,    // 前缀内的地址都在本链路上
+    pub autonomous: bool, // 可以用于无状态地址自动配置
+    pub valid_lifetime: u32,
+    pub preferred_lifetime: u32,
+}
+
+/// 邻居发现报文
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NdpMessage {
+    RouterSolicitation {
+        source_lladdr: Option<MacAddr>,
+    },
+    RouterAdvertisement {
+        cur_hop_limit: u8, // 0 表示未指定
+        managed: bool,
+        other: bool,
+        router_lifetime: u16, // 秒，0 表示不是默认路由器
+        reachable_time: u32,  // 毫秒
+        retrans_timer: u32,   // 毫秒
+        source_lladdr: Option<MacAddr>,
+        mtu: Option<u32>,
+        prefixes: Vec<PrefixInfo>,
+    },
+    NeighborSolicitation {
+        target: Ipv6Addr,
+        source_lladdr: Option<MacAddr>,
+    },
+    NeighborAdvertisement {
+        router: bool,
+        solicited: bool,
+        overrides: bool, // 覆盖缓存中已有的链路层地址
+        target: Ipv6Addr,
+        target_lladdr: Option<MacAddr>,
+    },
+    Redirect {
+        target: Ipv6Addr,      // 更好的下一跳
+        destination: Ipv6Addr, // 被重定向的目标
+        target_lladdr: Option<MacAddr>,
+    },
+}
+
+impl NdpMessage {
+    /// 从 ICMPv6 报文解析，不是 NDP 报文或格式错误时返回错误
+    pub fn parse(icmp: &IcmpPacket) -> Result<Self> {
+        let invalid =
+            || StackError::InvalidPacket(format!("Invalid NDP message {}", icmp.icmp_type));
+        if icmp.code != 0 {
+            return Err(invalid());
+        }
+        let data = &icmp.payload;
+        let (fixed_len, message) = match Icmpv6Type::from_u8(icmp.icmp_type) {
+            Some(Icmpv6Type::RouterSolicitation) => (
+                0,
+                NdpMessage::RouterSolicitation {
+                    source_lladdr: None,
+                },
+            ),
+            Some(Icmpv6Type::RouterAdvertisement) if data.len() >= 8 => {
+                let flags = icmp.identifier as u8;
+                (
+                    8,
+                    NdpMessage::RouterAdvertisement {
+                        cur_hop_limit: (icmp.identifier >> 8) as u8,
+                        managed: flags & RA_FLAG_MANAGED != 0,
+                        other: flags & RA_FLAG_OTHER != 0,
+                        router_lifetime: icmp.sequence,
+                        reachable_time: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+                        retrans_timer: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+                        source_lladdr: None,
+                        mtu: None,
+                        prefixes: Vec::new(),
+                    },
+                )
+            }
+            Some(Icmpv6Type::NeighborSolicitation) if data.len() >= 16 => (
+                16,
+                NdpMessage::NeighborSolicitation {
+                    target: read_addr(&data[..16]),
+                    source_lladdr: None,
+                },
+            ),
+            Some(Icmpv6Type::NeighborAdvertisement) if data.len() >= 16 => (
+                16,
+                NdpMessage::NeighborAdvertisement {
+                    router: icmp.identifier & NA_FLAG_ROUTER != 0,
+                    solicited: icmp.identifier & NA_FLAG_SOLICITED != 0,
+                    overrides: icmp.identifier & NA_FLAG_OVERRIDE != 0,
+                    target: read_addr(&data[..16]),
+                    target_lladdr: None,
+                },
+            ),
+            Some(Icmpv6Type::Redirect) if data.len() >= 32 => (
+                32,
+                NdpMessage::Redirect {
+                    target: read_addr(&data[..16]),
+                    destination: read_addr(&data[16..32]),
+                    target_lladdr: None,
+                },
+            ),
+            _ => return Err(invalid()),
+        };
+
+        // 选项：类型、以 8 字节为单位的长度、内容
+        let mut message = message;
+        let mut options = &data[fixed_len..];
+        while !options.is_empty() {
+            let len = options.get(1).map_or(0, |&len| len as usize * 8);
+            if len == 0 || len > options.len() {
+                return Err(invalid());
+            }
+            let (option, rest) = options.split_at(len);
+            options = rest;
+            let lladdr = || -> MacAddr {
+                [
+                    option[2], option[3], option[4], option[5], option[6], option[7],
+                ]
+            };
+            match (option[0], &mut message) {
+                (NDP_OPT_SOURCE_LLADDR, NdpMessage::RouterSolicitation { source_lladdr })
+                | (NDP_OPT_SOURCE_LLADDR, NdpMessage::RouterAdvertisement { source_lladdr, .. })
+                | (NDP_OPT_SOURCE_LLADDR, NdpMessage::NeighborSolicitation { source_lladdr, .. }) =>
+                {
+                    *source_lladdr = Some(lladdr());
+                }
+                (
+                    NDP_OPT_TARGET_LLADDR,
+                    NdpMessage::NeighborAdvertisement { target_lladdr, .. },
+                )
+                | (NDP_OPT_TARGET_LLADDR, NdpMessage::Redirect { target_lladdr, .. }) => {
+                    *target_lladdr = Some(lladdr());
+                }
+                (NDP_OPT_MTU, NdpMessage::RouterAdvertisement { mtu, .. }) => {
+                    *mtu = Some(u32::from_be_bytes([
+                        option[4], option[5], option[6], option[7],
+                    ]));
+                }
+                (NDP_OPT_PREFIX_INFO, NdpMessage::RouterAdvertisement { prefixes, .. })
+                    if len == 32 =>
+                {
+                    prefixes.push(PrefixInfo {
+                        prefix_len: option[2],
+                        on_link: option[3] & PREFIX_FLAG_ON_LINK != 0,
+                        autonomous: option[3] & PREFIX_FLAG_AUTONOMOUS != 0,
+                        valid_lifetime: u32::from_be_bytes([
+                            option[4], option[5], option[6], option[7],
+                        ]),
+                        preferred_lifetime: u32::from_be_bytes([
+                            option[8], option[9], option[10], option[11],
+                        ]),
+                        prefix: read_addr(&option[16..32]),
+                    });
+                }
+                // 不认识的选项直接忽略（RFC 4861 第 4.6 节）
+                _ => {}
+            }
+        }
+        Ok(message)
+    }
+
+    /// 构造成 ICMPv6 报文
+    pub fn to_icmp(&self) -> IcmpPacket {
+        let mut icmp = IcmpPacket {
+            icmp_type: 0,
+            code: 0,
+            checksum: 0,
+            identifier: 0,
+            sequence: 0,
+            payload: Vec::new(),
+        };
+        let payload = &mut icmp.payload;
+        let lladdr_option = |payload: &mut Vec<u8>, kind: u8, mac: &Option<MacAddr>| {
+            if let Some(mac) = mac {
+                payload.extend_from_slice(&[kind, 1]);
+                payload.extend_from_slice(mac);
+            }
+        };
+        let icmp_type = match self {
+            NdpMessage::RouterSolicitation { source_lladdr } => {
+                lladdr_option(payload, NDP_OPT_SOURCE_LLADDR, source_lladdr);
+                Icmpv6Type::RouterSolicitation
+            }
+            NdpMessage::RouterAdvertisement {
+                cur_hop_limit,
+                managed,
+                other,
+                router_lifetime,
+                reachable_time,
+                retrans_timer,
+                source_lladdr,
+                mtu,
+                prefixes,
+            } => {
+                let mut flags = 0;
+                if *managed {
+                    flags |= RA_FLAG_MANAGED;
+                }
+                if *other {
+                    flags |= RA_FLAG_OTHER;
+                }
+                icmp.identifier = u16::from_be_bytes([*cur_hop_limit, flags]);
+                icmp.sequence = *router_lifetime;
+                payload.extend_from_slice(&reachable_time.to_be_bytes());
+                payload.extend_from_slice(&retrans_timer.to_be_bytes());
+                lladdr_option(payload, NDP_OPT_SOURCE_LLADDR, source_lladdr);
+                if let Some(mtu) = mtu {
+                    payload.extend_from_slice(&[NDP_OPT_MTU, 1, 0, 0]);
+                    payload.extend_from_slice(&mtu.to_be_bytes());
+                }
+                for prefix in prefixes {
+                    let mut flags = 0;
+                    if prefix.on_link {
+                        flags |= PREFIX_FLAG_ON_LINK;
+                    }
+                    if prefix.autonomous {
+                        flags |= PREFIX_FLAG_AUTONOMOUS;
+                    }
+                    payload.extend_from_slice(&[NDP_OPT_PREFIX_INFO, 4, prefix.prefix_len, flags]);
+                    payload.extend_from_slice(&prefix.valid_lifetime.to_be_bytes());
+                    payload.extend_from_slice(&prefix.preferred_lifetime.to_be_bytes());
+                    payload.extend_from_slice(&[0; 4]);
+                    payload.extend_from_slice(&prefix.prefix.octets());
+                }
+                Icmpv6Type::RouterAdvertisement
+            }
+            NdpMessage::NeighborSolicitation {
+                target,
+                source_lladdr,
+            } => {
+                payload.extend_from_slice(&target.octets());
+                lladdr_option(payload, NDP_OPT_SOURCE_LLADDR, source_lladdr);
+                Icmpv6Type::NeighborSolicitation
+            }
+            NdpMessage::NeighborAdvertisement {
+                router,
+                solicited,
+                overrides,
+                target,
+                target_lladdr,
+            } => {
+                if *router {
+                    icmp.identifier |= NA_FLAG_ROUTER;
+                }
+                if *solicited {
+                    icmp.identifier |= NA_FLAG_SOLICITED;
+                }
+                if *overrides {
+                    icmp.identifier |= NA_FLAG_OVERRIDE;
+                }
+                payload.extend_from_slice(&target.octets());
+                lladdr_option(payload, NDP_OPT_TARGET_LLADDR, target_lladdr);
+                Icmpv6Type::NeighborAdvertisement
+            }
+            NdpMessage::Redirect {
+                target,
+                destination,
+                target_lladdr,
+            } => {
+                payload.extend_from_slice(&target.octets());
+                payload.extend_from_slice(&destination.octets());
+                lladdr_option(payload, NDP_OPT_TARGET_LLADDR, target_lladdr);
+                Icmpv6Type::Redirect
+            }
+        };
+        icmp.icmp_type = Icmpv6Type::to_u8(icmp_type);
+        icmp
+    }
+
+    /// 构造成跳数限制为 255 的 IPv6 包
+    pub fn to_packet(&self, src_addr: Ipv6Addr, dst_addr: Ipv6Addr) -> Ipv6Packet {
+        Ipv6Packet::build(
+            src_addr,
+            dst_addr,
+            IPV6_NEXT_ICMPV6,
+            NDP_HOP_LIMIT,
+            self.to_icmp().to_bytes_v6(src_addr, dst_addr),
+        )
+    }
+}
+
+fn read_addr(data: &[u8]) -> Ipv6Addr {
+    let mut octets = [0u8; 16];
+    octets.copy_from_slice(&data[..16]);
+    Ipv6Addr::from(octets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ndp_round_trip() {
+        let src: Ipv6Addr = "fe80::1".parse().unwrap();
+        let dst: Ipv6Addr = "fe80::2".parse().unwrap();
+        let messages = vec![
+            NdpMessage::NeighborSolicitation {
+                target: dst,
+                source_lladdr: Some([2, 0, 0, 0, 0, 1]),
+            },
+            NdpMessage::NeighborAdvertisement {
+                router: false,
+                solicited: true,
+                overrides: true,
+                target: dst,
+                target_lladdr: Some([2, 0, 0, 0, 0, 2]),
+            },
+            NdpMessage::RouterAdvertisement {
+                cur_hop_limit: 64,
+                managed: false,
+                other: true,
+                router_lifetime: 1800,
+                reachable_time: 0,
+                retrans_timer: 0,
+                source_lladdr: Some([2, 0, 0, 0, 0, 3]),
+                mtu: Some(1500),
+                prefixes: vec![PrefixInfo {
+                    prefix: "2001:db8::".parse().unwrap(),
+                    prefix_len: 64,
+                    on_link: true,
+                    autonomous: true,
+                    valid_lifetime: 86400,
+                    preferred_lifetime: 14400,
+                }],
+            },
+        ];
+        for message in messages {
+            let packet = Ipv6Packet::parse(&message.to_packet(src, dst).to_bytes()).unwrap();
+            assert_eq!(packet.hop_limit, NDP_HOP_LIMIT);
+            let icmp = IcmpPacket::parse_v6(&packet.payload, src, dst).unwrap();
+            assert_eq!(NdpMessage::parse(&icmp).unwrap(), message);
+            // 伪头部中的地址不同，校验和错误
+            let other: Ipv6Addr = "fe80::3".parse().unwrap();
+            assert!(IcmpPacket::parse_v6(&packet.payload, src, other).is_err());
+        }
+    }
+}
@@ -2,14 +2,29 @@
 //!
 //! IPv4 协议负责数据包的路由和转发
 
+pub mod options;
+pub mod reassembly;
+
+use std::hash::{BuildHasher, RandomState};
 use std::net::Ipv4Addr;
 
 use crate::error::{Result, StackError};
+use crate::icmp::internet_checksum;
 
 const IP_PACKET_LEN: usize = 20;
 
+/// 头部最长 15 * 4 = 60 字节，选项最多 40 字节
+const IP_MAX_OPTIONS_LEN: usize = 40;
+
+// 标志位（3 bit）
+pub const IP_FLAG_DF: u8 = 0b010; // 不分片（Don't Fragment）
+pub const IP_FLAG_MF: u8 = 0b001; // 还有更多分片（More Fragments）
+
+/// 标识计数器的数量，目标地址按哈希分到不同计数器
+const IP_IDENT_BUCKETS: usize = 2048;
+
 /// IPv4 数据包结构
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Ipv4Packet {
     pub version: u8,          // 版本号（IPv4 是 4）
     pub ihl: u8,              // 头部长度（Internet Header Length）
@@ -23,6 +38,7 @@ pub struct Ipv4Packet {
     pub checksum: u16,        // 头部校验和
     pub src_addr: Ipv4Addr,   // 源 IP 地址
     pub dst_addr: Ipv4Addr,   // 目标 IP 地址
+    pub options: Vec<u8>,     // 选项（头部第 20 字节到 IHL * 4 之间的原始字节）
     pub payload: Vec<u8>,     // 数据负载
 }
 
@@ -36,12 +52,35 @@ impl Ipv4Packet {
         // 字节 0： 版本号(高四位) + ihs(低四位)
         let version = data[0] >> 4;
         let ihl = data[0] & 0x0F;
+        if version != 4 {
+            return Err(StackError::InvalidPacket(format!(
+                "Ip version {} is not 4",
+                version
+            )));
+        }
+        // 计算头部长度（IHL * 4 字节）
+        let header_len = (ihl as usize) * 4;
+        if header_len < IP_PACKET_LEN || header_len > data.len() {
+            return Err(StackError::InvalidPacket(format!(
+                "Ip header length {} invalid for {} bytes",
+                header_len,
+                data.len()
+            )));
+        }
 
         // 字节 1： tos
         let tos = data[1];
 
         // 字节 2-3： 总长度
         let total_length = u16::from_be_bytes([data[2], data[3]]);
+        // 以太网帧可能有填充，超过总长度的部分不属于这个包
+        if (total_length as usize) < header_len || total_length as usize > data.len() {
+            return Err(StackError::InvalidPacket(format!(
+                "Ip total length {} invalid for {} bytes",
+                total_length,
+                data.len()
+            )));
+        }
 
         // 字节 4-5： 标识符
         let identification = u16::from_be_bytes([data[4], data[5]]);
@@ -61,6 +100,13 @@ impl Ipv4Packet {
 
         // 字节 10-11： 校验和
         let checksum = u16::from_be_bytes([data[10], data[11]]);
+        // 包含校验和字段在内的头部求和结果应为 0
+        if internet_checksum(&data[..header_len]) != 0 {
+            return Err(StackError::ChecksumMismatch(format!(
+                "Ip header checksum {:#06x} mismatch",
+                checksum
+            )));
+        }
 
         // 字节12-15： 源地址
         let src_addr = Ipv4Addr::new(data[12], data[13], data[14], data[15]);
@@ -68,11 +114,11 @@ impl Ipv4Packet {
         // 字节16-19： 目标地址
         let dst_addr = Ipv4Addr::new(data[16], data[17], data[18], data[19]);
 
-        // 计算头部长度（IHL * 4 字节）
-        let header_len = (ihl as usize) * 4;
+        // 选项保留原始字节
+        let options = data[IP_PACKET_LEN..header_len].to_vec();
 
-        // payload 从头部结束后开始
-        let payload = data[header_len..].to_vec();
+        // payload 从头部结束后开始，到总长度结束
+        let payload = data[header_len..total_length as usize].to_vec();
         Ok(Self {
             version,
             ihl,
@@ -86,6 +132,7 @@ impl Ipv4Packet {
             checksum,
             src_addr,
             dst_addr,
+            options,
             payload,
         })
     }
@@ -112,18 +159,88 @@ impl Ipv4Packet {
             checksum: 0, // 稍后计算
             src_addr,
             dst_addr,
+            options: Vec::new(),
             payload,
         }
     }
 
-    /// 序列化为字节数组
+    pub fn dont_fragment(&self) -> bool {
+        self.flags & IP_FLAG_DF != 0
+    }
+
+    pub fn more_fragments(&self) -> bool {
+        self.flags & IP_FLAG_MF != 0
+    }
+
+    /// 是否是分片（MF 置位或片偏移不为 0）
+    pub fn is_fragment(&self) -> bool {
+        self.more_fragments() || self.fragment_offset != 0
+    }
+
+    /// 按 MTU 分片（RFC 791 第 3.2 节）。不超过 MTU 时原样返回；
+    /// 需要分片但设置了 DF 时返回错误。除最后一片外每片的数据长度都是 8 的倍数，
+    /// 第一片携带全部选项，之后的分片只携带 copied 位为 1 的选项
+    pub fn fragment(&self, mtu: usize) -> Result<Vec<Ipv4Packet>> {
+        if self.header_len() + self.payload.len() <= mtu {
+            return Ok(vec![self.clone()]);
+        }
+        if self.dont_fragment() {
+            return Err(StackError::InvalidPacket(format!(
+                "Ip packet of {} bytes exceeds MTU {} with DF set",
+                self.header_len() + self.payload.len(),
+                mtu
+            )));
+        }
+
+        let mut fragments = Vec::new();
+        let mut offset = 0;
+        while offset < self.payload.len() {
+            let mut fragment = self.clone();
+            if offset > 0 {
+                fragment.options = copied_options(&self.options);
+            }
+            let max_data = mtu.saturating_sub(fragment.header_len()) / 8 * 8;
+            if max_data == 0 {
+                return Err(StackError::InvalidPacket(format!(
+                    "MTU {} too small to fragment",
+                    mtu
+                )));
+            }
+            let end = (offset + max_data).min(self.payload.len());
+            fragment.payload = self.payload[offset..end].to_vec();
+            fragment.fragment_offset = self.fragment_offset + (offset / 8) as u16;
+            // 最后一片保留原来的 MF，已经是分片的包再次分片时仍然正确
+            if end < self.payload.len() {
+                fragment.flags |= IP_FLAG_MF;
+            }
+            fragment.total_length = (fragment.header_len() + fragment.payload.len()) as u16;
+            fragment.ihl = (fragment.header_len() / 4) as u8;
+            fragments.push(fragment);
+            offset = end;
+        }
+        Ok(fragments)
+    }
+
+    /// 头部长度：固定 20 字节加上按 4 字节对齐的选项
+    pub fn header_len(&self) -> usize {
+        IP_PACKET_LEN
+            + self
+                .options
+                .len()
+                .min(IP_MAX_OPTIONS_LEN)
+                .next_multiple_of(4)
+    }
+
+    /// 序列化为字节数组，IHL 和总长度按选项和负载的实际长度填写
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::with_capacity(20 + self.payload.len());
+        let header_len = self.header_len();
+        let total_length = (header_len + self.payload.len()) as u16;
+        let mut bytes = Vec::with_capacity(header_len + self.payload.len());
 
         // 第 1 行：版本(4bit) + IHL(4bit) + TOS(8bit) + 总长度(16bit)
-        bytes.push((self.version << 4) | self.ihl);
+        bytes.push((self.version << 4) | (header_len / 4) as u8);
         bytes.push(self.tos);
-        bytes.extend_from_slice(&self.total_length.to_be_bytes());
+        bytes.extend_from_slice(&total_length.to_be_bytes());
 
         // 第 2 行：标识(16bit) + 标志(3bit) + 片偏移(13bit)
         bytes.extend_from_slice(&self.identification.to_be_bytes());
@@ -139,8 +256,13 @@ impl Ipv4Packet {
         bytes.extend_from_slice(&self.src_addr.octets());
         bytes.extend_from_slice(&self.dst_addr.octets());
 
-        // 计算校验和（只计算头部 20 字节）
-        let checksum = Self::calculate_ip_checksum(&bytes[..20]);
+        // 选项，不足 4 字节的部分用 0（选项列表结束）填充
+        let options_len = self.options.len().min(IP_MAX_OPTIONS_LEN);
+        bytes.extend_from_slice(&self.options[..options_len]);
+        bytes.resize(header_len, 0);
+
+        // 计算校验和（只计算头部）
+        let checksum = internet_checksum(&bytes[..header_len]);
         bytes[10..12].copy_from_slice(&checksum.to_be_bytes());
 
         // 添加负载
@@ -149,23 +271,161 @@ impl Ipv4Packet {
         bytes
     }
 
-    /// 计算 IP 校验和
-    fn calculate_ip_checksum(header: &[u8]) -> u16 {
-        let mut sum: u32 = 0;
-
-        for chunk in header.chunks(2) {
-            let word = if chunk.len() == 2 {
-                u16::from_be_bytes([chunk[0], chunk[1]]) as u32
-            } else {
-                (chunk[0] as u32) << 8
-            };
-            sum += word;
-        }
-
+    /// 转发时把原始数据报的 TTL 减一，按 RFC 1624 增量更新头部校验和，
+    /// 不需要重新计算整个头部。返回新的 TTL
+    pub fn decrement_ttl(data: &mut [u8]) -> u8 {
+        let old = u16::from_be_bytes([data[8], data[9]]);
+        data[8] = data[8].saturating_sub(1);
+        let new = u16::from_be_bytes([data[8], data[9]]);
+        // HC' = ~(~HC + ~m + m')
+        let checksum = u16::from_be_bytes([data[10], data[11]]);
+        let mut sum = (!checksum) as u32 + (!old) as u32 + new as u32;
         while sum >> 16 != 0 {
             sum = (sum & 0xFFFF) + (sum >> 16);
         }
+        data[10..12].copy_from_slice(&(!sum as u16).to_be_bytes());
+        data[8]
+    }
+}
+
+/// 分片时需要复制到每个分片的选项（选项类型最高位 copied 为 1）
+fn copied_options(options: &[u8]) -> Vec<u8> {
+    let mut copied = Vec::new();
+    let mut i = 0;
+    while i < options.len() {
+        let kind = options[i];
+        match kind {
+            0 => break, // 选项列表结束
+            1 => {
+                i += 1; // NOP
+                continue;
+            }
+            _ => {}
+        }
+        let Some(&len) = options.get(i + 1) else {
+            break;
+        };
+        let len = len as usize;
+        if len < 2 || i + len > options.len() {
+            break;
+        }
+        if kind & 0x80 != 0 {
+            copied.extend_from_slice(&options[i..i + len]);
+        }
+        i += len;
+    }
+    copied
+}
+
+/// 按目标地址生成 IP 标识（Identification）：
+/// 同一目标的标识递增，不同目标使用不同的计数器，初始值随机
+#[derive(Debug)]
+pub struct IdentGenerator {
+    counters: Vec<u16>,
+    secret: RandomState,
+}
+
+impl IdentGenerator {
+    pub fn new() -> Self {
+        let secret = RandomState::new();
+        let counters = (0..IP_IDENT_BUCKETS)
+            .map(|i| secret.hash_one(i) as u16)
+            .collect();
+        Self { counters, secret }
+    }
+
+    pub fn next(&mut self, dst_addr: Ipv4Addr, protocol: u8) -> u16 {
+        let bucket = self.secret.hash_one((dst_addr, protocol)) as usize % IP_IDENT_BUCKETS;
+        let ident = self.counters[bucket];
+        self.counters[bucket] = ident.wrapping_add(1);
+        ident
+    }
+}
+
+impl Default for IdentGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_validation() {
+        let src = Ipv4Addr::new(192, 168, 10, 1);
+        let dst = Ipv4Addr::new(192, 168, 10, 2);
+        let mut packet = Ipv4Packet::build(src, dst, 17, 64, b"hello".to_vec());
+        packet.options = vec![0x94, 0x04, 0x00, 0x00]; // Router Alert
+        let mut bytes = packet.to_bytes();
+        assert_eq!(bytes[0], 0x46);
+        // 以太网填充不属于负载
+        bytes.extend_from_slice(&[0; 6]);
+        let parsed = Ipv4Packet::parse(&bytes).unwrap();
+        assert_eq!(parsed.options, vec![0x94, 0x04, 0x00, 0x00]);
+        assert_eq!(parsed.payload, b"hello");
+        assert_eq!(parsed.to_bytes(), bytes[..bytes.len() - 6]);
+
+        // 校验和错误
+        let mut corrupted = bytes.clone();
+        corrupted[8] ^= 0xff;
+        assert!(matches!(
+            Ipv4Packet::parse(&corrupted),
+            Err(StackError::ChecksumMismatch(_))
+        ));
+        // 版本不是 4、IHL 超出缓冲区、总长度超出缓冲区
+        let mut version = bytes.clone();
+        version[0] = 0x66;
+        assert!(Ipv4Packet::parse(&version).is_err());
+        let mut ihl = bytes[..20].to_vec();
+        ihl[0] = 0x4f;
+        assert!(matches!(
+            Ipv4Packet::parse(&ihl),
+            Err(StackError::InvalidPacket(_))
+        ));
+        assert!(Ipv4Packet::parse(&bytes[..26]).is_err());
+
+        // 增量更新的校验和与重新计算的一致
+        let mut forwarded = packet.to_bytes();
+        assert_eq!(Ipv4Packet::decrement_ttl(&mut forwarded), 63);
+        packet.ttl = 63;
+        assert_eq!(forwarded, packet.to_bytes());
+    }
+
+    #[test]
+    fn test_fragment() {
+        let src = Ipv4Addr::new(192, 168, 10, 2);
+        let dst = Ipv4Addr::new(192, 168, 10, 1);
+        let payload: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+        let mut packet = Ipv4Packet::build(src, dst, 17, 64, payload.clone());
+        // Security（copied）和 Record Route（不复制）
+        packet.options = vec![0x82, 0x04, 0x00, 0x00, 0x07, 0x03, 0x04, 0x01];
+        packet.identification = 42;
+
+        let fragments = packet.fragment(1500).unwrap();
+        assert_eq!(fragments.len(), 3);
+        let mut data = Vec::new();
+        for (i, fragment) in fragments.iter().enumerate() {
+            let bytes = fragment.to_bytes();
+            assert!(bytes.len() <= 1500);
+            let parsed = Ipv4Packet::parse(&bytes).unwrap();
+            assert_eq!(parsed.identification, 42);
+            assert_eq!(parsed.fragment_offset as usize * 8, data.len());
+            assert_eq!(parsed.more_fragments(), i < 2);
+            assert_eq!(parsed.options.len(), if i == 0 { 8 } else { 4 });
+            data.extend_from_slice(&parsed.payload);
+        }
+        assert_eq!(data, payload);
+
+        // 设置了 DF 不能分片
+        packet.flags = IP_FLAG_DF;
+        assert!(packet.fragment(1500).is_err());
+        assert_eq!(packet.fragment(4000).unwrap().len(), 1);
 
-        !sum as u16
+        // 同一目标的标识递增
+        let mut ident = IdentGenerator::new();
+        let first = ident.next(dst, 17);
+        assert_eq!(ident.next(dst, 17), first.wrapping_add(1));
     }
 }
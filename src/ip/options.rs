@@ -0,0 +1,380 @@
+//! IPv4 头部选项（RFC 791）
+//!
+//! `Ipv4Packet::options` 保留选项的原始字节，需要处理时解析成 `Ipv4Option`，
+//! 修改后再写回。路由器转发时在 Record Route 和 Timestamp 中记录自己，
+//! 并按 Loose/Strict Source Route 选择下一跳
+
+use std::net::Ipv4Addr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::Ipv4Packet;
+use crate::error::{Result, StackError};
+
+// 选项类型
+const IPOPT_EOL: u8 = 0; // 选项列表结束
+const IPOPT_NOP: u8 = 1; // 无操作（用于对齐）
+const IPOPT_RR: u8 = 7; // 路由记录
+const IPOPT_TS: u8 = 68; // 时间戳
+const IPOPT_LSRR: u8 = 131; // 宽松源路由
+const IPOPT_SSRR: u8 = 137; // 严格源路由
+const IPOPT_RA: u8 = 148; // 路由器警告（RFC 2113）
+
+/// 指针从选项第一个字节开始按 1 计数，路由类选项的第一个槽位在第 4 字节，
+/// 时间戳选项的第一个槽位在第 5 字节
+const IPOPT_ROUTE_MIN_POINTER: u8 = 4;
+const IPOPT_TS_MIN_POINTER: u8 = 5;
+
+// 时间戳选项的 flag
+pub const IPOPT_TS_TSONLY: u8 = 0; // 只记录时间戳
+pub const IPOPT_TS_TSANDADDR: u8 = 1; // 记录地址和时间戳
+pub const IPOPT_TS_PRESPEC: u8 = 3; // 只在预先指定的地址记录时间戳
+
+/// 一个 IPv4 选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ipv4Option {
+    EndOfList,
+    Nop,
+    RecordRoute(RouteOption),
+    LooseSourceRoute(RouteOption),
+    StrictSourceRoute(RouteOption),
+    Timestamp(TimestampOption),
+    RouterAlert(u16), // 0 表示路由器应检查这个包
+    Unknown { kind: u8, data: Vec<u8> },
+}
+
+/// 路由记录和源路由共用的格式：指针和地址槽位
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteOption {
+    pub pointer: u8,          // 下一个槽位的位置
+    pub route: Vec<Ipv4Addr>, // 所有槽位，包括还没写入的
+}
+
+impl RouteOption {
+    /// 预留 slots 个空槽位，用于构造路由记录选项
+    pub fn new(slots: usize) -> Self {
+        Self {
+            pointer: IPOPT_ROUTE_MIN_POINTER,
+            route: vec![Ipv4Addr::UNSPECIFIED; slots],
+        }
+    }
+
+    fn index(&self) -> usize {
+        (self.pointer.saturating_sub(IPOPT_ROUTE_MIN_POINTER) / 4) as usize
+    }
+
+    /// 已经写入的地址
+    pub fn recorded(&self) -> &[Ipv4Addr] {
+        &self.route[..self.index().min(self.route.len())]
+    }
+
+    /// 下一个槽位的地址，对源路由来说是下一跳
+    pub fn next(&self) -> Option<Ipv4Addr> {
+        self.route.get(self.index()).copied()
+    }
+
+    /// 在下一个槽位写入地址并前移指针，没有空槽位时返回 false
+    pub fn record(&mut self, addr: Ipv4Addr) -> bool {
+        let index = self.index();
+        match self.route.get_mut(index) {
+            Some(slot) => {
+                *slot = addr;
+                self.pointer += 4;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// 时间戳选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimestampOption {
+    pub pointer: u8,
+    pub overflow: u8, // 因为没有空间而没能记录的模块数（4 bit）
+    pub flag: u8,
+    pub entries: Vec<(Option<Ipv4Addr>, u32)>, // flag 为 0 时没有地址
+}
+
+impl TimestampOption {
+    pub fn new(flag: u8, slots: usize) -> Self {
+        let addr = (flag != IPOPT_TS_TSONLY).then_some(Ipv4Addr::UNSPECIFIED);
+        Self {
+            pointer: IPOPT_TS_MIN_POINTER,
+            overflow: 0,
+            flag,
+            entries: vec![(addr, 0); slots],
+        }
+    }
+
+    fn entry_len(&self) -> usize {
+        if self.flag == IPOPT_TS_TSONLY { 4 } else { 8 }
+    }
+
+    fn index(&self) -> usize {
+        self.pointer.saturating_sub(IPOPT_TS_MIN_POINTER) as usize / self.entry_len()
+    }
+
+    /// 已经写入的记录
+    pub fn recorded(&self) -> &[(Option<Ipv4Addr>, u32)] {
+        &self.entries[..self.index().min(self.entries.len())]
+    }
+
+    /// 记录本机的时间戳。flag 为 3 时只有下一个预先指定的地址是 addr 才记录；
+    /// 没有空间时溢出计数加一
+    pub fn record(&mut self, addr: Ipv4Addr, timestamp: u32) -> bool {
+        let index = self.index();
+        let flag = self.flag;
+        let Some(entry) = self.entries.get_mut(index) else {
+            self.overflow = (self.overflow + 1).min(0x0F);
+            return false;
+        };
+        match flag {
+            IPOPT_TS_TSONLY => *entry = (None, timestamp),
+            IPOPT_TS_PRESPEC if entry.0 != Some(addr) => return false,
+            _ => *entry = (Some(addr), timestamp),
+        }
+        self.pointer += self.entry_len() as u8;
+        true
+    }
+}
+
+impl Ipv4Option {
+    pub fn kind(&self) -> u8 {
+        match self {
+            Ipv4Option::EndOfList => IPOPT_EOL,
+            Ipv4Option::Nop => IPOPT_NOP,
+            Ipv4Option::RecordRoute(_) => IPOPT_RR,
+            Ipv4Option::LooseSourceRoute(_) => IPOPT_LSRR,
+            Ipv4Option::StrictSourceRoute(_) => IPOPT_SSRR,
+            Ipv4Option::Timestamp(_) => IPOPT_TS,
+            Ipv4Option::RouterAlert(_) => IPOPT_RA,
+            Ipv4Option::Unknown { kind, .. } => *kind,
+        }
+    }
+
+    /// 解析选项字节，遇到选项列表结束时停止
+    pub fn parse_list(data: &[u8]) -> Result<Vec<Ipv4Option>> {
+        let mut options = Vec::new();
+        let mut i = 0;
+        while i < data.len() {
+            match data[i] {
+                IPOPT_EOL => {
+                    options.push(Ipv4Option::EndOfList);
+                    break;
+                }
+                IPOPT_NOP => {
+                    options.push(Ipv4Option::Nop);
+                    i += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            let len = data.get(i + 1).copied().unwrap_or(0) as usize;
+            if len < 2 || i + len > data.len() {
+                return Err(StackError::InvalidPacket(format!(
+                    "Ip option {} has invalid length {}",
+                    data[i], len
+                )));
+            }
+            options.push(Self::parse(data[i], &data[i + 2..i + len])?);
+            i += len;
+        }
+        Ok(options)
+    }
+
+    /// 解析一个选项，body 是类型和长度之后的部分
+    fn parse(kind: u8, body: &[u8]) -> Result<Self> {
+        let invalid = || StackError::InvalidPacket(format!("Invalid ip option {}", kind));
+        match kind {
+            IPOPT_RR | IPOPT_LSRR | IPOPT_SSRR => {
+                let (&pointer, addrs) = body.split_first().ok_or_else(invalid)?;
+                if pointer < IPOPT_ROUTE_MIN_POINTER || addrs.len() % 4 != 0 {
+                    return Err(invalid());
+                }
+                let route = RouteOption {
+                    pointer,
+                    route: addrs
+                        .chunks(4)
+                        .map(|a| Ipv4Addr::new(a[0], a[1], a[2], a[3]))
+                        .collect(),
+                };
+                Ok(match kind {
+                    IPOPT_RR => Ipv4Option::RecordRoute(route),
+                    IPOPT_LSRR => Ipv4Option::LooseSourceRoute(route),
+                    _ => Ipv4Option::StrictSourceRoute(route),
+                })
+            }
+            IPOPT_TS => {
+                if body.len() < 2 || body[0] < IPOPT_TS_MIN_POINTER {
+                    return Err(invalid());
+                }
+                let flag = body[1] & 0x0F;
+                let entry_len = match flag {
+                    IPOPT_TS_TSONLY => 4,
+                    IPOPT_TS_TSANDADDR | IPOPT_TS_PRESPEC => 8,
+                    _ => return Err(invalid()),
+                };
+                let data = &body[2..];
+                if !data.len().is_multiple_of(entry_len) {
+                    return Err(invalid());
+                }
+                let entries = data
+                    .chunks(entry_len)
+                    .map(|e| match entry_len {
+                        4 => (None, u32::from_be_bytes([e[0], e[1], e[2], e[3]])),
+                        _ => (
+                            Some(Ipv4Addr::new(e[0], e[1], e[2], e[3])),
+                            u32::from_be_bytes([e[4], e[5], e[6], e[7]]),
+                        ),
+                    })
+                    .collect();
+                Ok(Ipv4Option::Timestamp(TimestampOption {
+                    pointer: body[0],
+                    overflow: body[1] >> 4,
+                    flag,
+                    entries,
+                }))
+            }
+            IPOPT_RA => match body {
+                [a, b] => Ok(Ipv4Option::RouterAlert(u16::from_be_bytes([*a, *b]))),
+                _ => Err(invalid()),
+            },
+            _ => Ok(Ipv4Option::Unknown {
+                kind,
+                data: body.to_vec(),
+            }),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        match self {
+            Ipv4Option::EndOfList | Ipv4Option::Nop => return vec![self.kind()],
+            Ipv4Option::RecordRoute(route)
+            | Ipv4Option::LooseSourceRoute(route)
+            | Ipv4Option::StrictSourceRoute(route) => {
+                body.push(route.pointer);
+                for addr in &route.route {
+                    body.extend_from_slice(&addr.octets());
+                }
+            }
+            Ipv4Option::Timestamp(ts) => {
+                body.push(ts.pointer);
+                body.push((ts.overflow << 4) | (ts.flag & 0x0F));
+                for (addr, timestamp) in &ts.entries {
+                    if let Some(addr) = addr {
+                        body.extend_from_slice(&addr.octets());
+                    }
+                    body.extend_from_slice(&timestamp.to_be_bytes());
+                }
+            }
+            Ipv4Option::RouterAlert(value) => body.extend_from_slice(&value.to_be_bytes()),
+            Ipv4Option::Unknown { data, .. } => body.extend_from_slice(data),
+        }
+        let mut bytes = vec![self.kind(), (body.len() + 2) as u8];
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    pub fn list_to_bytes(options: &[Ipv4Option]) -> Vec<u8> {
+        options
+            .iter()
+            .flat_map(|option| option.to_bytes())
+            .collect()
+    }
+
+    /// 转发或回复 ping 时记录本机：Record Route 写入地址，Timestamp 写入时间戳，
+    /// 其他选项不变
+    pub fn record(&mut self, addr: Ipv4Addr, timestamp: u32) {
+        match self {
+            Ipv4Option::RecordRoute(route) => {
+                route.record(addr);
+            }
+            Ipv4Option::Timestamp(ts) => {
+                ts.record(addr, timestamp);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 时间戳选项使用的时间：UTC 午夜以来的毫秒数
+pub fn ip_timestamp() -> u32 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    (now.as_millis() % 86_400_000) as u32
+}
+
+impl Ipv4Packet {
+    pub fn parse_options(&self) -> Result<Vec<Ipv4Option>> {
+        Ipv4Option::parse_list(&self.options)
+    }
+
+    pub fn set_options(&mut self, options: &[Ipv4Option]) {
+        self.options = Ipv4Option::list_to_bytes(options);
+        self.ihl = (self.header_len() / 4) as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options_round_trip() {
+        let mut ts = TimestampOption::new(IPOPT_TS_TSANDADDR, 2);
+        ts.record(Ipv4Addr::new(10, 0, 0, 1), 1000);
+        let options = vec![
+            Ipv4Option::RecordRoute(RouteOption::new(2)),
+            Ipv4Option::Nop,
+            Ipv4Option::RouterAlert(0),
+            Ipv4Option::Timestamp(ts),
+            Ipv4Option::EndOfList,
+        ];
+        let mut packet = Ipv4Packet::build(
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 2),
+            1,
+            64,
+            vec![0; 8],
+        );
+        packet.set_options(&options);
+        let parsed = Ipv4Packet::parse(&packet.to_bytes()).unwrap();
+        assert_eq!(parsed.header_len(), 20 + 40);
+        assert_eq!(parsed.parse_options().unwrap(), options);
+
+        // 长度超出选项区域
+        assert!(Ipv4Option::parse_list(&[IPOPT_RR, 7, 4, 0]).is_err());
+        assert_eq!(
+            Ipv4Option::parse_list(&[IPOPT_RA, 4, 0, 0, 0, 0]).unwrap(),
+            vec![Ipv4Option::RouterAlert(0), Ipv4Option::EndOfList]
+        );
+    }
+
+    #[test]
+    fn test_record() {
+        let a = Ipv4Addr::new(10, 0, 0, 1);
+        let b = Ipv4Addr::new(10, 0, 0, 2);
+        let mut route = RouteOption::new(1);
+        assert!(route.record(a));
+        assert!(!route.record(b));
+        assert_eq!(route.recorded(), &[a]);
+
+        // 源路由的下一跳
+        let source = RouteOption {
+            pointer: 8,
+            route: vec![a, b],
+        };
+        assert_eq!(source.next(), Some(b));
+
+        // 预先指定地址时只有轮到本机才记录，空间用完后计入溢出
+        let mut ts = TimestampOption::new(IPOPT_TS_PRESPEC, 1);
+        ts.entries[0].0 = Some(b);
+        assert!(!ts.record(a, 1));
+        assert!(ts.record(b, 2));
+        assert!(!ts.record(b, 3));
+        assert_eq!(ts.recorded(), &[(Some(b), 2)]);
+        assert_eq!(ts.overflow, 1);
+    }
+}
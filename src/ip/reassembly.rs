@@ -0,0 +1,337 @@
+//! IPv4 分片重组
+//!
+//! 按 (源地址, 目标地址, 协议, 标识) 缓存分片，分片可以乱序到达。
+//! 重叠的分片按 RFC 5722 的建议丢弃整个数据报；超时未完成的数据报被丢弃，
+//! 收到过第一片时回复 ICMP 超时（分片重组超时）
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use tracing::debug;
+
+use super::{IP_FLAG_MF, IP_PACKET_LEN, Ipv4Packet};
+use crate::error::{Result, StackError};
+
+/// 重组超时时间（RFC 791 建议 15 秒，Linux 默认 30 秒）
+const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 所有未完成数据报占用的内存上限（对应 Linux 的 ipfrag_high_thresh）
+const REASSEMBLY_MEMORY_LIMIT: usize = 4 * 1024 * 1024;
+
+/// IPv4 数据报的最大长度
+const MAX_DATAGRAM_LEN: usize = 65535;
+
+/// 标识同一个数据报的分片
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FragmentKey {
+    pub src_addr: Ipv4Addr,
+    pub dst_addr: Ipv4Addr,
+    pub protocol: u8,
+    pub identification: u16,
+}
+
+impl FragmentKey {
+    fn of(packet: &Ipv4Packet) -> Self {
+        Self {
+            src_addr: packet.src_addr,
+            dst_addr: packet.dst_addr,
+            protocol: packet.protocol,
+            identification: packet.identification,
+        }
+    }
+}
+
+/// 一个未完成的数据报
+#[derive(Debug)]
+struct FragmentBuffer {
+    first: Option<Ipv4Packet>,           // 第一片的头部（不含数据）
+    fragments: BTreeMap<usize, Vec<u8>>, // 字节偏移 -> 分片数据
+    total_len: Option<usize>,            // 收到最后一片后才知道数据总长度
+    received: usize,                     // 已收到的数据字节数
+    created: Instant,
+    poisoned: bool, // 发现重叠，之后的分片全部丢弃直到超时
+}
+
+/// 分片重组缓冲区
+#[derive(Debug)]
+pub struct Reassembler {
+    buffers: HashMap<FragmentKey, FragmentBuffer>,
+    memory: usize, // 所有缓冲区的数据字节数
+    memory_limit: usize,
+    timed_out: Vec<Ipv4Packet>, // 超时数据报的第一片，用于回复 ICMP 超时
+}
+
+impl Reassembler {
+    pub fn new() -> Self {
+        Self {
+            buffers: HashMap::new(),
+            memory: 0,
+            memory_limit: REASSEMBLY_MEMORY_LIMIT,
+            timed_out: Vec::new(),
+        }
+    }
+
+    pub fn set_memory_limit(&mut self, memory_limit: usize) {
+        self.memory_limit = memory_limit;
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.memory
+    }
+
+    /// 放入一个收到的包。不是分片时原样返回；
+    /// 数据报重组完成时返回完整的数据报，还缺分片时返回 None
+    pub fn push(&mut self, packet: Ipv4Packet, now: Instant) -> Result<Option<Ipv4Packet>> {
+        if !packet.is_fragment() {
+            return Ok(Some(packet));
+        }
+        let key = FragmentKey::of(&packet);
+        let offset = packet.fragment_offset as usize * 8;
+        let end = offset + packet.payload.len();
+        let more = packet.more_fragments();
+        if packet.payload.is_empty()
+            || (more && !packet.payload.len().is_multiple_of(8))
+            || end + IP_PACKET_LEN > MAX_DATAGRAM_LEN
+        {
+            return Err(StackError::InvalidPacket(format!(
+                "Invalid fragment at offset {} with {} bytes",
+                offset,
+                packet.payload.len()
+            )));
+        }
+
+        // 内存不够时先淘汰最早的数据报
+        while self.memory + packet.payload.len() > self.memory_limit {
+            let Some(oldest) = self
+                .buffers
+                .iter()
+                .filter(|(k, _)| **k != key)
+                .min_by_key(|(_, buffer)| buffer.created)
+                .map(|(k, _)| *k)
+            else {
+                return Err(StackError::InvalidPacket(String::from(
+                    "Reassembly memory limit exceeded",
+                )));
+            };
+            debug!("Reassembly memory full, evict {:?}", oldest);
+            self.remove(&oldest);
+        }
+
+        let buffer = self.buffers.entry(key).or_insert_with(|| FragmentBuffer {
+            first: None,
+            fragments: BTreeMap::new(),
+            total_len: None,
+            received: 0,
+            created: now,
+            poisoned: false,
+        });
+        if buffer.poisoned {
+            return Err(StackError::InvalidPacket(String::from(
+                "Fragment of discarded datagram",
+            )));
+        }
+        // 完全相同的重复分片直接忽略
+        if buffer
+            .fragments
+            .get(&offset)
+            .is_some_and(|data| *data == packet.payload)
+        {
+            return Ok(None);
+        }
+
+        // 重组后的头部取自第一片，收到第一片后按它的头部长度检查数据报总长度
+        let header_len = match &buffer.first {
+            Some(first) if offset != 0 => Some(first.header_len()),
+            _ if offset == 0 => Some(packet.header_len()),
+            _ => None,
+        };
+        let data_end = buffer
+            .fragments
+            .iter()
+            .next_back()
+            .map_or(end, |(&o, data)| end.max(o + data.len()));
+        if header_len.is_some_and(|len| len + data_end > MAX_DATAGRAM_LEN) {
+            return Err(StackError::InvalidPacket(format!(
+                "Reassembled datagram from {} exceeds {} bytes",
+                key.src_addr, MAX_DATAGRAM_LEN
+            )));
+        }
+
+        let overlaps = buffer
+            .fragments
+            .iter()
+            .any(|(&o, data)| offset < o + data.len() && o < end);
+        let beyond_end = buffer.total_len.is_some_and(|total| end > total);
+        let bad_last = !more
+            && (buffer.total_len.is_some_and(|total| total != end)
+                || buffer
+                    .fragments
+                    .iter()
+                    .next_back()
+                    .is_some_and(|(&o, data)| o + data.len() > end));
+        if overlaps || beyond_end || bad_last {
+            buffer.poisoned = true;
+            self.memory -= buffer.received;
+            buffer.received = 0;
+            buffer.fragments.clear();
+            buffer.first = None;
+            return Err(StackError::InvalidPacket(format!(
+                "Overlapping fragment at offset {} from {}",
+                offset, key.src_addr
+            )));
+        }
+
+        if !more {
+            buffer.total_len = Some(end);
+        }
+        if offset == 0 {
+            buffer.first = Some(Ipv4Packet {
+                payload: Vec::new(),
+                ..packet.clone()
+            });
+        }
+        buffer.received += packet.payload.len();
+        self.memory += packet.payload.len();
+        buffer.fragments.insert(offset, packet.payload);
+
+        if buffer.first.is_none() || buffer.total_len != Some(buffer.received) {
+            return Ok(None);
+        }
+        let Some(buffer) = self.buffers.remove(&key) else {
+            return Ok(None);
+        };
+        self.memory -= buffer.received;
+        let Some(mut datagram) = buffer.first else {
+            return Ok(None);
+        };
+        datagram.payload = buffer.fragments.into_values().flatten().collect();
+        datagram.flags &= !IP_FLAG_MF;
+        datagram.fragment_offset = 0;
+        let Ok(total_length) = u16::try_from(datagram.header_len() + datagram.payload.len()) else {
+            return Err(StackError::InvalidPacket(format!(
+                "Reassembled datagram from {} exceeds {} bytes",
+                key.src_addr, MAX_DATAGRAM_LEN
+            )));
+        };
+        datagram.total_length = total_length;
+        Ok(Some(datagram))
+    }
+
+    /// 丢弃超时的数据报
+    pub fn poll(&mut self, now: Instant) {
+        let expired: Vec<FragmentKey> = self
+            .buffers
+            .iter()
+            .filter(|(_, buffer)| now.duration_since(buffer.created) >= REASSEMBLY_TIMEOUT)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in expired {
+            debug!("Reassembly of {:?} timed out", key);
+            if let Some(first) = self.remove(&key) {
+                self.timed_out.push(first);
+            }
+        }
+    }
+
+    /// 取出超时数据报的第一片（含数据）
+    pub fn take_timed_out(&mut self) -> Vec<Ipv4Packet> {
+        std::mem::take(&mut self.timed_out)
+    }
+
+    /// 删除缓冲区，返回带第一片数据的第一片
+    fn remove(&mut self, key: &FragmentKey) -> Option<Ipv4Packet> {
+        let mut buffer = self.buffers.remove(key)?;
+        self.memory -= buffer.received;
+        let mut first = buffer.first.take()?;
+        first.payload = buffer.fragments.remove(&0).unwrap_or_default();
+        Some(first)
+    }
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ip::IP_MAX_OPTIONS_LEN;
+
+    fn fragments() -> (Vec<u8>, Vec<Ipv4Packet>) {
+        let src = Ipv4Addr::new(192, 168, 10, 1);
+        let dst = Ipv4Addr::new(192, 168, 10, 2);
+        let payload: Vec<u8> = (0..3000).map(|i| (i % 251) as u8).collect();
+        let mut packet = Ipv4Packet::build(src, dst, 17, 64, payload.clone());
+        packet.identification = 7;
+        (payload, packet.fragment(1000).unwrap())
+    }
+
+    #[test]
+    fn test_reassemble_out_of_order() {
+        let now = Instant::now();
+        let (payload, fragments) = fragments();
+        let mut reassembler = Reassembler::new();
+        let mut result = None;
+        for fragment in fragments.iter().rev() {
+            assert!(result.is_none());
+            result = reassembler.push(fragment.clone(), now).unwrap();
+        }
+        let datagram = result.unwrap();
+        assert_eq!(datagram.payload, payload);
+        assert!(!datagram.is_fragment());
+        assert_eq!(reassembler.memory_usage(), 0);
+
+        // 重叠的分片使整个数据报被丢弃
+        let mut overlapping = fragments[1].clone();
+        overlapping.fragment_offset -= 1;
+        reassembler.push(fragments[0].clone(), now).unwrap();
+        assert!(reassembler.push(overlapping, now).is_err());
+        assert!(reassembler.push(fragments[1].clone(), now).is_err());
+        assert!(reassembler.push(fragments[3].clone(), now).is_err());
+        assert_eq!(reassembler.memory_usage(), 0);
+    }
+
+    #[test]
+    fn test_reassembly_length_uses_first_header() {
+        let now = Instant::now();
+        let src = Ipv4Addr::new(192, 168, 10, 1);
+        let dst = Ipv4Addr::new(192, 168, 10, 2);
+        let mut first = Ipv4Packet::build(src, dst, 17, 64, vec![0; 8]);
+        first.options = vec![1; IP_MAX_OPTIONS_LEN];
+        first.flags |= IP_FLAG_MF;
+        // 最后一片自身只有 20 字节头部，但和第一片的 60 字节头部合起来超过 65535
+        let mut last = Ipv4Packet::build(src, dst, 17, 64, vec![0; 8]);
+        last.fragment_offset = ((MAX_DATAGRAM_LEN - IP_PACKET_LEN - 8) / 8) as u16;
+        assert!(last.fragment_offset as usize * 8 + 8 + IP_PACKET_LEN <= MAX_DATAGRAM_LEN);
+
+        let mut reassembler = Reassembler::new();
+        reassembler.push(first.clone(), now).unwrap();
+        assert!(reassembler.push(last.clone(), now).is_err());
+
+        // 第一片后到也一样
+        let mut reassembler = Reassembler::new();
+        reassembler.push(last, now).unwrap();
+        assert!(reassembler.push(first, now).is_err());
+    }
+
+    #[test]
+    fn test_reassembly_timeout() {
+        let now = Instant::now();
+        let (_, fragments) = fragments();
+        let mut reassembler = Reassembler::new();
+        reassembler.push(fragments[0].clone(), now).unwrap();
+        reassembler.push(fragments[2].clone(), now).unwrap();
+        reassembler.poll(now + REASSEMBLY_TIMEOUT / 2);
+        assert!(reassembler.take_timed_out().is_empty());
+
+        reassembler.poll(now + REASSEMBLY_TIMEOUT);
+        let timed_out = reassembler.take_timed_out();
+        assert_eq!(timed_out.len(), 1);
+        assert_eq!(timed_out[0].fragment_offset, 0);
+        assert_eq!(timed_out[0].payload, fragments[0].payload);
+        assert_eq!(reassembler.memory_usage(), 0);
+    }
+}
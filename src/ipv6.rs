@@ -0,0 +1,553 @@
+//! IPv6 层实现
+//!
+//! IPv6 头部固定 40 字节，可选功能放在扩展头部中，
+//! 每个头部的 Next Header 指向下一个头部，最后一个指向上层协议（RFC 8200）
+
+use std::net::Ipv6Addr;
+
+use crate::arp::MacAddr;
+use crate::error::{Result, StackError};
+use crate::icmp::internet_checksum;
+
+const IPV6_HEADER_LEN: usize = 40;
+
+// Next Header 的取值
+pub const IPV6_NEXT_HOP_BY_HOP: u8 = 0; // 逐跳选项
+pub const IPV6_NEXT_TCP: u8 = 6;
+pub const IPV6_NEXT_UDP: u8 = 17;
+pub const IPV6_NEXT_ROUTING: u8 = 43; // 路由头部
+pub const IPV6_NEXT_FRAGMENT: u8 = 44; // 分片头部
+pub const IPV6_NEXT_ICMPV6: u8 = 58;
+pub const IPV6_NEXT_NONE: u8 = 59; // 没有下一个头部
+pub const IPV6_NEXT_DEST_OPTS: u8 = 60; // 目的选项
+
+/// 链路本地范围的所有节点和所有路由器组播地址
+pub const ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+pub const ALL_ROUTERS: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 2);
+
+// 逐跳选项和目的选项中的填充
+const IPV6_OPT_PAD1: u8 = 0;
+const IPV6_OPT_PADN: u8 = 1;
+const IPV6_OPT_ROUTER_ALERT: u8 = 5; // RFC 2711
+
+/// IPv6 数据包结构
+#[derive(Debug, Clone)]
+pub struct Ipv6Packet {
+    pub version: u8,         // 版本号（IPv6 是 6）
+    pub traffic_class: u8,   // 流量类别
+    pub flow_label: u32,     // 流标签（20 bit）
+    pub payload_length: u16, // 负载长度（包括扩展头部）
+    pub next_header: u8,     // 第一个扩展头部或上层协议
+    pub hop_limit: u8,       // 跳数限制
+    pub src_addr: Ipv6Addr,  // 源地址
+    pub dst_addr: Ipv6Addr,  // 目标地址
+    pub payload: Vec<u8>,    // 扩展头部和上层数据
+}
+
+/// 扩展头部
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtensionHeader {
+    HopByHop(Vec<Ipv6TlvOption>),
+    Routing {
+        routing_type: u8,
+        segments_left: u8,
+        data: Vec<u8>, // 类型相关的数据
+    },
+    Fragment {
+        offset: u16, // 以 8 字节为单位
+        more_fragments: bool,
+        identification: u32,
+    },
+    DestinationOptions(Vec<Ipv6TlvOption>),
+}
+
+/// 逐跳选项和目的选项中的一个 TLV 选项（不含填充）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ipv6TlvOption {
+    pub kind: u8,
+    pub data: Vec<u8>,
+}
+
+/// 扩展头部链的解析结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtensionChain {
+    pub headers: Vec<ExtensionHeader>,
+    pub protocol: u8,                      // 链末尾的上层协议
+    pub offset: usize,                     // 上层数据在负载中的偏移
+    pub pointer: usize, // 指向上层协议的 Next Header 字段在包中的位置，用于参数问题报文
+    pub problem: Option<ExtensionProblem>, // 按顺序处理头部时遇到的第一个问题
+}
+
+/// 接收方处理扩展头部时要求丢弃包的情况（RFC 8200 第 4 节），pointer 是差错报文指向的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtensionProblem {
+    Discard,                                // 不认识的选项要求静默丢弃
+    UnrecognizedOption { pointer: usize },  // 不认识的选项要求回复参数问题，指向选项类型
+    UnrecognizedRouting { pointer: usize }, // 剩余段数不为 0 的未知路由类型，指向路由类型
+    Fragmented,                             // 分片，不支持重组
+}
+
+impl Ipv6Packet {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < IPV6_HEADER_LEN {
+            return Err(StackError::InvalidPacket(String::from(
+                "Ipv6 packet too short",
+            )));
+        }
+        // 字节 0-3：版本(4bit) + 流量类别(8bit) + 流标签(20bit)
+        let first = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let version = (first >> 28) as u8;
+        if version != 6 {
+            return Err(StackError::InvalidPacket(format!(
+                "Ipv6 version {} is not 6",
+                version
+            )));
+        }
+        let traffic_class = (first >> 20) as u8;
+        let flow_label = first & 0x000F_FFFF;
+
+        // 字节 4-5：负载长度，超过的部分是以太网填充
+        let payload_length = u16::from_be_bytes([data[4], data[5]]);
+        let end = IPV6_HEADER_LEN + payload_length as usize;
+        if end > data.len() {
+            return Err(StackError::InvalidPacket(format!(
+                "Ipv6 payload length {} invalid for {} bytes",
+                payload_length,
+                data.len()
+            )));
+        }
+        let next_header = data[6];
+        let hop_limit = data[7];
+        let src_addr = Ipv6Addr::from(<[u8; 16]>::try_from(&data[8..24]).unwrap());
+        let dst_addr = Ipv6Addr::from(<[u8; 16]>::try_from(&data[24..40]).unwrap());
+        Ok(Self {
+            version,
+            traffic_class,
+            flow_label,
+            payload_length,
+            next_header,
+            hop_limit,
+            src_addr,
+            dst_addr,
+            payload: data[IPV6_HEADER_LEN..end].to_vec(),
+        })
+    }
+
+    pub fn build(
+        src_addr: Ipv6Addr,
+        dst_addr: Ipv6Addr,
+        next_header: u8,
+        hop_limit: u8,
+        payload: Vec<u8>,
+    ) -> Self {
+        Self {
+            version: 6,
+            traffic_class: 0,
+            flow_label: 0,
+            payload_length: payload.len() as u16,
+            next_header,
+            hop_limit,
+            src_addr,
+            dst_addr,
+            payload,
+        }
+    }
+
+    /// 构造带扩展头部的包，headers 按顺序串成链，最后指向 protocol
+    pub fn build_with_extensions(
+        src_addr: Ipv6Addr,
+        dst_addr: Ipv6Addr,
+        headers: &[ExtensionHeader],
+        protocol: u8,
+        hop_limit: u8,
+        data: &[u8],
+    ) -> Self {
+        let mut payload = Vec::new();
+        for (i, header) in headers.iter().enumerate() {
+            let next = headers.get(i + 1).map_or(protocol, ExtensionHeader::kind);
+            payload.extend_from_slice(&header.to_bytes(next));
+        }
+        payload.extend_from_slice(data);
+        let next_header = headers.first().map_or(protocol, ExtensionHeader::kind);
+        Self::build(src_addr, dst_addr, next_header, hop_limit, payload)
+    }
+
+    /// 序列化为字节数组，负载长度按实际负载填写
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(IPV6_HEADER_LEN + self.payload.len());
+        let first = ((self.version as u32) << 28)
+            | ((self.traffic_class as u32) << 20)
+            | (self.flow_label & 0x000F_FFFF);
+        bytes.extend_from_slice(&first.to_be_bytes());
+        bytes.extend_from_slice(&(self.payload.len() as u16).to_be_bytes());
+        bytes.push(self.next_header);
+        bytes.push(self.hop_limit);
+        bytes.extend_from_slice(&self.src_addr.octets());
+        bytes.extend_from_slice(&self.dst_addr.octets());
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    /// 沿着 Next Header 遍历扩展头部，遇到上层协议或 No Next Header 时停止。
+    /// 逐跳选项只能紧跟在 IPv6 头部之后
+    pub fn extensions(&self) -> Result<ExtensionChain> {
+        let mut headers = Vec::new();
+        let mut next = self.next_header;
+        let mut offset = 0;
+        let mut pointer = 6;
+        let mut problem = None;
+        loop {
+            let data = &self.payload[offset..];
+            let header = match next {
+                IPV6_NEXT_HOP_BY_HOP if offset != 0 => {
+                    return Err(StackError::InvalidPacket(String::from(
+                        "Ipv6 hop-by-hop options not first",
+                    )));
+                }
+                IPV6_NEXT_HOP_BY_HOP | IPV6_NEXT_DEST_OPTS | IPV6_NEXT_ROUTING => {
+                    // 长度以 8 字节为单位，不包括前 8 字节
+                    let len = data.get(1).map_or(0, |&len| (len as usize + 1) * 8);
+                    if data.len() < 8 || data.len() < len {
+                        return Err(StackError::InvalidPacket(format!(
+                            "Ipv6 extension header {} truncated",
+                            next
+                        )));
+                    }
+                    match next {
+                        IPV6_NEXT_ROUTING => ExtensionHeader::Routing {
+                            routing_type: data[2],
+                            segments_left: data[3],
+                            data: data[4..len].to_vec(),
+                        },
+                        IPV6_NEXT_HOP_BY_HOP => {
+                            ExtensionHeader::HopByHop(Ipv6TlvOption::parse_list(&data[2..len])?)
+                        }
+                        _ => ExtensionHeader::DestinationOptions(Ipv6TlvOption::parse_list(
+                            &data[2..len],
+                        )?),
+                    }
+                }
+                IPV6_NEXT_FRAGMENT => {
+                    if data.len() < 8 {
+                        return Err(StackError::InvalidPacket(String::from(
+                            "Ipv6 fragment header truncated",
+                        )));
+                    }
+                    let offset_flags = u16::from_be_bytes([data[2], data[3]]);
+                    ExtensionHeader::Fragment {
+                        offset: offset_flags >> 3,
+                        more_fragments: offset_flags & 1 != 0,
+                        identification: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+                    }
+                }
+                protocol => {
+                    return Ok(ExtensionChain {
+                        headers,
+                        protocol,
+                        offset,
+                        pointer,
+                        problem,
+                    });
+                }
+            };
+            pointer = IPV6_HEADER_LEN + offset;
+            if problem.is_none() {
+                problem = self.problem(&header, data, pointer);
+            }
+            offset += header.len(data);
+            next = data[0];
+            headers.push(header);
+        }
+    }
+
+    /// 接收方处理一个扩展头部（RFC 8200 第 4.2-4.5 节），start 是头部在包中的位置。
+    /// 选项类型的高两位决定不认识时的动作：00 跳过，01 丢弃，10 丢弃并回复参数问题，
+    /// 11 只在目标不是组播地址时回复
+    fn problem(
+        &self,
+        header: &ExtensionHeader,
+        data: &[u8],
+        start: usize,
+    ) -> Option<ExtensionProblem> {
+        match header {
+            ExtensionHeader::HopByHop(_) | ExtensionHeader::DestinationOptions(_) => {
+                let len = header.len(data);
+                let mut i = 2;
+                while i < len {
+                    let kind = data[i];
+                    if kind == IPV6_OPT_PAD1 {
+                        i += 1;
+                        continue;
+                    }
+                    if kind != IPV6_OPT_PADN && kind != IPV6_OPT_ROUTER_ALERT {
+                        match kind >> 6 {
+                            0 => {}
+                            1 => return Some(ExtensionProblem::Discard),
+                            3 if self.dst_addr.is_multicast() => {
+                                return Some(ExtensionProblem::Discard);
+                            }
+                            _ => {
+                                return Some(ExtensionProblem::UnrecognizedOption {
+                                    pointer: start + i,
+                                });
+                            }
+                        }
+                    }
+                    i += 2 + data[i + 1] as usize;
+                }
+                None
+            }
+            // 剩余段数为 0 时忽略路由头部，否则本机不支持任何路由类型
+            ExtensionHeader::Routing { segments_left, .. } => (*segments_left > 0)
+                .then_some(ExtensionProblem::UnrecognizedRouting { pointer: start + 2 }),
+            // 偏移为 0 且没有后续分片的原子分片按未分片处理（RFC 6946）
+            ExtensionHeader::Fragment {
+                offset,
+                more_fragments,
+                ..
+            } => (*offset != 0 || *more_fragments).then_some(ExtensionProblem::Fragmented),
+        }
+    }
+}
+
+impl ExtensionHeader {
+    /// 这个扩展头部对应的 Next Header 值
+    pub fn kind(&self) -> u8 {
+        match self {
+            ExtensionHeader::HopByHop(_) => IPV6_NEXT_HOP_BY_HOP,
+            ExtensionHeader::Routing { .. } => IPV6_NEXT_ROUTING,
+            ExtensionHeader::Fragment { .. } => IPV6_NEXT_FRAGMENT,
+            ExtensionHeader::DestinationOptions(_) => IPV6_NEXT_DEST_OPTS,
+        }
+    }
+
+    /// 在原始数据中占用的长度
+    fn len(&self, data: &[u8]) -> usize {
+        match self {
+            ExtensionHeader::Fragment { .. } => 8,
+            _ => (data[1] as usize + 1) * 8,
+        }
+    }
+
+    /// 序列化为字节数组，长度补齐到 8 字节的倍数
+    pub fn to_bytes(&self, next_header: u8) -> Vec<u8> {
+        let mut bytes = vec![next_header, 0];
+        match self {
+            ExtensionHeader::HopByHop(options) | ExtensionHeader::DestinationOptions(options) => {
+                for option in options {
+                    bytes.push(option.kind);
+                    bytes.push(option.data.len() as u8);
+                    bytes.extend_from_slice(&option.data);
+                }
+                // 用 Pad1 或 PadN 补齐
+                match bytes.len().next_multiple_of(8) - bytes.len() {
+                    0 => {}
+                    1 => bytes.push(IPV6_OPT_PAD1),
+                    pad => {
+                        bytes.push(IPV6_OPT_PADN);
+                        bytes.push((pad - 2) as u8);
+                        bytes.resize(bytes.len() + pad - 2, 0);
+                    }
+                }
+            }
+            ExtensionHeader::Routing {
+                routing_type,
+                segments_left,
+                data,
+            } => {
+                bytes.push(*routing_type);
+                bytes.push(*segments_left);
+                bytes.extend_from_slice(data);
+                bytes.resize(bytes.len().next_multiple_of(8), 0);
+            }
+            ExtensionHeader::Fragment {
+                offset,
+                more_fragments,
+                identification,
+            } => {
+                let offset_flags = (offset << 3) | *more_fragments as u16;
+                bytes.extend_from_slice(&offset_flags.to_be_bytes());
+                bytes.extend_from_slice(&identification.to_be_bytes());
+                return bytes;
+            }
+        }
+        bytes[1] = (bytes.len() / 8 - 1) as u8;
+        bytes
+    }
+}
+
+impl Ipv6TlvOption {
+    /// 解析选项列表，跳过 Pad1 和 PadN
+    pub fn parse_list(data: &[u8]) -> Result<Vec<Ipv6TlvOption>> {
+        let mut options = Vec::new();
+        let mut i = 0;
+        while i < data.len() {
+            if data[i] == IPV6_OPT_PAD1 {
+                i += 1;
+                continue;
+            }
+            let Some(&len) = data.get(i + 1) else {
+                return Err(StackError::InvalidPacket(String::from(
+                    "Ipv6 option truncated",
+                )));
+            };
+            let end = i + 2 + len as usize;
+            if end > data.len() {
+                return Err(StackError::InvalidPacket(String::from(
+                    "Ipv6 option truncated",
+                )));
+            }
+            if data[i] != IPV6_OPT_PADN {
+                options.push(Ipv6TlvOption {
+                    kind: data[i],
+                    data: data[i + 2..end].to_vec(),
+                });
+            }
+            i = end;
+        }
+        Ok(options)
+    }
+}
+
+/// 地址对应的被请求节点组播地址 ff02::1:ffXX:XXXX（RFC 4291 第 2.7.1 节）
+pub fn solicited_node(addr: Ipv6Addr) -> Ipv6Addr {
+    let octets = addr.octets();
+    Ipv6Addr::from([
+        0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0xff, octets[13], octets[14], octets[15],
+    ])
+}
+
+/// IPv6 组播地址对应的以太网地址：33:33 加上地址的低 32 位（RFC 2464 第 7 节）
+pub fn multicast_mac(addr: Ipv6Addr) -> MacAddr {
+    let octets = addr.octets();
+    [0x33, 0x33, octets[12], octets[13], octets[14], octets[15]]
+}
+
+/// 上层协议校验和，包括 IPv6 伪头部（RFC 8200 第 8.1 节）：
+/// 源地址、目标地址、上层数据长度和 Next Header。
+/// data 是上层头部和数据，校验和字段需要先置 0；接收方对整个数据计算结果为 0 表示正确
+pub fn pseudo_header_checksum(
+    src_addr: Ipv6Addr,
+    dst_addr: Ipv6Addr,
+    next_header: u8,
+    data: &[u8],
+) -> u16 {
+    let mut pseudo = Vec::with_capacity(40 + data.len());
+    pseudo.extend_from_slice(&src_addr.octets());
+    pseudo.extend_from_slice(&dst_addr.octets());
+    pseudo.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    pseudo.extend_from_slice(&[0, 0, 0, next_header]);
+    pseudo.extend_from_slice(data);
+    internet_checksum(&pseudo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::udp::UdpDatagram;
+
+    #[test]
+    fn test_extension_chain() {
+        let src: Ipv6Addr = "fe80::1".parse().unwrap();
+        let dst: Ipv6Addr = "2001:db8::2".parse().unwrap();
+        let headers = vec![
+            ExtensionHeader::HopByHop(vec![Ipv6TlvOption {
+                kind: 5, // Router Alert
+                data: vec![0, 0],
+            }]),
+            ExtensionHeader::Routing {
+                routing_type: 4,
+                segments_left: 0,
+                data: vec![0; 4],
+            },
+            ExtensionHeader::Fragment {
+                offset: 3,
+                more_fragments: true,
+                identification: 0x1234_5678,
+            },
+            ExtensionHeader::DestinationOptions(Vec::new()),
+        ];
+        let packet =
+            Ipv6Packet::build_with_extensions(src, dst, &headers, IPV6_NEXT_UDP, 64, b"data");
+        let mut bytes = packet.to_bytes();
+        bytes.extend_from_slice(&[0; 4]); // 以太网填充
+        let parsed = Ipv6Packet::parse(&bytes).unwrap();
+        assert_eq!((parsed.src_addr, parsed.dst_addr), (src, dst));
+        assert_eq!(parsed.next_header, IPV6_NEXT_HOP_BY_HOP);
+        let chain = parsed.extensions().unwrap();
+        assert_eq!(chain.headers, headers);
+        assert_eq!(chain.protocol, IPV6_NEXT_UDP);
+        assert_eq!(&parsed.payload[chain.offset..], b"data");
+        assert_eq!(chain.pointer, 40 + chain.offset - 8);
+        // 剩余段数为 0 的路由头部被忽略，非首片要丢弃
+        assert_eq!(chain.problem, Some(ExtensionProblem::Fragmented));
+
+        // 不认识的选项按类型的高两位处理
+        let option_problem = |kind: u8, dst: Ipv6Addr| {
+            let options = vec![Ipv6TlvOption {
+                kind,
+                data: vec![0; 2],
+            }];
+            let headers = [
+                ExtensionHeader::HopByHop(Vec::new()),
+                ExtensionHeader::DestinationOptions(options),
+            ];
+            Ipv6Packet::build_with_extensions(src, dst, &headers, IPV6_NEXT_NONE, 64, &[])
+                .extensions()
+                .unwrap()
+                .problem
+        };
+        assert_eq!(option_problem(0x1e, dst), None);
+        assert_eq!(option_problem(0x5e, dst), Some(ExtensionProblem::Discard));
+        let pointer = 40 + 8 + 2;
+        let reply = Some(ExtensionProblem::UnrecognizedOption { pointer });
+        assert_eq!(option_problem(0x9e, dst), reply);
+        assert_eq!(option_problem(0x9e, ALL_NODES), reply);
+        assert_eq!(option_problem(0xde, dst), reply);
+        assert_eq!(
+            option_problem(0xde, ALL_NODES),
+            Some(ExtensionProblem::Discard)
+        );
+
+        // 还有剩余段数的路由头部，差错指向路由类型
+        let routing = ExtensionHeader::Routing {
+            routing_type: 4,
+            segments_left: 1,
+            data: vec![0; 4],
+        };
+        let packet =
+            Ipv6Packet::build_with_extensions(src, dst, &[routing], IPV6_NEXT_NONE, 64, &[]);
+        assert_eq!(
+            packet.extensions().unwrap().problem,
+            Some(ExtensionProblem::UnrecognizedRouting { pointer: 42 })
+        );
+
+        // 逐跳选项不在第一个位置
+        let misplaced = Ipv6Packet::build_with_extensions(
+            src,
+            dst,
+            &[headers[3].clone(), headers[0].clone()],
+            IPV6_NEXT_NONE,
+            64,
+            &[],
+        );
+        assert!(misplaced.extensions().is_err());
+    }
+
+    #[test]
+    fn test_pseudo_header_checksum() {
+        let src: Ipv6Addr = "fe80::1".parse().unwrap();
+        let dst: Ipv6Addr = "fe80::2".parse().unwrap();
+        // ICMPv6 Echo Request，校验和占位
+        let mut icmp = vec![128, 0, 0, 0, 0, 1, 0, 1, b'h', b'i', b'!'];
+        let checksum = pseudo_header_checksum(src, dst, IPV6_NEXT_ICMPV6, &icmp);
+        icmp[2..4].copy_from_slice(&checksum.to_be_bytes());
+        assert_eq!(pseudo_header_checksum(src, dst, IPV6_NEXT_ICMPV6, &icmp), 0);
+        assert_ne!(pseudo_header_checksum(dst, src, IPV6_NEXT_UDP, &icmp), 0);
+
+        let datagram = UdpDatagram::build_ipv6(5353, 5353, b"hi".to_vec(), src, dst);
+        let parsed = UdpDatagram::parse(&datagram.to_bytes()).unwrap();
+        assert!(parsed.verify_checksum_ipv6(src, dst).is_ok());
+        assert!(parsed.verify_checksum_ipv6(src, src).is_err());
+    }
+}
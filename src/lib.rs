@@ -2,8 +2,15 @@ pub mod error;
 pub mod ethernet;
 pub mod arp;
 pub mod icmp;
+pub mod icmpv6;
 pub mod ip;
+pub mod ipv6;
+pub mod ndp;
+pub mod rarp;
+pub mod route;
 pub mod socket;
+pub mod stack;
+pub mod tcp;
 pub mod udp;
 pub mod device;
 
@@ -0,0 +1,908 @@
+//! 邻居发现协议实现（RFC 4861）
+//!
+//! NDP 在 IPv6 中承担 ARP 的工作：用邻居请求/通告解析链路层地址，
+//! 另外还负责发现路由器和链路上的前缀，以及处理路由器发来的重定向。
+//! 接口地址由 `slaac` 子模块自动配置并做重复地址检测
+use tracing::{debug, info, warn};
+
+pub mod slaac;
+
+use slaac::{
+    AddressGeneration, AddressOrigin, AddressState, IDGEN_RETRIES, Ipv6Address, LINK_LOCAL_PREFIX,
+    interface_id, with_interface_id,
+};
+
+use crate::arp::{MacAddr, NeighborCache, NeighborState};
+use crate::icmpv6::{NDP_HOP_LIMIT, NdpMessage, PrefixInfo};
+use crate::ipv6::{ALL_NODES, ALL_ROUTERS, Ipv6Packet, multicast_mac, solicited_node};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::net::Ipv6Addr;
+use std::time::{Duration, Instant};
+
+/// 邻居可达性确认后保持 REACHABLE 的时间（REACHABLE_TIME）
+const REACHABLE_TIME: Duration = Duration::from_secs(30);
+
+/// 两次路由器请求之间的间隔（RTR_SOLICITATION_INTERVAL）
+const RTR_SOLICITATION_INTERVAL: Duration = Duration::from_secs(4);
+
+/// 最多发送的路由器请求次数（MAX_RTR_SOLICITATIONS）
+const MAX_RTR_SOLICITATIONS: u32 = 3;
+
+/// 路由器通告没有指定时使用的跳数限制
+const DEFAULT_HOP_LIMIT: u8 = 64;
+
+/// IPv6 要求链路 MTU 至少是 1280
+const IPV6_MIN_MTU: u32 = 1280;
+
+/// 加入组播组后发送第一个重复地址检测请求前的随机等待上限（MAX_RTR_SOLICITATION_DELAY）
+const DAD_DELAY: Duration = Duration::from_secs(1);
+
+/// 路由器通告中学到的链路上的前缀
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OnLinkPrefix {
+    pub prefix: Ipv6Addr,
+    pub prefix_len: u8,
+    pub expires: Option<Instant>, // None 表示永不过期
+}
+
+/// 默认路由器列表中的路由器
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DefaultRouter {
+    pub addr: Ipv6Addr, // 路由器的链路本地地址
+    pub expires: Instant,
+}
+
+#[derive(Debug)]
+pub struct NdpModule {
+    cache: NeighborCache<Ipv6Addr>,
+    our_mac: MacAddr,
+    addresses: Vec<Ipv6Address>,
+    generation: AddressGeneration, // 自动配置地址的接口标识生成方式
+    random: RandomState,
+    routers: Vec<DefaultRouter>,
+    prefixes: Vec<OnLinkPrefix>,
+    redirects: HashMap<Ipv6Addr, Ipv6Addr>, // 目标地址 -> 重定向后的下一跳
+    mtu: Option<u32>,                       // 路由器通告的链路 MTU
+    hop_limit: u8,
+    router_solicits: u32, // 已发送的路由器请求次数
+    next_router_solicit: Option<Instant>,
+    outgoing: Vec<(MacAddr, Ipv6Packet)>, // 待发送的 NDP 报文及目标 MAC
+    resolved: Vec<(MacAddr, Vec<u8>)>,    // 解析完成、可以发送的包
+    unreachable: Vec<Vec<u8>>,            // 解析失败而丢弃的包
+}
+
+impl NdpModule {
+    pub fn new(our_mac: MacAddr) -> Self {
+        Self {
+            cache: NeighborCache::new(REACHABLE_TIME),
+            our_mac,
+            addresses: Vec::new(),
+            generation: AddressGeneration::default(),
+            random: RandomState::new(),
+            routers: Vec::new(),
+            prefixes: Vec::new(),
+            redirects: HashMap::new(),
+            mtu: None,
+            hop_limit: DEFAULT_HOP_LIMIT,
+            router_solicits: 0,
+            next_router_solicit: None,
+            outgoing: Vec::new(),
+            resolved: Vec::new(),
+            unreachable: Vec::new(),
+        }
+    }
+
+    /// 手动给接口添加地址，地址直接可用，同时加入对应的被请求节点组播组
+    pub fn add_address(&mut self, addr: Ipv6Addr) {
+        if self.address(addr).is_none() {
+            info!("Add IPv6 address {}", addr);
+            self.addresses
+                .push(Ipv6Address::manual(addr, 64, Instant::now()));
+        }
+    }
+
+    pub fn remove_address(&mut self, addr: Ipv6Addr) -> bool {
+        let len = self.addresses.len();
+        self.addresses.retain(|a| a.addr != addr);
+        self.addresses.len() != len
+    }
+
+    /// 接口上的所有地址，包括还在检测和检测失败的地址
+    pub fn addresses(&self) -> &[Ipv6Address] {
+        &self.addresses
+    }
+
+    pub fn address(&self, addr: Ipv6Addr) -> Option<&Ipv6Address> {
+        self.addresses.iter().find(|a| a.addr == addr)
+    }
+
+    /// 是否是可以使用的本机地址，TENTATIVE 地址不接收发给它的包
+    pub fn has_address(&self, addr: Ipv6Addr) -> bool {
+        self.address(addr).is_some_and(|a| a.is_usable())
+    }
+
+    /// 设置自动配置地址的接口标识生成方式，只影响之后生成的地址
+    pub fn set_address_generation(&mut self, generation: AddressGeneration) {
+        self.generation = generation;
+    }
+
+    /// 开始无状态地址自动配置：由 MAC 生成链路本地地址并做重复地址检测，
+    /// 之后收到带自治标志的前缀时生成全局地址
+    pub fn autoconfigure(&mut self, now: Instant) {
+        let exists = self
+            .addresses
+            .iter()
+            .any(|a| a.origin == AddressOrigin::LinkLocal);
+        if !exists {
+            self.start_address(LINK_LOCAL_PREFIX, AddressOrigin::LinkLocal, 0, now);
+        }
+    }
+
+    /// 由前缀生成地址并开始重复地址检测，等待随机时间后发送第一个请求
+    fn start_address(
+        &mut self,
+        prefix: Ipv6Addr,
+        origin: AddressOrigin,
+        dad_counter: u8,
+        now: Instant,
+    ) -> Ipv6Addr {
+        let id = interface_id(self.generation, prefix, self.our_mac, dad_counter);
+        let addr = with_interface_id(prefix, id);
+        let random = self.random.hash_one((now, addr)) % 1000;
+        let start = now + DAD_DELAY * random as u32 / 1000;
+        let mut address = Ipv6Address::tentative(addr, 64, origin, start);
+        address.dad_counter = dad_counter;
+        info!("Add tentative IPv6 address {}", addr);
+        self.addresses.retain(|a| a.addr != addr);
+        self.addresses.push(address);
+        addr
+    }
+
+    /// 重复地址检测失败（RFC 4862 第 5.4.5 节）：EUI-64 地址需要手动处理，
+    /// 稳定隐私地址换一个重复次数重新生成
+    fn duplicate_detected(&mut self, addr: Ipv6Addr, now: Instant) {
+        let Some(address) = self.addresses.iter_mut().find(|a| a.addr == addr) else {
+            return;
+        };
+        warn!("Duplicate address {} detected", addr);
+        address.state = AddressState::Duplicated;
+        let (origin, dad_counter) = (address.origin, address.dad_counter);
+        if origin == AddressOrigin::Manual
+            || !matches!(self.generation, AddressGeneration::StablePrivacy(_))
+        {
+            return;
+        }
+        if dad_counter >= IDGEN_RETRIES {
+            warn!("Give up generating address for prefix of {}", addr);
+            return;
+        }
+        let prefix = mask(addr, 64);
+        let valid_until = address.valid_until;
+        let preferred_until = address.preferred_until;
+        self.addresses.retain(|a| a.addr != addr);
+        let addr = self.start_address(prefix, origin, dad_counter + 1, now);
+        if let Some(address) = self.addresses.iter_mut().find(|a| a.addr == addr) {
+            address.valid_until = valid_until;
+            address.preferred_until = preferred_until;
+        }
+    }
+
+    /// 是否加入了这个组播组：所有节点组和每个地址的被请求节点组，
+    /// 重复地址检测开始前就要加入
+    pub fn is_member(&self, group: Ipv6Addr) -> bool {
+        group == ALL_NODES || self.groups().any(|g| g == group)
+    }
+
+    /// 是否接收发往这个 MAC 的帧：本机 MAC 和已加入组播组对应的 33:33 地址
+    pub fn accepts_mac(&self, mac: MacAddr) -> bool {
+        mac == self.our_mac
+            || mac == multicast_mac(ALL_NODES)
+            || self.groups().any(|group| multicast_mac(group) == mac)
+    }
+
+    fn groups(&self) -> impl Iterator<Item = Ipv6Addr> + '_ {
+        self.addresses
+            .iter()
+            .filter(|a| a.state != AddressState::Duplicated)
+            .map(|a| solicited_node(a.addr))
+    }
+
+    /// 发往 dst 的包使用的源地址：只用可以使用的地址，链路本地目标用链路本地地址，
+    /// 其余优先用全局地址，PREFERRED 优先于 DEPRECATED
+    pub fn source_address(&self, dst: Ipv6Addr) -> Option<Ipv6Addr> {
+        let link_local = is_link_local(dst) || dst.is_multicast();
+        self.addresses
+            .iter()
+            .filter(|a| a.is_usable())
+            .min_by_key(|a| {
+                (
+                    is_link_local(a.addr) != link_local,
+                    a.state != AddressState::Preferred,
+                )
+            })
+            .map(|a| a.addr)
+    }
+
+    /// 本机发出的包使用的跳数限制，可以由路由器通告修改
+    pub fn hop_limit(&self) -> u8 {
+        self.hop_limit
+    }
+
+    /// 路由器通告的链路 MTU
+    pub fn mtu(&self) -> Option<u32> {
+        self.mtu
+    }
+
+    pub fn routers(&self) -> &[DefaultRouter] {
+        &self.routers
+    }
+
+    pub fn prefixes(&self) -> &[OnLinkPrefix] {
+        &self.prefixes
+    }
+
+    /// 地址是否在链路上：链路本地地址或路由器通告的前缀内
+    pub fn is_on_link(&self, addr: Ipv6Addr) -> bool {
+        is_link_local(addr)
+            || self
+                .prefixes
+                .iter()
+                .any(|prefix| prefix_matches(addr, prefix.prefix, prefix.prefix_len))
+    }
+
+    /// 下一跳（RFC 4861 第 5.2 节）：组播和链路上的地址直接发送，
+    /// 被重定向的目标发给重定向指定的下一跳，其余发给默认路由器
+    pub fn next_hop(&self, dst: Ipv6Addr) -> Option<Ipv6Addr> {
+        if dst.is_multicast() {
+            return Some(dst);
+        }
+        if let Some(next_hop) = self.redirects.get(&dst) {
+            return Some(*next_hop);
+        }
+        if self.is_on_link(dst) {
+            return Some(dst);
+        }
+        self.routers.first().map(|router| router.addr)
+    }
+
+    /// 查找下一跳的 MAC，组播地址直接映射
+    pub fn resolve(&mut self, next_hop: Ipv6Addr, now: Instant) -> Option<MacAddr> {
+        if next_hop.is_multicast() {
+            return Some(multicast_mac(next_hop));
+        }
+        self.cache.loopup(&next_hop, now)
+    }
+
+    /// 上层确认邻居可达
+    pub fn confirm(&mut self, addr: Ipv6Addr, now: Instant) {
+        self.cache.confirm(&addr, now);
+    }
+
+    pub fn neighbor_state(&self, addr: Ipv6Addr) -> Option<NeighborState> {
+        if self.cache.is_pending(&addr) {
+            return Some(NeighborState::Incomplete);
+        }
+        self.cache.state(&addr)
+    }
+
+    pub fn cache(&self) -> &NeighborCache<Ipv6Addr> {
+        &self.cache
+    }
+
+    pub fn cache_mut(&mut self) -> &mut NeighborCache<Ipv6Addr> {
+        &mut self.cache
+    }
+
+    /// 开始发现路由器：立即发送路由器请求，没有收到通告时在 poll 中重发
+    pub fn solicit_routers(&mut self, now: Instant) {
+        self.router_solicits = 0;
+        self.next_router_solicit = Some(now);
+        self.poll_router_solicit(now);
+    }
+
+    /// 处理收到的 NDP 报文，跳数限制不是 255 的报文可能来自链路外，直接丢弃
+    pub fn handle_message(&mut self, packet: &Ipv6Packet, message: &NdpMessage, now: Instant) {
+        if packet.hop_limit != NDP_HOP_LIMIT {
+            debug!("NDP message with hop limit {}, skip", packet.hop_limit);
+            return;
+        }
+        match message {
+            NdpMessage::NeighborSolicitation {
+                target,
+                source_lladdr,
+            } => self.handle_solicitation(packet, *target, *source_lladdr, now),
+            NdpMessage::NeighborAdvertisement {
+                solicited,
+                overrides,
+                target,
+                target_lladdr,
+                ..
+            } => self.handle_advertisement(*target, *target_lladdr, *solicited, *overrides, now),
+            NdpMessage::RouterAdvertisement { .. } => {
+                self.handle_router_advertisement(packet, message, now)
+            }
+            NdpMessage::Redirect {
+                target,
+                destination,
+                target_lladdr,
+            } => {
+                // 只接受当前第一跳路由器发来的重定向（RFC 4861 第 8.1 节）
+                if !is_link_local(packet.src_addr)
+                    || self.next_hop(*destination) != Some(packet.src_addr)
+                    || (target != destination && !is_link_local(*target))
+                {
+                    debug!("Invalid redirect from {}, skip", packet.src_addr);
+                    return;
+                }
+                info!("Redirect {} to {}", destination, target);
+                self.redirects.insert(*destination, *target);
+                if let Some(mac) = target_lladdr {
+                    self.cache.learn(*target, *mac, now);
+                }
+            }
+            // 主机不处理路由器请求
+            NdpMessage::RouterSolicitation { .. } => {}
+        }
+    }
+
+    /// 回答请求本机地址的邻居请求，并从中学习请求方的 MAC
+    fn handle_solicitation(
+        &mut self,
+        packet: &Ipv6Packet,
+        target: Ipv6Addr,
+        source_lladdr: Option<MacAddr>,
+        now: Instant,
+    ) {
+        let src = packet.src_addr;
+        // 其他节点在对我们正在检测的地址做重复地址检测（RFC 4862 第 5.4.3 节）
+        if self
+            .address(target)
+            .is_some_and(|a| a.state == AddressState::Tentative)
+        {
+            if src.is_unspecified() {
+                self.duplicate_detected(target, now);
+            }
+            return;
+        }
+        if !self.has_address(target) {
+            debug!("Neighbor solicitation for {} is not for us", target);
+            return;
+        }
+        // 源地址未指定的是其他节点的重复地址检测，回复发给所有节点
+        let (dst, dst_mac) = if src.is_unspecified() {
+            (ALL_NODES, Some(multicast_mac(ALL_NODES)))
+        } else {
+            if let Some(mac) = source_lladdr {
+                self.cache.learn(src, mac, now);
+            }
+            (src, self.cache.mac(&src))
+        };
+        let Some(dst_mac) = dst_mac else {
+            debug!("Unknown link-layer address of {}, skip", src);
+            return;
+        };
+        let advertisement = NdpMessage::NeighborAdvertisement {
+            router: false,
+            solicited: !src.is_unspecified(),
+            overrides: true,
+            target,
+            target_lladdr: Some(self.our_mac),
+        };
+        self.outgoing
+            .push((dst_mac, advertisement.to_packet(target, dst)));
+        debug!("Send neighbor advertisement for {} to {}", target, dst);
+    }
+
+    /// 处理邻居通告（RFC 4861 第 7.2.5 节），解析完成时发送排队的包
+    fn handle_advertisement(
+        &mut self,
+        target: Ipv6Addr,
+        target_lladdr: Option<MacAddr>,
+        solicited: bool,
+        overrides: bool,
+        now: Instant,
+    ) {
+        if let Some(address) = self.address(target) {
+            if address.state == AddressState::Tentative {
+                self.duplicate_detected(target, now);
+            } else {
+                warn!("Address {} is used by {:02x?}", target, target_lladdr);
+            }
+            return;
+        }
+        if self.cache.is_pending(&target) {
+            let Some(mac) = target_lladdr else {
+                return;
+            };
+            let packets = self.cache.take_pending(&target).unwrap_or_default();
+            info!(
+                "NDP resolved {}, flush {} queued packets",
+                target,
+                packets.len()
+            );
+            self.cache.insert(target, mac, now);
+            self.resolved
+                .extend(packets.into_iter().map(|packet| (mac, packet)));
+            return;
+        }
+        let Some(old_mac) = self.cache.mac(&target) else {
+            return;
+        };
+        if self.cache.state(&target) == Some(NeighborState::Permanent) {
+            return;
+        }
+        let mac = target_lladdr.unwrap_or(old_mac);
+        if mac != old_mac && !overrides {
+            return;
+        }
+        if solicited {
+            self.cache.insert(target, mac, now);
+        } else if mac != old_mac {
+            self.cache.learn(target, mac, now);
+        }
+    }
+
+    /// 处理路由器通告（RFC 4861 第 6.3.4 节）：更新默认路由器、链路参数和前缀
+    fn handle_router_advertisement(
+        &mut self,
+        packet: &Ipv6Packet,
+        message: &NdpMessage,
+        now: Instant,
+    ) {
+        let NdpMessage::RouterAdvertisement {
+            cur_hop_limit,
+            router_lifetime,
+            source_lladdr,
+            mtu,
+            prefixes,
+            ..
+        } = message
+        else {
+            return;
+        };
+        let router = packet.src_addr;
+        if !is_link_local(router) {
+            debug!(
+                "Router advertisement from {} is not link-local, skip",
+                router
+            );
+            return;
+        }
+        self.next_router_solicit = None;
+        self.routers.retain(|r| r.addr != router);
+        if *router_lifetime > 0 {
+            info!("Default router {}, lifetime {}s", router, router_lifetime);
+            self.routers.push(DefaultRouter {
+                addr: router,
+                expires: now + Duration::from_secs(*router_lifetime as u64),
+            });
+        }
+        if *cur_hop_limit != 0 {
+            self.hop_limit = *cur_hop_limit;
+        }
+        if let Some(mtu) = mtu
+            && *mtu >= IPV6_MIN_MTU
+        {
+            self.mtu = Some(*mtu);
+        }
+        if let Some(mac) = source_lladdr {
+            self.cache.learn(router, *mac, now);
+        }
+        for info in prefixes.iter().filter(|info| info.on_link) {
+            if is_link_local(info.prefix) {
+                continue;
+            }
+            let prefix = mask(info.prefix, info.prefix_len);
+            self.prefixes
+                .retain(|p| p.prefix != prefix || p.prefix_len != info.prefix_len);
+            if info.valid_lifetime == 0 {
+                continue;
+            }
+            let expires = (info.valid_lifetime != u32::MAX)
+                .then(|| now + Duration::from_secs(info.valid_lifetime as u64));
+            self.prefixes.push(OnLinkPrefix {
+                prefix,
+                prefix_len: info.prefix_len,
+                expires,
+            });
+        }
+        for info in prefixes.iter().filter(|info| info.autonomous) {
+            self.autoconfigure_prefix(info, now);
+        }
+    }
+
+    /// 处理带自治标志的前缀（RFC 4862 第 5.5.3 节）：新前缀生成地址并做重复地址检测，
+    /// 已有地址更新生存期
+    fn autoconfigure_prefix(&mut self, info: &PrefixInfo, now: Instant) {
+        if is_link_local(info.prefix) || info.preferred_lifetime > info.valid_lifetime {
+            return;
+        }
+        // 以太网上的接口标识是 64 位
+        if info.prefix_len != 64 {
+            debug!(
+                "Ignore autonomous prefix {}/{}",
+                info.prefix, info.prefix_len
+            );
+            return;
+        }
+        let prefix = mask(info.prefix, 64);
+        // 重复的地址也算已经配置过：放弃这个前缀直到手动处理（RFC 4862 第 5.4.5 节），
+        // 刷新它的生存期让这个状态在前缀还被通告时保留下来
+        let mut found = false;
+        for address in self
+            .addresses
+            .iter_mut()
+            .filter(|a| a.origin == AddressOrigin::Autoconf && mask(a.addr, 64) == prefix)
+        {
+            address.update_lifetimes(info.valid_lifetime, info.preferred_lifetime, now);
+            found = true;
+        }
+        if found || info.valid_lifetime == 0 {
+            return;
+        }
+        let addr = self.start_address(prefix, AddressOrigin::Autoconf, 0, now);
+        if let Some(address) = self.addresses.iter_mut().find(|a| a.addr == addr) {
+            address.set_lifetimes(info.valid_lifetime, info.preferred_lifetime, now);
+        }
+    }
+
+    /// 缓存里没有下一跳地址时，把包放进等待队列；
+    /// 第一次遇到这个地址时立即向被请求节点组播地址发送邻居请求
+    pub fn queue_packet(&mut self, next_hop: Ipv6Addr, packet: Vec<u8>, now: Instant) {
+        if self.cache.queue(next_hop, packet, now) && self.cache.start_resolution(&next_hop, now) {
+            self.solicit(next_hop, None);
+        }
+    }
+
+    /// 发送邻居请求，mac 为 None 时发给被请求节点组播地址，否则是单播探测
+    fn solicit(&mut self, target: Ipv6Addr, mac: Option<MacAddr>) {
+        let Some(src) = self.source_address(target) else {
+            debug!("No IPv6 address to solicit {}", target);
+            return;
+        };
+        let (dst, dst_mac) = match mac {
+            Some(mac) => (target, mac),
+            None => {
+                let group = solicited_node(target);
+                (group, multicast_mac(group))
+            }
+        };
+        let solicitation = NdpMessage::NeighborSolicitation {
+            target,
+            source_lladdr: Some(self.our_mac),
+        };
+        self.outgoing
+            .push((dst_mac, solicitation.to_packet(src, dst)));
+    }
+
+    /// 处理定时器：重发邻居请求和路由器请求，推进邻居状态机和重复地址检测，
+    /// 删除过期的路由器、前缀和地址
+    pub fn poll(&mut self, now: Instant) {
+        for (addr, mac) in self.cache.poll(now) {
+            self.solicit(addr, Some(mac));
+        }
+        let (retries, failed) = self.cache.poll_pending(now);
+        for addr in retries {
+            self.solicit(addr, None);
+        }
+        self.unreachable.extend(failed);
+
+        let routers = self.routers.len();
+        self.routers.retain(|router| now < router.expires);
+        if self.routers.len() != routers {
+            // 路由器失效后重定向的结果不再可信，全部作废
+            self.redirects.clear();
+        }
+        self.prefixes
+            .retain(|prefix| prefix.expires.is_none_or(|expires| now < expires));
+        for address in &mut self.addresses {
+            if let Some(solicitation) = address.poll(now) {
+                self.outgoing.push(solicitation);
+            }
+        }
+        self.addresses.retain(|address| {
+            let expired = address.is_expired(now);
+            if expired {
+                info!("IPv6 address {} expired", address.addr);
+            }
+            !expired
+        });
+        self.poll_router_solicit(now);
+    }
+
+    fn poll_router_solicit(&mut self, now: Instant) {
+        let Some(next) = self.next_router_solicit else {
+            return;
+        };
+        if now < next {
+            return;
+        }
+        if self.router_solicits >= MAX_RTR_SOLICITATIONS {
+            debug!("No router advertisement received");
+            self.next_router_solicit = None;
+            return;
+        }
+        self.router_solicits += 1;
+        self.next_router_solicit = Some(now + RTR_SOLICITATION_INTERVAL);
+        // 还没有地址时用未指定地址发送，不能带源链路层地址选项
+        let src = self.source_address(ALL_ROUTERS);
+        let solicitation = NdpMessage::RouterSolicitation {
+            source_lladdr: src.map(|_| self.our_mac),
+        };
+        let packet = solicitation.to_packet(src.unwrap_or(Ipv6Addr::UNSPECIFIED), ALL_ROUTERS);
+        self.outgoing.push((multicast_mac(ALL_ROUTERS), packet));
+    }
+
+    /// 取出待发送的 NDP 报文及目标 MAC
+    pub fn take_outgoing(&mut self) -> Vec<(MacAddr, Ipv6Packet)> {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    /// 取出已解析出下一跳 MAC 的包
+    pub fn take_resolved(&mut self) -> Vec<(MacAddr, Vec<u8>)> {
+        std::mem::take(&mut self.resolved)
+    }
+
+    /// 取出解析失败而丢弃的包，用于回复地址不可达
+    pub fn take_unreachable(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.unreachable)
+    }
+}
+
+/// 链路本地单播地址 fe80::/10
+fn is_link_local(addr: Ipv6Addr) -> bool {
+    addr.segments()[0] & 0xffc0 == 0xfe80
+}
+
+fn prefix_mask(prefix_len: u8) -> u128 {
+    match prefix_len {
+        0 => 0,
+        len => u128::MAX << (128 - len.min(128) as u32),
+    }
+}
+
+fn mask(addr: Ipv6Addr, prefix_len: u8) -> Ipv6Addr {
+    Ipv6Addr::from(u128::from(addr) & prefix_mask(prefix_len))
+}
+
+fn prefix_matches(addr: Ipv6Addr, prefix: Ipv6Addr, prefix_len: u8) -> bool {
+    mask(addr, prefix_len) == mask(prefix, prefix_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUR_MAC: MacAddr = [0x42; 6];
+    const ROUTER_MAC: MacAddr = [0x02, 0, 0, 0, 0, 1];
+
+    fn addr(s: &str) -> Ipv6Addr {
+        s.parse().unwrap()
+    }
+
+    fn receive(
+        ndp: &mut NdpModule,
+        src: Ipv6Addr,
+        dst: Ipv6Addr,
+        message: NdpMessage,
+        now: Instant,
+    ) {
+        let packet = message.to_packet(src, dst);
+        ndp.handle_message(&packet, &message, now);
+    }
+
+    #[test]
+    fn test_resolve_and_router_discovery() {
+        let now = Instant::now();
+        let mut ndp = NdpModule::new(OUR_MAC);
+        ndp.add_address(addr("fe80::42"));
+        assert!(ndp.accepts_mac([0x33, 0x33, 0xff, 0, 0, 0x42]));
+        assert!(ndp.is_member(addr("ff02::1:ff00:42")));
+
+        // 没有路由器时链路外的地址不可达
+        assert_eq!(ndp.next_hop(addr("2001:db8:1::1")), None);
+        ndp.solicit_routers(now);
+        let (mac, rs) = ndp.take_outgoing().pop().unwrap();
+        assert_eq!(mac, [0x33, 0x33, 0, 0, 0, 2]);
+        assert_eq!(rs.dst_addr, ALL_ROUTERS);
+
+        let router = addr("fe80::1");
+        let advertisement = NdpMessage::RouterAdvertisement {
+            cur_hop_limit: 32,
+            managed: false,
+            other: false,
+            router_lifetime: 600,
+            reachable_time: 0,
+            retrans_timer: 0,
+            source_lladdr: Some(ROUTER_MAC),
+            mtu: Some(1400),
+            prefixes: vec![PrefixInfo {
+                prefix: addr("2001:db8::"),
+                prefix_len: 64,
+                on_link: true,
+                autonomous: true,
+                valid_lifetime: 3600,
+                preferred_lifetime: 1800,
+            }],
+        };
+        receive(&mut ndp, router, ALL_NODES, advertisement, now);
+        assert_eq!(ndp.hop_limit(), 32);
+        assert_eq!(ndp.mtu(), Some(1400));
+        assert_eq!(ndp.next_hop(addr("2001:db8:1::1")), Some(router));
+        assert_eq!(ndp.next_hop(addr("2001:db8::7")), Some(addr("2001:db8::7")));
+        assert_eq!(ndp.resolve(router, now), Some(ROUTER_MAC));
+
+        // 在链路上的邻居需要先解析
+        let neighbor = addr("2001:db8::7");
+        ndp.add_address(addr("2001:db8::42"));
+        ndp.queue_packet(neighbor, vec![1, 2, 3], now);
+        let (mac, ns) = ndp.take_outgoing().pop().unwrap();
+        assert_eq!(mac, [0x33, 0x33, 0xff, 0, 0, 0x07]);
+        assert_eq!(ns.src_addr, addr("2001:db8::42"));
+        let advertisement = NdpMessage::NeighborAdvertisement {
+            router: false,
+            solicited: true,
+            overrides: true,
+            target: neighbor,
+            target_lladdr: Some([0x02, 0, 0, 0, 0, 7]),
+        };
+        receive(&mut ndp, neighbor, addr("2001:db8::42"), advertisement, now);
+        assert_eq!(
+            ndp.take_resolved(),
+            vec![([0x02, 0, 0, 0, 0, 7], vec![1, 2, 3])]
+        );
+        assert_eq!(ndp.neighbor_state(neighbor), Some(NeighborState::Reachable));
+
+        // 正在解析的地址数量和邻居缓存使用同一个上限
+        ndp.cache_mut().set_max_entries(2);
+        ndp.queue_packet(addr("2001:db8::8"), vec![4], now);
+        ndp.queue_packet(addr("2001:db8::9"), vec![5], now);
+        ndp.queue_packet(addr("2001:db8::a"), vec![6], now);
+        assert_eq!(ndp.take_outgoing().len(), 2);
+        assert_eq!(ndp.neighbor_state(addr("2001:db8::a")), None);
+        ndp.cache_mut().flush();
+
+        // 第一跳路由器把目标重定向到链路上的另一台路由器
+        let destination = addr("2001:db8:1::1");
+        let redirect = NdpMessage::Redirect {
+            target: addr("fe80::2"),
+            destination,
+            target_lladdr: None,
+        };
+        receive(&mut ndp, router, addr("fe80::42"), redirect.clone(), now);
+        assert_eq!(ndp.next_hop(destination), Some(addr("fe80::2")));
+        // 不是当前第一跳发来的重定向被忽略
+        let redirect = NdpMessage::Redirect {
+            target: addr("fe80::3"),
+            destination,
+            target_lladdr: None,
+        };
+        receive(&mut ndp, router, addr("fe80::42"), redirect, now);
+        assert_eq!(ndp.next_hop(destination), Some(addr("fe80::2")));
+
+        // 路由器和前缀过期
+        ndp.poll(now + Duration::from_secs(3601));
+        assert!(ndp.routers().is_empty());
+        assert!(!ndp.is_on_link(neighbor));
+    }
+
+    #[test]
+    fn test_slaac_and_duplicate_address_detection() {
+        let now = Instant::now();
+        let mut ndp = NdpModule::new(OUR_MAC);
+        ndp.autoconfigure(now);
+        let link_local = addr("fe80::4042:42ff:fe42:4242");
+        assert_eq!(
+            ndp.address(link_local).unwrap().state,
+            AddressState::Tentative
+        );
+        assert!(!ndp.has_address(link_local));
+        assert!(ndp.is_member(solicited_node(link_local)));
+
+        // 重复地址检测：随机等待后发送源地址未指定的邻居请求，没有冲突则地址可用
+        ndp.poll(now + Duration::from_secs(1));
+        let (_, ns) = ndp.take_outgoing().pop().unwrap();
+        assert!(ns.src_addr.is_unspecified());
+        assert_eq!(ns.dst_addr, solicited_node(link_local));
+        ndp.poll(now + Duration::from_secs(3));
+        assert!(ndp.has_address(link_local));
+        assert_eq!(ndp.source_address(ALL_ROUTERS), Some(link_local));
+
+        // 路由器通告的自治前缀生成全局地址，其他主机已经在用这个地址
+        let router_advertisement = |valid_lifetime| NdpMessage::RouterAdvertisement {
+            cur_hop_limit: 0,
+            managed: false,
+            other: false,
+            router_lifetime: 600,
+            reachable_time: 0,
+            retrans_timer: 0,
+            source_lladdr: None,
+            mtu: None,
+            prefixes: vec![PrefixInfo {
+                prefix: addr("2001:db8::"),
+                prefix_len: 64,
+                on_link: true,
+                autonomous: true,
+                valid_lifetime,
+                preferred_lifetime: 60,
+            }],
+        };
+        receive(
+            &mut ndp,
+            addr("fe80::1"),
+            ALL_NODES,
+            router_advertisement(3600),
+            now,
+        );
+        let global = addr("2001:db8::4042:42ff:fe42:4242");
+        assert_eq!(ndp.address(global).unwrap().state, AddressState::Tentative);
+        let advertisement = NdpMessage::NeighborAdvertisement {
+            router: false,
+            solicited: false,
+            overrides: true,
+            target: global,
+            target_lladdr: Some(ROUTER_MAC),
+        };
+        receive(&mut ndp, global, ALL_NODES, advertisement, now);
+        assert_eq!(ndp.address(global).unwrap().state, AddressState::Duplicated);
+        assert!(!ndp.has_address(global));
+
+        // 之后的通告不会重新生成这个地址，也不再做重复地址检测
+        ndp.take_outgoing();
+        receive(
+            &mut ndp,
+            addr("fe80::1"),
+            ALL_NODES,
+            router_advertisement(3600),
+            now,
+        );
+        assert_eq!(ndp.addresses().len(), 2);
+        assert_eq!(ndp.address(global).unwrap().state, AddressState::Duplicated);
+        ndp.poll(now + Duration::from_secs(5));
+        assert!(
+            ndp.take_outgoing()
+                .iter()
+                .all(|(_, packet)| !packet.src_addr.is_unspecified())
+        );
+
+        // 稳定隐私地址冲突时换一个接口标识重新生成
+        let mut ndp = NdpModule::new(OUR_MAC);
+        ndp.set_address_generation(AddressGeneration::StablePrivacy([7; 16]));
+        receive(
+            &mut ndp,
+            addr("fe80::1"),
+            ALL_NODES,
+            router_advertisement(3600),
+            now,
+        );
+        let first = ndp.addresses()[0].addr;
+        let solicitation = NdpMessage::NeighborSolicitation {
+            target: first,
+            source_lladdr: None,
+        };
+        receive(
+            &mut ndp,
+            Ipv6Addr::UNSPECIFIED,
+            solicited_node(first),
+            solicitation,
+            now,
+        );
+        let second = &ndp.addresses()[0];
+        assert_eq!(ndp.addresses().len(), 1);
+        assert_ne!(second.addr, first);
+        assert_eq!(
+            (second.dad_counter, second.state),
+            (1, AddressState::Tentative)
+        );
+
+        // 首选期过后弃用，有效期过后删除
+        ndp.poll(now + Duration::from_secs(1));
+        ndp.poll(now + Duration::from_secs(3));
+        assert!(ndp.has_address(ndp.addresses()[0].addr));
+        ndp.poll(now + Duration::from_secs(61));
+        assert_eq!(ndp.addresses()[0].state, AddressState::Deprecated);
+        ndp.poll(now + Duration::from_secs(3601));
+        assert!(ndp.addresses().is_empty());
+    }
+}
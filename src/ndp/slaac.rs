@@ -0,0 +1,273 @@
+//! 无状态地址自动配置（RFC 4862）
+//!
+//! 由接口 MAC 生成接口标识：默认按 EUI-64（RFC 4291 附录 A），
+//! 也可以用 RFC 7217 的稳定隐私地址。新地址先处于 TENTATIVE 状态，
+//! 重复地址检测通过后才能使用；路由器通告中的前缀按有效期和首选期管理
+
+use sha2::{Digest, Sha256};
+use std::net::Ipv6Addr;
+use std::time::{Duration, Instant};
+
+use crate::arp::MacAddr;
+use crate::icmpv6::NdpMessage;
+use crate::ipv6::{Ipv6Packet, multicast_mac, solicited_node};
+
+const DUP_ADDR_DETECT_TRANSMITS: u32 = 1; // 重复地址检测发送的邻居请求数量
+const DAD_RETRANS_TIMER: Duration = Duration::from_secs(1); // 邻居请求间隔，最后一个发出后等待的时间
+const TWO_HOURS: Duration = Duration::from_secs(2 * 60 * 60); // 防止通告缩短有效期的下限（第 5.5.3 节 e）
+
+/// 稳定隐私地址重复时最多重新生成的次数（RFC 7217 IDGEN_RETRIES）
+pub const IDGEN_RETRIES: u8 = 3;
+
+/// 链路本地前缀 fe80::/64
+pub const LINK_LOCAL_PREFIX: Ipv6Addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0);
+
+/// 接口标识的生成方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddressGeneration {
+    #[default]
+    Eui64, // 由 MAC 地址生成，所有前缀使用同一个接口标识
+    StablePrivacy([u8; 16]), // RFC 7217，参数是本机的密钥，不同前缀得到不同的接口标识
+}
+
+/// 地址的状态（RFC 4862 第 2 节）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressState {
+    Tentative,  // 正在做重复地址检测，还不能使用
+    Preferred,  // 可以正常使用
+    Deprecated, // 首选期已过，已有连接可以继续使用，不再作为新连接的源地址
+    Duplicated, // 重复地址检测失败
+}
+
+/// 地址的来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressOrigin {
+    Manual,    // 手动配置
+    LinkLocal, // 自动生成的链路本地地址
+    Autoconf,  // 由路由器通告的前缀生成
+}
+
+/// 接口上的一个 IPv6 地址
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ipv6Address {
+    pub addr: Ipv6Addr,
+    pub prefix_len: u8,
+    pub origin: AddressOrigin,
+    pub state: AddressState,
+    pub preferred_until: Option<Instant>, // None 表示永久
+    pub valid_until: Option<Instant>,
+    pub dad_counter: u8, // 稳定隐私地址重新生成的次数
+    dad_sent: u32,       // 已发送的重复地址检测请求
+    next_dad: Instant,   // 下一次发送请求或检测完成的时间
+}
+
+impl Ipv6Address {
+    /// 手动配置的地址直接可用，不做重复地址检测
+    pub fn manual(addr: Ipv6Addr, prefix_len: u8, now: Instant) -> Self {
+        Self {
+            addr,
+            prefix_len,
+            origin: AddressOrigin::Manual,
+            state: AddressState::Preferred,
+            preferred_until: None,
+            valid_until: None,
+            dad_counter: 0,
+            dad_sent: 0,
+            next_dad: now,
+        }
+    }
+
+    /// 新生成的地址，在 start 时刻发送第一个重复地址检测请求
+    pub fn tentative(
+        addr: Ipv6Addr,
+        prefix_len: u8,
+        origin: AddressOrigin,
+        start: Instant,
+    ) -> Self {
+        let mut address = Self::manual(addr, prefix_len, start);
+        address.origin = origin;
+        address.state = AddressState::Tentative;
+        address
+    }
+
+    /// 地址是否可以收发数据
+    pub fn is_usable(&self) -> bool {
+        matches!(
+            self.state,
+            AddressState::Preferred | AddressState::Deprecated
+        )
+    }
+
+    /// 有效期是否已过，过期的地址要从接口删除
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.valid_until.is_some_and(|until| now >= until)
+    }
+
+    /// 推进定时器：返回需要发送的重复地址检测请求，检测通过后地址变为 PREFERRED；
+    /// 首选期过后变为 DEPRECATED
+    pub fn poll(&mut self, now: Instant) -> Option<(MacAddr, Ipv6Packet)> {
+        match self.state {
+            AddressState::Tentative if now >= self.next_dad => {
+                if self.dad_sent >= DUP_ADDR_DETECT_TRANSMITS {
+                    self.state = AddressState::Preferred;
+                    return self.poll(now);
+                }
+                self.dad_sent += 1;
+                self.next_dad = now + DAD_RETRANS_TIMER;
+                Some(self.dad_solicitation())
+            }
+            AddressState::Preferred if self.preferred_until.is_some_and(|until| now >= until) => {
+                self.state = AddressState::Deprecated;
+                None
+            }
+            AddressState::Deprecated if self.preferred_until.is_none_or(|until| now < until) => {
+                self.state = AddressState::Preferred;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// 重复地址检测请求：源地址未指定、不带源链路层地址，发给被请求节点组播地址
+    fn dad_solicitation(&self) -> (MacAddr, Ipv6Packet) {
+        let group = solicited_node(self.addr);
+        let solicitation = NdpMessage::NeighborSolicitation {
+            target: self.addr,
+            source_lladdr: None,
+        };
+        (
+            multicast_mac(group),
+            solicitation.to_packet(Ipv6Addr::UNSPECIFIED, group),
+        )
+    }
+
+    /// 新地址直接使用通告中的生存期
+    pub fn set_lifetimes(&mut self, valid: u32, preferred: u32, now: Instant) {
+        self.valid_until = lifetime(valid, now);
+        self.preferred_until = lifetime(preferred, now);
+    }
+
+    /// 按路由器通告更新生存期（RFC 4862 第 5.5.3 节 e）：首选期直接更新，
+    /// 有效期不能被通告缩短到两小时以内，防止伪造的通告让地址失效
+    pub fn update_lifetimes(&mut self, valid: u32, preferred: u32, now: Instant) {
+        self.preferred_until = lifetime(preferred, now);
+        let remaining = self
+            .valid_until
+            .map(|until| until.saturating_duration_since(now));
+        let received = lifetime(valid, now).map(|until| until - now);
+        self.valid_until = match (received, remaining) {
+            (None, _) => None,
+            (Some(received), Some(remaining)) if received > TWO_HOURS || received > remaining => {
+                Some(now + received)
+            }
+            (Some(received), None) if received > TWO_HOURS => Some(now + received),
+            (Some(_), Some(remaining)) if remaining <= TWO_HOURS => self.valid_until,
+            _ => Some(now + TWO_HOURS),
+        };
+    }
+}
+
+/// 生存期 0xffffffff 表示永久
+fn lifetime(seconds: u32, now: Instant) -> Option<Instant> {
+    (seconds != u32::MAX).then(|| now + Duration::from_secs(seconds as u64))
+}
+
+/// 修改后的 EUI-64 接口标识：MAC 中间插入 ff:fe，并翻转 U/L 位
+pub fn eui64_interface_id(mac: MacAddr) -> [u8; 8] {
+    [
+        mac[0] ^ 0x02,
+        mac[1],
+        mac[2],
+        0xff,
+        0xfe,
+        mac[3],
+        mac[4],
+        mac[5],
+    ]
+}
+
+/// 按生成方式计算前缀下的接口标识。稳定隐私地址取
+/// SHA-256(前缀高 64 位 ‖ MAC ‖ 重复次数 ‖ 密钥) 的低 64 位，
+/// 同一前缀下地址保持不变，换一个网络就得到不同的地址（RFC 7217 第 5 节）
+pub fn interface_id(
+    generation: AddressGeneration,
+    prefix: Ipv6Addr,
+    mac: MacAddr,
+    dad_counter: u8,
+) -> [u8; 8] {
+    match generation {
+        AddressGeneration::Eui64 => eui64_interface_id(mac),
+        AddressGeneration::StablePrivacy(secret) => {
+            let mut hasher = Sha256::new();
+            hasher.update(&prefix.octets()[..8]);
+            hasher.update(mac);
+            hasher.update([dad_counter]);
+            hasher.update(secret);
+            let digest = hasher.finalize();
+            let mut id = [0; 8];
+            id.copy_from_slice(&digest[24..]);
+            id
+        }
+    }
+}
+
+/// 64 位前缀加上接口标识
+pub fn with_interface_id(prefix: Ipv6Addr, interface_id: [u8; 8]) -> Ipv6Addr {
+    let mut octets = prefix.octets();
+    octets[8..].copy_from_slice(&interface_id);
+    Ipv6Addr::from(octets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUR_MAC: MacAddr = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+
+    #[test]
+    fn test_interface_id_and_lifetimes() {
+        let link_local = with_interface_id(LINK_LOCAL_PREFIX, eui64_interface_id(OUR_MAC));
+        assert_eq!(
+            link_local,
+            "fe80::5054:ff:fe12:3456".parse::<Ipv6Addr>().unwrap()
+        );
+
+        // 稳定隐私地址：同一前缀不变，不同前缀或重复次数不同
+        let secret = AddressGeneration::StablePrivacy([7; 16]);
+        let prefix: Ipv6Addr = "2001:db8:1::".parse().unwrap();
+        let id = interface_id(secret, prefix, OUR_MAC, 0);
+        assert_eq!(id, interface_id(secret, prefix, OUR_MAC, 0));
+        // 已知答案：固定编码下不随 Rust 版本和进程变化
+        assert_eq!(
+            with_interface_id(prefix, id),
+            "2001:db8:1:0:16a:212e:2f69:c950"
+                .parse::<Ipv6Addr>()
+                .unwrap()
+        );
+        assert_ne!(
+            id,
+            interface_id(secret, "2001:db8:2::".parse().unwrap(), OUR_MAC, 0)
+        );
+        assert_ne!(id, interface_id(secret, prefix, OUR_MAC, 1));
+
+        // 重复地址检测通过后可用，首选期过后弃用
+        let now = Instant::now();
+        let mut address = Ipv6Address::tentative(link_local, 64, AddressOrigin::Autoconf, now);
+        address.set_lifetimes(3 * 3600, 60, now);
+        let (_, ns) = address.poll(now).unwrap();
+        assert!(ns.src_addr.is_unspecified());
+        assert!(!address.is_usable());
+        assert!(address.poll(now + DAD_RETRANS_TIMER).is_none());
+        assert_eq!(address.state, AddressState::Preferred);
+        address.poll(now + Duration::from_secs(60));
+        assert_eq!(address.state, AddressState::Deprecated);
+
+        // 通告不能把剩余有效期缩短到两小时以内
+        address.update_lifetimes(60, 60, now);
+        assert_eq!(address.valid_until, Some(now + TWO_HOURS));
+        address.update_lifetimes(60, 60, now);
+        assert_eq!(address.valid_until, Some(now + TWO_HOURS));
+        address.update_lifetimes(u32::MAX, u32::MAX, now);
+        assert_eq!(address.valid_until, None);
+    }
+}
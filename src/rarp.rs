@@ -0,0 +1,178 @@
+//! RARP 协议实现
+//!
+//! RARP（Reverse Address Resolution Protocol，RFC 903）用于把 MAC 地址解析为 IP 地址，
+//! 无盘主机启动时用它获取自己的 IP。报文格式与 ARP 相同，EtherType 为 0x8035
+
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use tracing::{debug, info};
+
+use crate::arp::{ArpOperation, ArpPacket, MacAddr};
+
+/// 客户端两次请求之间的间隔
+const RARP_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 客户端最多发送的请求次数
+const RARP_MAX_REQUESTS: u32 = 5;
+
+/// 客户端状态
+#[derive(Debug)]
+struct RarpClient {
+    requests: u32,         // 已发送的请求次数
+    next_request: Instant, // 下一次发送请求的时间
+}
+
+#[derive(Debug)]
+pub struct RarpModule {
+    our_mac: MacAddr,
+    our_ip: Ipv4Addr,
+    table: HashMap<MacAddr, Ipv4Addr>, // 服务端的 MAC -> IP 映射表
+    client: Option<RarpClient>,        // 正在获取本机地址
+    outgoing: Vec<ArpPacket>,          // 待广播的请求
+    assigned: Option<Ipv4Addr>,        // 客户端获得的地址，尚未取出
+}
+
+impl RarpModule {
+    pub fn new(our_mac: MacAddr, our_ip: Ipv4Addr) -> Self {
+        Self {
+            our_mac,
+            our_ip,
+            table: HashMap::new(),
+            client: None,
+            outgoing: Vec::new(),
+            assigned: None,
+        }
+    }
+
+    pub fn set_ip(&mut self, ip: Ipv4Addr) {
+        self.our_ip = ip;
+    }
+
+    /// 服务端：为 mac 分配 ip，映射表非空时回答请求
+    pub fn add_mapping(&mut self, mac: MacAddr, ip: Ipv4Addr) {
+        self.table.insert(mac, ip);
+    }
+
+    pub fn remove_mapping(&mut self, mac: &MacAddr) -> Option<Ipv4Addr> {
+        self.table.remove(mac)
+    }
+
+    /// 客户端：开始广播请求获取本机地址
+    pub fn start_client(&mut self, now: Instant) {
+        info!("RARP requesting address for {:02x?}", self.our_mac);
+        self.client = Some(RarpClient {
+            requests: 0,
+            next_request: now,
+        });
+        self.poll(now);
+    }
+
+    /// 客户端是否还在等待响应
+    pub fn is_requesting(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// 处理收到的 RARP 报文，返回需要发回给请求方的响应
+    pub fn handle_packet(&mut self, rarp: &ArpPacket) -> Option<ArpPacket> {
+        if !rarp.is_ipv4_over_ethernet() {
+            return None;
+        }
+        match ArpOperation::from_u16(rarp.operation) {
+            Some(ArpOperation::RArpRequest) => {
+                // 地址本身还没确定时不能作为服务端
+                if self.our_ip.is_unspecified() {
+                    return None;
+                }
+                let Some(ip) = self.table.get(&rarp.target_mac) else {
+                    debug!("RARP no mapping for {:02x?}", rarp.target_mac);
+                    return None;
+                };
+                info!("RARP assign {} to {:02x?}", ip, rarp.target_mac);
+                Some(ArpPacket::build_rarp_reply(
+                    rarp,
+                    self.our_mac,
+                    self.our_ip,
+                    *ip,
+                ))
+            }
+            Some(ArpOperation::RArpReply) => {
+                if self.client.is_some() && rarp.target_mac == self.our_mac {
+                    info!(
+                        "RARP got address {} from {}",
+                        rarp.target_ip, rarp.sender_ip
+                    );
+                    self.client = None;
+                    self.assigned = Some(rarp.target_ip);
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// 处理客户端的重传定时器，次数用完后放弃
+    pub fn poll(&mut self, now: Instant) {
+        let Some(client) = self.client.as_mut() else {
+            return;
+        };
+        if now < client.next_request {
+            return;
+        }
+        if client.requests >= RARP_MAX_REQUESTS {
+            info!("RARP got no reply, give up");
+            self.client = None;
+            return;
+        }
+        client.requests += 1;
+        client.next_request = now + RARP_RETRY_INTERVAL;
+        self.outgoing
+            .push(ArpPacket::build_rarp_request(self.our_mac));
+    }
+
+    /// 取出待广播的请求
+    pub fn take_outgoing(&mut self) -> Vec<ArpPacket> {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    /// 取出客户端获得的地址
+    pub fn take_assigned(&mut self) -> Option<Ipv4Addr> {
+        self.assigned.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER_MAC: MacAddr = [0x42; 6];
+    const SERVER_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 10, 2);
+    const CLIENT_MAC: MacAddr = [0x02, 0, 0, 0, 0, 1];
+    const CLIENT_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 10, 20);
+
+    #[test]
+    fn test_rarp_client_and_server() {
+        let now = Instant::now();
+        let mut server = RarpModule::new(SERVER_MAC, SERVER_IP);
+        server.add_mapping(CLIENT_MAC, CLIENT_IP);
+        let mut client = RarpModule::new(CLIENT_MAC, Ipv4Addr::UNSPECIFIED);
+
+        client.start_client(now);
+        client.poll(now + RARP_RETRY_INTERVAL);
+        let requests = client.take_outgoing();
+        assert_eq!(requests.len(), 2);
+        let request = ArpPacket::parse(&requests[0].to_bytes()).unwrap();
+        assert_eq!(request.target_mac, CLIENT_MAC);
+
+        // 映射表里没有的 MAC 不回答
+        let unknown = ArpPacket::build_rarp_request([0x02, 0, 0, 0, 0, 9]);
+        assert!(server.handle_packet(&unknown).is_none());
+
+        let reply = server.handle_packet(&request).unwrap();
+        assert_eq!(reply.target_ip, CLIENT_IP);
+        assert!(client.handle_packet(&reply).is_none());
+        assert!(!client.is_requesting());
+        assert_eq!(client.take_assigned(), Some(CLIENT_IP));
+    }
+}
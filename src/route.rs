@@ -0,0 +1,191 @@
+//! IPv4 路由表
+//!
+//! 每条路由是 (前缀, 掩码, 网关, 接口, 度量)，查找时按最长前缀匹配，
+//! 前缀长度相同时选度量最小的。没有网关的路由表示目标在本网段，直接发给目标
+
+use std::fmt;
+use std::net::Ipv4Addr;
+
+/// 直连网段路由的度量
+const SUBNET_METRIC: u32 = 0;
+
+/// 一条路由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Route {
+    pub prefix: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    pub gateway: Option<Ipv4Addr>, // None 表示直连
+    pub interface: usize,          // 出接口的编号
+    pub metric: u32,
+}
+
+impl Route {
+    pub fn new(
+        prefix: Ipv4Addr,
+        netmask: Ipv4Addr,
+        gateway: Option<Ipv4Addr>,
+        interface: usize,
+        metric: u32,
+    ) -> Self {
+        // 去掉前缀中主机部分的位
+        let prefix = Ipv4Addr::from(u32::from(prefix) & u32::from(netmask));
+        Self {
+            prefix,
+            netmask,
+            gateway,
+            interface,
+            metric,
+        }
+    }
+
+    /// 接口地址所在网段的直连路由
+    pub fn subnet(ip: Ipv4Addr, netmask: Ipv4Addr, interface: usize) -> Self {
+        Self::new(ip, netmask, None, interface, SUBNET_METRIC)
+    }
+
+    /// 经过网关的默认路由 0.0.0.0/0
+    pub fn default_via(gateway: Ipv4Addr, interface: usize, metric: u32) -> Self {
+        Self::new(
+            Ipv4Addr::UNSPECIFIED,
+            Ipv4Addr::UNSPECIFIED,
+            Some(gateway),
+            interface,
+            metric,
+        )
+    }
+
+    pub fn prefix_len(&self) -> u32 {
+        u32::from(self.netmask).count_ones()
+    }
+
+    pub fn matches(&self, dst: Ipv4Addr) -> bool {
+        u32::from(dst) & u32::from(self.netmask) == u32::from(self.prefix)
+    }
+
+    /// 发往 dst 的包的下一跳：有网关时是网关，否则是目标本身
+    pub fn next_hop(&self, dst: Ipv4Addr) -> Ipv4Addr {
+        self.gateway.unwrap_or(dst)
+    }
+}
+
+/// 按 `ip route` 的格式输出，例如 "default via 192.168.10.1 dev 0 metric 0"
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.prefix_len() == 0 {
+            write!(f, "default")?;
+        } else {
+            write!(f, "{}/{}", self.prefix, self.prefix_len())?;
+        }
+        if let Some(gateway) = self.gateway {
+            write!(f, " via {}", gateway)?;
+        }
+        write!(f, " dev {} metric {}", self.interface, self.metric)
+    }
+}
+
+/// 路由表
+#[derive(Debug, Default)]
+pub struct RoutingTable {
+    routes: Vec<Route>,
+}
+
+impl RoutingTable {
+    pub fn new() -> Self {
+        Self { routes: Vec::new() }
+    }
+
+    /// 添加路由，前缀、掩码和度量都相同的旧路由被替换
+    pub fn add(&mut self, route: Route) {
+        self.routes.retain(|r| {
+            (r.prefix, r.netmask, r.metric) != (route.prefix, route.netmask, route.metric)
+        });
+        self.routes.push(route);
+    }
+
+    /// 删除前缀和掩码匹配的所有路由，返回是否删除了路由
+    pub fn remove(&mut self, prefix: Ipv4Addr, netmask: Ipv4Addr) -> bool {
+        let prefix = Ipv4Addr::from(u32::from(prefix) & u32::from(netmask));
+        let len = self.routes.len();
+        self.routes
+            .retain(|r| (r.prefix, r.netmask) != (prefix, netmask));
+        self.routes.len() != len
+    }
+
+    /// 删除经过某个接口的所有路由
+    pub fn remove_interface(&mut self, interface: usize) {
+        self.routes.retain(|r| r.interface != interface);
+    }
+
+    /// 设置默认网关，替换已有的默认路由
+    pub fn set_default_gateway(&mut self, gateway: Ipv4Addr, interface: usize) {
+        self.remove(Ipv4Addr::UNSPECIFIED, Ipv4Addr::UNSPECIFIED);
+        self.add(Route::default_via(gateway, interface, SUBNET_METRIC));
+    }
+
+    /// 最长前缀匹配，前缀长度相同时选度量最小的
+    pub fn lookup(&self, dst: Ipv4Addr) -> Option<&Route> {
+        self.routes
+            .iter()
+            .filter(|r| r.matches(dst))
+            .min_by_key(|r| (std::cmp::Reverse(r.prefix_len()), r.metric))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Route> {
+        self.routes.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_longest_prefix_match() {
+        let mut table = RoutingTable::new();
+        let gateway = Ipv4Addr::new(192, 168, 10, 1);
+        table.add(Route::subnet(
+            Ipv4Addr::new(192, 168, 10, 2),
+            Ipv4Addr::new(255, 255, 255, 0),
+            0,
+        ));
+        table.set_default_gateway(gateway, 0);
+        table.add(Route::new(
+            Ipv4Addr::new(10, 1, 0, 0),
+            Ipv4Addr::new(255, 255, 0, 0),
+            Some(Ipv4Addr::new(192, 168, 10, 254)),
+            0,
+            10,
+        ));
+        table.add(Route::new(
+            Ipv4Addr::new(10, 1, 0, 0),
+            Ipv4Addr::new(255, 255, 0, 0),
+            Some(Ipv4Addr::new(192, 168, 10, 253)),
+            0,
+            5,
+        ));
+
+        let peer = Ipv4Addr::new(192, 168, 10, 9);
+        assert_eq!(table.lookup(peer).unwrap().next_hop(peer), peer);
+        let remote = Ipv4Addr::new(8, 8, 8, 8);
+        assert_eq!(table.lookup(remote).unwrap().next_hop(remote), gateway);
+        let route = table.lookup(Ipv4Addr::new(10, 1, 2, 3)).unwrap();
+        assert_eq!(route.gateway, Some(Ipv4Addr::new(192, 168, 10, 253)));
+        assert_eq!(
+            route.to_string(),
+            "10.1.0.0/16 via 192.168.10.253 dev 0 metric 5"
+        );
+
+        // 删除默认路由后，网段外的地址不可达
+        assert!(table.remove(Ipv4Addr::UNSPECIFIED, Ipv4Addr::UNSPECIFIED));
+        assert!(table.lookup(remote).is_none());
+        assert_eq!(table.len(), 3);
+    }
+}
@@ -2,7 +2,32 @@
 //!
 //! 提供类似操作系统的 socket 接口，供应用程序使用
 
-use std::net::SocketAddrV4;
+use std::collections::{HashMap, VecDeque};
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::ops::RangeInclusive;
+use std::time::Instant;
+
+use tracing::debug;
+
+use crate::error::{Result, StackError};
+use crate::ip::Ipv4Packet;
+use crate::tcp::congestion::CongestionAlgorithm;
+use crate::tcp::tcb::{TcpConfig, TcpState};
+use crate::tcp::{TcpConnKey, TcpModule, TcpSegment};
+use crate::udp::UdpDatagram;
+
+/// 临时端口范围（RFC 6335）
+const EPHEMERAL_PORTS: RangeInclusive<u16> = 49152..=65535;
+
+/// 发送 UDP 数据报时使用的 TTL
+const DEFAULT_TTL: u8 = 64;
+
+/// UDP 数据报能携带的最大数据量：65535 - IP 头部 20 - UDP 头部 8
+const UDP_MAX_PAYLOAD: usize = 65507;
+
+/// 每个 UDP socket 最多排队的数据报数量，应用不读取时丢弃新数据报
+const UDP_RX_QUEUE_LEN: usize = 64;
 
 /// Socket 类型
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -11,17 +36,690 @@ pub enum SocketType {
     Udp, // UDP socket
 }
 
+/// Socket 句柄，由 `SocketManager::socket` 分配
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SocketHandle(usize);
+
+/// UDP 收包统计，对应 /proc/net/snmp 中的同名计数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UdpStats {
+    pub in_datagrams: u64,  // 交给 socket 的数据报
+    pub no_ports: u64,      // 目标端口没有 socket 而丢弃的数据报
+    pub in_errors: u64,     // 校验和错误的数据报
+    pub rcvbuf_errors: u64, // 接收队列已满而丢弃的数据报
+}
+
+/// Socket 的协议状态
+#[derive(Debug)]
+enum SocketInner {
+    Udp {
+        rx_queue: VecDeque<(Vec<u8>, SocketAddrV4)>, // 收到的数据和来源地址
+    },
+    TcpUnconnected,        // 新建或只调用过 bind
+    TcpListener,           // 正在监听
+    TcpStream(TcpConnKey), // 已连接（connect 或 accept 得到）
+}
+
 /// Socket 结构
 #[derive(Debug)]
 pub struct Socket {
     pub socket_type: SocketType,           // Socket 类型
     pub local_addr: Option<SocketAddrV4>,  // 本地地址（IP + 端口）
     pub remote_addr: Option<SocketAddrV4>, // 远程地址（IP + 端口）
+    inner: SocketInner,
+    congestion: Option<CongestionAlgorithm>, // TCP 拥塞控制算法，None 时使用 TcpModule 的配置
+}
+
+impl Socket {
+    fn new(socket_type: SocketType) -> Self {
+        let inner = match socket_type {
+            SocketType::Tcp => SocketInner::TcpUnconnected,
+            SocketType::Udp => SocketInner::Udp {
+                rx_queue: VecDeque::new(),
+            },
+        };
+        Self {
+            socket_type,
+            local_addr: None,
+            remote_addr: None,
+            inner,
+            congestion: None,
+        }
+    }
+
+    /// 是否正在监听
+    pub fn is_listening(&self) -> bool {
+        matches!(self.inner, SocketInner::TcpListener)
+    }
 }
 
 /// Socket 管理器
 /// 负责管理所有的 socket 连接
 #[derive(Debug)]
 pub struct SocketManager {
-    // 后续会添加 socket 列表等字段
+    sockets: HashMap<SocketHandle, Socket>,
+    next_handle: usize,
+    local_ip: Ipv4Addr, // 未指定本地 IP 时使用的源地址
+    next_ephemeral_port: u16,
+    tcp: TcpModule,
+    udp_outgoing: Vec<Ipv4Packet>,
+    udp_stats: UdpStats,
+}
+
+impl SocketManager {
+    pub fn new(local_ip: Ipv4Addr) -> Self {
+        Self {
+            sockets: HashMap::new(),
+            next_handle: 0,
+            local_ip,
+            next_ephemeral_port: *EPHEMERAL_PORTS.start(),
+            tcp: TcpModule::new(),
+            udp_outgoing: Vec::new(),
+            udp_stats: UdpStats::default(),
+        }
+    }
+
+    /// 修改未指定本地 IP 时使用的源地址
+    pub fn set_local_ip(&mut self, local_ip: Ipv4Addr) {
+        self.local_ip = local_ip;
+    }
+
+    pub fn tcp(&self) -> &TcpModule {
+        &self.tcp
+    }
+
+    pub fn tcp_mut(&mut self) -> &mut TcpModule {
+        &mut self.tcp
+    }
+
+    pub fn udp_stats(&self) -> &UdpStats {
+        &self.udp_stats
+    }
+
+    pub fn get(&self, handle: SocketHandle) -> Option<&Socket> {
+        self.sockets.get(&handle)
+    }
+
+    /// 创建 socket
+    pub fn socket(&mut self, socket_type: SocketType) -> SocketHandle {
+        let handle = SocketHandle(self.next_handle);
+        self.next_handle += 1;
+        self.sockets.insert(handle, Socket::new(socket_type));
+        handle
+    }
+
+    /// 绑定本地地址，端口为 0 时分配临时端口
+    pub fn bind(&mut self, handle: SocketHandle, addr: SocketAddrV4) -> Result<()> {
+        let socket = self.socket_ref(handle)?;
+        if socket.local_addr.is_some() {
+            return Err(io_error(ErrorKind::InvalidInput, "socket already bound"));
+        }
+        let socket_type = socket.socket_type;
+        let port = if addr.port() == 0 {
+            self.ephemeral_port(socket_type, *addr.ip())?
+        } else if self.addr_in_use(socket_type, addr) {
+            return Err(io_error(
+                ErrorKind::AddrInUse,
+                &format!("{} already in use", addr),
+            ));
+        } else {
+            addr.port()
+        };
+        let local = SocketAddrV4::new(*addr.ip(), port);
+        self.socket_mut(handle)?.local_addr = Some(local);
+        debug!("socket {:?} bound to {}", handle, local);
+        Ok(())
+    }
+
+    /// 开始监听，只用于已绑定的 TCP socket
+    pub fn listen(&mut self, handle: SocketHandle) -> Result<()> {
+        let socket = self.socket_ref(handle)?;
+        if !matches!(socket.inner, SocketInner::TcpUnconnected) {
+            return Err(io_error(
+                ErrorKind::InvalidInput,
+                "listen requires an unconnected TCP socket",
+            ));
+        }
+        let local = socket
+            .local_addr
+            .ok_or_else(|| io_error(ErrorKind::InvalidInput, "listen requires bind"))?;
+        let config = self.tcp_config(socket.congestion);
+        self.tcp.listen_with(local, config)?;
+        self.socket_mut(handle)?.inner = SocketInner::TcpListener;
+        Ok(())
+    }
+
+    /// 取出一个已建立的连接，没有时返回 WouldBlock
+    pub fn accept(&mut self, handle: SocketHandle) -> Result<(SocketHandle, SocketAddrV4)> {
+        let socket = self.socket_ref(handle)?;
+        if !socket.is_listening() {
+            return Err(io_error(ErrorKind::InvalidInput, "socket is not listening"));
+        }
+        let local = socket.local_addr.unwrap_or_else(unspecified);
+        let congestion = socket.congestion;
+        let key = self
+            .tcp
+            .accept(local)
+            .ok_or_else(|| StackError::Io(ErrorKind::WouldBlock.into()))?;
+
+        let new_handle = self.socket(SocketType::Tcp);
+        let socket = self.socket_mut(new_handle)?;
+        socket.local_addr = Some(key.local);
+        socket.remote_addr = Some(key.remote);
+        socket.inner = SocketInner::TcpStream(key);
+        socket.congestion = congestion;
+        Ok((new_handle, key.remote))
+    }
+
+    /// TCP 发起连接；UDP 只记录默认的目标地址
+    pub fn connect(
+        &mut self,
+        handle: SocketHandle,
+        remote: SocketAddrV4,
+        now: Instant,
+    ) -> Result<()> {
+        let socket = self.socket_ref(handle)?;
+        match socket.inner {
+            SocketInner::Udp { .. } => {
+                self.auto_bind(handle)?;
+                self.socket_mut(handle)?.remote_addr = Some(remote);
+                Ok(())
+            }
+            SocketInner::TcpUnconnected => {
+                let local = self.auto_bind(handle)?;
+                let local = SocketAddrV4::new(self.source_ip(*local.ip()), local.port());
+                let config = self.tcp_config(self.socket_ref(handle)?.congestion);
+                let key = self.tcp.connect_with(local, remote, &config, now)?;
+                let socket = self.socket_mut(handle)?;
+                socket.local_addr = Some(local);
+                socket.remote_addr = Some(remote);
+                socket.inner = SocketInner::TcpStream(key);
+                Ok(())
+            }
+            _ => Err(io_error(
+                ErrorKind::AlreadyExists,
+                "socket already connected",
+            )),
+        }
+    }
+
+    /// 为 TCP socket 选择拥塞控制算法：在 connect 或 listen 之前设置时从握手开始使用，
+    /// 监听 socket 上 accept 得到的连接继承它；已连接的 socket 立即切换
+    pub fn set_congestion_control(
+        &mut self,
+        handle: SocketHandle,
+        algorithm: CongestionAlgorithm,
+    ) -> Result<()> {
+        let socket = self.socket_mut(handle)?;
+        match socket.inner {
+            SocketInner::Udp { .. } => {
+                return Err(io_error(ErrorKind::InvalidInput, "not a TCP socket"));
+            }
+            SocketInner::TcpUnconnected => {}
+            SocketInner::TcpListener => {
+                let local = socket.local_addr.unwrap_or_else(unspecified);
+                self.tcp.set_listener_congestion_control(local, algorithm)?;
+            }
+            SocketInner::TcpStream(key) => self.tcp.set_congestion_control(&key, algorithm)?,
+        }
+        self.socket_mut(handle)?.congestion = Some(algorithm);
+        Ok(())
+    }
+
+    /// 新连接的配置：TcpModule 的配置加上 socket 选择的拥塞控制算法
+    fn tcp_config(&self, congestion: Option<CongestionAlgorithm>) -> TcpConfig {
+        let mut config = self.tcp.config().clone();
+        if let Some(congestion) = congestion {
+            config.congestion = congestion;
+        }
+        config
+    }
+
+    /// 发送数据，返回写入发送缓冲区的字节数；UDP 需要先 connect
+    pub fn send(&mut self, handle: SocketHandle, data: &[u8]) -> Result<usize> {
+        let socket = self.socket_ref(handle)?;
+        match &socket.inner {
+            SocketInner::TcpStream(key) => {
+                let key = *key;
+                self.tcp.send(&key, data)
+            }
+            SocketInner::Udp { .. } => {
+                let remote = socket
+                    .remote_addr
+                    .ok_or_else(|| io_error(ErrorKind::NotConnected, "socket not connected"))?;
+                self.sendto(handle, data, remote)
+            }
+            _ => Err(io_error(ErrorKind::NotConnected, "socket not connected")),
+        }
+    }
+
+    /// 接收数据；TCP 返回 0 表示对方已关闭，没有数据时返回 WouldBlock
+    pub fn recv(&mut self, handle: SocketHandle, buf: &mut [u8]) -> Result<usize> {
+        let socket = self.socket_ref(handle)?;
+        match &socket.inner {
+            SocketInner::TcpStream(key) => {
+                let key = *key;
+                self.tcp.recv(&key, buf)
+            }
+            SocketInner::Udp { .. } => self.recvfrom(handle, buf).map(|(len, _)| len),
+            _ => Err(io_error(ErrorKind::NotConnected, "socket not connected")),
+        }
+    }
+
+    /// 发送 UDP 数据报，未绑定时自动分配临时端口
+    pub fn sendto(
+        &mut self,
+        handle: SocketHandle,
+        data: &[u8],
+        remote: SocketAddrV4,
+    ) -> Result<usize> {
+        if self.socket_ref(handle)?.socket_type != SocketType::Udp {
+            return Err(io_error(
+                ErrorKind::InvalidInput,
+                "sendto requires a UDP socket",
+            ));
+        }
+        if data.len() > UDP_MAX_PAYLOAD {
+            return Err(io_error(ErrorKind::InvalidInput, "datagram too large"));
+        }
+        let local = self.auto_bind(handle)?;
+        let src_ip = self.source_ip(*local.ip());
+        let datagram = UdpDatagram::build(
+            local.port(),
+            remote.port(),
+            data.to_vec(),
+            src_ip,
+            *remote.ip(),
+        );
+        self.udp_outgoing.push(Ipv4Packet::build(
+            src_ip,
+            *remote.ip(),
+            17,
+            DEFAULT_TTL,
+            datagram.to_bytes(),
+        ));
+        Ok(data.len())
+    }
+
+    /// 取出一个 UDP 数据报，缓冲区不够时多余部分被丢弃
+    pub fn recvfrom(
+        &mut self,
+        handle: SocketHandle,
+        buf: &mut [u8],
+    ) -> Result<(usize, SocketAddrV4)> {
+        let SocketInner::Udp { rx_queue } = &mut self.socket_mut(handle)?.inner else {
+            return Err(io_error(
+                ErrorKind::InvalidInput,
+                "recvfrom requires a UDP socket",
+            ));
+        };
+        let (payload, from) = rx_queue
+            .pop_front()
+            .ok_or_else(|| StackError::Io(ErrorKind::WouldBlock.into()))?;
+        let len = payload.len().min(buf.len());
+        buf[..len].copy_from_slice(&payload[..len]);
+        Ok((len, from))
+    }
+
+    /// 关闭 socket 并释放句柄；TCP 连接会继续完成挥手
+    pub fn close(&mut self, handle: SocketHandle) -> Result<()> {
+        let socket = self
+            .sockets
+            .remove(&handle)
+            .ok_or_else(|| bad_handle(handle))?;
+        match socket.inner {
+            SocketInner::TcpListener => {
+                self.tcp
+                    .unlisten(socket.local_addr.unwrap_or_else(unspecified));
+            }
+            SocketInner::TcpStream(key) => {
+                // 连接可能已经被重置并删除
+                let _ = self.tcp.close(&key);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// TCP 连接状态
+    pub fn tcp_state(&self, handle: SocketHandle) -> Option<TcpState> {
+        match self.sockets.get(&handle)?.inner {
+            SocketInner::TcpStream(key) => self.tcp.state(&key),
+            SocketInner::TcpListener => Some(TcpState::Listen),
+            _ => None,
+        }
+    }
+
+    /// 把收到的 UDP 数据报放进绑定到目标地址的 socket 的接收队列
+    /// 返回 false 表示目标端口没有 socket，数据报已被丢弃
+    pub fn handle_udp_datagram(
+        &mut self,
+        src_addr: Ipv4Addr,
+        dst_addr: Ipv4Addr,
+        datagram: &UdpDatagram,
+    ) -> Result<bool> {
+        if let Err(e) = datagram.verify_checksum(src_addr, dst_addr) {
+            self.udp_stats.in_errors += 1;
+            return Err(e);
+        }
+        let from = SocketAddrV4::new(src_addr, datagram.src_port);
+        let to = SocketAddrV4::new(dst_addr, datagram.dst_port);
+        let Some(handle) = self.find_udp_socket(to, from) else {
+            self.udp_stats.no_ports += 1;
+            debug!("UDP {} -> {} dropped: port unreachable", from, to);
+            return Ok(false);
+        };
+
+        let socket = self.socket_mut(handle)?;
+        let SocketInner::Udp { rx_queue } = &mut socket.inner else {
+            return Ok(false);
+        };
+        if rx_queue.len() >= UDP_RX_QUEUE_LEN {
+            self.udp_stats.rcvbuf_errors += 1;
+            debug!("UDP {} -> {} dropped: receive queue full", from, to);
+            return Ok(true);
+        }
+        rx_queue.push_back((datagram.payload.clone(), from));
+        self.udp_stats.in_datagrams += 1;
+        Ok(true)
+    }
+
+    /// 处理收到的 TCP 段
+    pub fn handle_tcp_segment(
+        &mut self,
+        src_addr: Ipv4Addr,
+        dst_addr: Ipv4Addr,
+        seg: &TcpSegment,
+        now: Instant,
+    ) -> Result<()> {
+        self.tcp.handle_segment(src_addr, dst_addr, seg, now)
+    }
+
+    /// 处理定时器
+    pub fn poll(&mut self, now: Instant) {
+        self.tcp.poll(now);
+    }
+
+    /// 取出所有待发送的 IPv4 包
+    pub fn take_outgoing(&mut self) -> Vec<Ipv4Packet> {
+        let mut packets: Vec<Ipv4Packet> = self.udp_outgoing.drain(..).collect();
+        packets.extend(self.tcp.take_outgoing());
+        packets
+    }
+
+    fn socket_ref(&self, handle: SocketHandle) -> Result<&Socket> {
+        self.sockets.get(&handle).ok_or_else(|| bad_handle(handle))
+    }
+
+    fn socket_mut(&mut self, handle: SocketHandle) -> Result<&mut Socket> {
+        self.sockets
+            .get_mut(&handle)
+            .ok_or_else(|| bad_handle(handle))
+    }
+
+    /// 未绑定的 socket 绑定到 0.0.0.0 上的临时端口
+    fn auto_bind(&mut self, handle: SocketHandle) -> Result<SocketAddrV4> {
+        if let Some(local) = self.socket_ref(handle)?.local_addr {
+            return Ok(local);
+        }
+        self.bind(handle, unspecified())?;
+        Ok(self
+            .socket_ref(handle)?
+            .local_addr
+            .unwrap_or_else(unspecified))
+    }
+
+    fn source_ip(&self, ip: Ipv4Addr) -> Ipv4Addr {
+        if ip.is_unspecified() {
+            self.local_ip
+        } else {
+            ip
+        }
+    }
+
+    /// 找到接收数据报的 UDP socket：
+    /// 已连接的 socket 只接收来自对端的数据，地址匹配越精确优先级越高
+    fn find_udp_socket(&self, to: SocketAddrV4, from: SocketAddrV4) -> Option<SocketHandle> {
+        self.sockets
+            .iter()
+            .filter(|(_, socket)| socket.socket_type == SocketType::Udp)
+            .filter_map(|(handle, socket)| {
+                let local = socket.local_addr?;
+                if local.port() != to.port() {
+                    return None;
+                }
+                let mut score = 0;
+                if local.ip() == to.ip() {
+                    score += 1;
+                } else if !local.ip().is_unspecified() {
+                    return None;
+                }
+                if let Some(remote) = socket.remote_addr {
+                    if remote != from {
+                        return None;
+                    }
+                    score += 2;
+                }
+                Some((score, *handle))
+            })
+            .max_by_key(|&(score, handle)| (score, std::cmp::Reverse(handle.0)))
+            .map(|(_, handle)| handle)
+    }
+
+    /// 同类型 socket 的地址冲突：端口相同且 IP 相同或任一方为 0.0.0.0
+    fn addr_in_use(&self, socket_type: SocketType, addr: SocketAddrV4) -> bool {
+        self.sockets.values().any(|socket| {
+            socket.socket_type == socket_type
+                && socket.local_addr.is_some_and(|local| {
+                    local.port() == addr.port()
+                        && (local.ip() == addr.ip()
+                            || local.ip().is_unspecified()
+                            || addr.ip().is_unspecified())
+                })
+        })
+    }
+
+    fn ephemeral_port(&mut self, socket_type: SocketType, ip: Ipv4Addr) -> Result<u16> {
+        let count = EPHEMERAL_PORTS.len();
+        for _ in 0..count {
+            let port = self.next_ephemeral_port;
+            self.next_ephemeral_port = if port == *EPHEMERAL_PORTS.end() {
+                *EPHEMERAL_PORTS.start()
+            } else {
+                port + 1
+            };
+            if !self.addr_in_use(socket_type, SocketAddrV4::new(ip, port)) {
+                return Ok(port);
+            }
+        }
+        Err(io_error(
+            ErrorKind::AddrInUse,
+            "no ephemeral port available",
+        ))
+    }
+}
+
+fn unspecified() -> SocketAddrV4 {
+    SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)
+}
+
+fn io_error(kind: ErrorKind, message: &str) -> StackError {
+    StackError::Io(std::io::Error::new(kind, message))
+}
+
+fn bad_handle(handle: SocketHandle) -> StackError {
+    io_error(ErrorKind::NotFound, &format!("invalid socket {:?}", handle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tcp::tcb::Tcb;
+
+    /// 把一方发出的 TCP 段交给另一方
+    fn deliver(from: &mut SocketManager, to: &mut SocketManager, now: Instant) -> usize {
+        let packets = from.take_outgoing();
+        for packet in &packets {
+            let seg = TcpSegment::parse(&packet.payload).unwrap();
+            to.handle_tcp_segment(packet.src_addr, packet.dst_addr, &seg, now)
+                .unwrap();
+        }
+        packets.len()
+    }
+
+    /// socket 对应的 TCP 连接
+    fn tcb(manager: &SocketManager, handle: SocketHandle) -> &Tcb {
+        let SocketInner::TcpStream(key) = manager.get(handle).unwrap().inner else {
+            panic!("not a TCP stream");
+        };
+        manager.tcp().connection(&key).unwrap()
+    }
+
+    #[test]
+    fn test_tcp_sockets() {
+        let now = Instant::now();
+        let server_ip = Ipv4Addr::new(192, 168, 10, 2);
+        let mut server = SocketManager::new(server_ip);
+        let mut client = SocketManager::new(Ipv4Addr::new(192, 168, 10, 1));
+
+        let listener = server.socket(SocketType::Tcp);
+        server
+            .bind(listener, SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 8080))
+            .unwrap();
+        // 监听 socket 上选择的算法由 accept 得到的连接继承
+        server
+            .set_congestion_control(listener, CongestionAlgorithm::Cubic)
+            .unwrap();
+        server.listen(listener).unwrap();
+        assert!(matches!(
+            server.accept(listener),
+            Err(StackError::Io(e)) if e.kind() == ErrorKind::WouldBlock
+        ));
+
+        let stream = client.socket(SocketType::Tcp);
+        client
+            .set_congestion_control(stream, CongestionAlgorithm::Cubic)
+            .unwrap();
+        client
+            .connect(stream, SocketAddrV4::new(server_ip, 8080), now)
+            .unwrap();
+        assert_eq!(tcb(&client, stream).congestion_control().name(), "cubic");
+        let local = client.get(stream).unwrap().local_addr.unwrap();
+        assert!(EPHEMERAL_PORTS.contains(&local.port()));
+
+        client.send(stream, b"hello").unwrap();
+        loop {
+            client.poll(now);
+            server.poll(now);
+            if deliver(&mut client, &mut server, now) + deliver(&mut server, &mut client, now) == 0
+            {
+                break;
+            }
+        }
+        let (conn, peer) = server.accept(listener).unwrap();
+        assert_eq!(peer, local);
+        assert_eq!(server.tcp_state(conn), Some(TcpState::Established));
+        assert_eq!(tcb(&server, conn).congestion_control().name(), "cubic");
+        // 连接建立后切换算法，拥塞窗口保持不变
+        let cc = tcb(&client, stream).congestion_control();
+        let window = (cc.cwnd(), cc.ssthresh());
+        client
+            .set_congestion_control(stream, CongestionAlgorithm::NewReno)
+            .unwrap();
+        assert_eq!(tcb(&client, stream).congestion_control().name(), "newreno");
+        let cc = tcb(&client, stream).congestion_control();
+        assert_eq!((cc.cwnd(), cc.ssthresh()), window);
+        let mut buf = [0u8; 16];
+        assert_eq!(server.recv(conn, &mut buf).unwrap(), 5);
+        assert_eq!(&buf[..5], b"hello");
+
+        server.close(conn).unwrap();
+        assert!(server.recv(conn, &mut buf).is_err());
+    }
+
+    #[test]
+    fn test_udp_demux() {
+        let local_ip = Ipv4Addr::new(192, 168, 10, 2);
+        let peer = SocketAddrV4::new(Ipv4Addr::new(192, 168, 10, 1), 5000);
+        let other = SocketAddrV4::new(Ipv4Addr::new(192, 168, 10, 3), 5000);
+        let mut manager = SocketManager::new(local_ip);
+        let dns = manager.socket(SocketType::Udp);
+        manager
+            .bind(dns, SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 53))
+            .unwrap();
+        let echo = manager.socket(SocketType::Udp);
+        manager.bind(echo, SocketAddrV4::new(local_ip, 7)).unwrap();
+        let connected = manager.socket(SocketType::Udp);
+        manager
+            .bind(connected, SocketAddrV4::new(local_ip, 9000))
+            .unwrap();
+        manager.connect(connected, peer, Instant::now()).unwrap();
+
+        let mut deliver = |from: SocketAddrV4, port: u16, payload: &[u8]| {
+            let datagram =
+                UdpDatagram::build(from.port(), port, payload.to_vec(), *from.ip(), local_ip);
+            manager
+                .handle_udp_datagram(*from.ip(), local_ip, &datagram)
+                .unwrap()
+        };
+        assert!(deliver(peer, 53, b"query"));
+        assert!(deliver(peer, 7, b"ping"));
+        assert!(deliver(peer, 9000, b"from peer"));
+        // 已连接的 socket 不接收其他来源的数据，没有 socket 的端口直接丢弃
+        assert!(!deliver(other, 9000, b"stranger"));
+        assert!(!deliver(peer, 1234, b"closed"));
+
+        let mut buf = [0u8; 16];
+        assert_eq!(manager.recvfrom(dns, &mut buf).unwrap(), (5, peer));
+        assert_eq!(&buf[..5], b"query");
+        assert_eq!(manager.recv(echo, &mut buf).unwrap(), 4);
+        assert_eq!(manager.recv(connected, &mut buf).unwrap(), 9);
+        assert!(manager.recv(connected, &mut buf).is_err());
+        assert_eq!(
+            *manager.udp_stats(),
+            UdpStats {
+                in_datagrams: 3,
+                no_ports: 2,
+                ..UdpStats::default()
+            }
+        );
+
+        // 校验和错误
+        let mut datagram = UdpDatagram::build(5000, 53, b"bad".to_vec(), *peer.ip(), local_ip);
+        datagram.checksum ^= 1;
+        assert!(
+            manager
+                .handle_udp_datagram(*peer.ip(), local_ip, &datagram)
+                .is_err()
+        );
+        assert_eq!(manager.udp_stats().in_errors, 1);
+    }
+
+    #[test]
+    fn test_udp_sockets() {
+        let local_ip = Ipv4Addr::new(192, 168, 10, 2);
+        let mut manager = SocketManager::new(local_ip);
+        let a = manager.socket(SocketType::Udp);
+        let b = manager.socket(SocketType::Udp);
+        manager
+            .bind(a, SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 53))
+            .unwrap();
+        assert!(matches!(
+            manager.bind(b, SocketAddrV4::new(local_ip, 53)),
+            Err(StackError::Io(e)) if e.kind() == ErrorKind::AddrInUse
+        ));
+        // TCP 和 UDP 的端口互不影响
+        let tcp = manager.socket(SocketType::Tcp);
+        manager.bind(tcp, SocketAddrV4::new(local_ip, 53)).unwrap();
+
+        let remote = SocketAddrV4::new(Ipv4Addr::new(192, 168, 10, 1), 5000);
+        assert_eq!(manager.sendto(a, b"query", remote).unwrap(), 5);
+        assert!(manager.send(b, b"x").is_err());
+        let packets = manager.take_outgoing();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].src_addr, local_ip);
+        let datagram = UdpDatagram::parse(&packets[0].payload).unwrap();
+        assert_eq!((datagram.src_port, datagram.dst_port), (53, 5000));
+        assert_eq!(datagram.payload, b"query");
+    }
 }
@@ -0,0 +1,1594 @@
+//! 协议栈主循环
+//!
+//! `Stack` 拥有网络接口、ARP 模块和所有协议处理模块，
+//! 负责从设备读取以太网帧、逐层分发，并把各层产生的 IP 包发送出去。
+//! 开启转发后，不是发给本机的包按路由表从其他接口转发出去，协议栈作为软件路由器工作。
+//! IPv6 由每个接口的 NDP 模块负责地址解析和选择下一跳
+
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant};
+
+use tracing::{debug, info, warn};
+
+use crate::arp::{ArpModule, ArpPacket, MacAddr};
+use crate::device::NetworkInterface;
+use crate::error::{Result, StackError};
+use crate::ethernet::{EtherType, EthernetFrame, FramePayload};
+use crate::icmp::{IcmpPacket, IcmpType, RedirectCode, TimeExceededCode, UnreachableCode};
+use crate::icmpv6::{Icmpv6Type, Icmpv6UnreachableCode, NdpMessage, ParameterProblemCode};
+use crate::ip::options::{Ipv4Option, ip_timestamp};
+use crate::ip::reassembly::Reassembler;
+use crate::ip::{IdentGenerator, Ipv4Packet};
+use crate::ipv6::{
+    ExtensionProblem, IPV6_NEXT_ICMPV6, IPV6_NEXT_NONE, IPV6_NEXT_TCP, IPV6_NEXT_UDP, Ipv6Packet,
+};
+use crate::ndp::NdpModule;
+use crate::rarp::RarpModule;
+use crate::route::{Route, RoutingTable};
+use crate::socket::SocketManager;
+use crate::tcp::TcpSegment;
+use crate::udp::UdpDatagram;
+
+/// 本机发出的 IP 包使用的 TTL
+const DEFAULT_TTL: u8 = 64;
+
+/// 接收缓冲区大小，足够容纳一个以太网帧
+const FRAME_BUFFER_SIZE: usize = 65536;
+
+/// 非阻塞设备上没有数据时的休眠时间
+const IDLE_SLEEP: Duration = Duration::from_millis(1);
+
+/// 一次 poll 内最多处理的本机回环包，防止互相回复形成死循环
+const LOOPBACK_BUDGET: usize = 64;
+
+/// 等待上层取走的路由器警告包上限
+const ROUTER_ALERT_QUEUE_LEN: usize = 64;
+
+const BROADCAST_MAC: MacAddr = [0xff; 6];
+
+/// 主接口的编号，socket 和 RARP 使用主接口的地址
+const PRIMARY: usize = 0;
+
+/// 协议栈收发统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StackStats {
+    pub rx_frames: u64,  // 收到的以太网帧
+    pub rx_errors: u64,  // 解析或处理失败的帧
+    pub rx_dropped: u64, // 不支持的协议、不是发给本机或无法转发的包
+    pub tx_frames: u64,  // 发送的以太网帧
+    pub tx_errors: u64,  // 设备发送失败
+    pub tx_dropped: u64, // 没有路由、ARP 解析失败或无法分片而丢弃的包
+    pub forwarded: u64,  // 转发的包
+}
+
+/// 一个网络接口和它的 ARP、NDP 模块
+struct Port {
+    interface: NetworkInterface,
+    arp: ArpModule,
+    ndp: NdpModule,
+}
+
+impl Port {
+    fn new(interface: NetworkInterface) -> Self {
+        let arp = ArpModule::new(interface.ip, interface.mac);
+        let ndp = NdpModule::new(interface.mac);
+        Self {
+            interface,
+            arp,
+            ndp,
+        }
+    }
+
+    /// 是否接收发往这个 MAC 的帧：本机 MAC、广播和已加入的 IPv6 组播组
+    fn accepts_mac(&self, mac: MacAddr) -> bool {
+        mac == self.interface.mac || mac == BROADCAST_MAC || self.ndp.accepts_mac(mac)
+    }
+
+    /// 地址是否在接口所在的网段
+    fn on_link(&self, addr: Ipv4Addr) -> bool {
+        let mask = u32::from(self.interface.netmask);
+        u32::from(addr) & mask == u32::from(self.interface.ip) & mask
+    }
+
+    fn directed_broadcast(&self) -> Ipv4Addr {
+        Ipv4Addr::from(u32::from(self.interface.ip) | !u32::from(self.interface.netmask))
+    }
+}
+
+/// 协议栈
+pub struct Stack {
+    ports: Vec<Port>, // 所有接口，第一个是主接口
+    rarp: RarpModule,
+    routes: RoutingTable,
+    sockets: SocketManager,
+    forwarding: bool,
+    outgoing: Vec<Ipv4Packet>, // 协议栈自己产生的 IP 包（ICMP 回复等）
+    outgoing_v6: Vec<(usize, Ipv6Packet)>, // 协议栈自己产生的 IPv6 包及出接口
+    loopback: VecDeque<Ipv4Packet>, // 发给本机的 IP 包
+    router_alerts: Vec<(usize, Ipv4Packet)>, // 带路由器警告选项的过路包及收到的接口
+    ident: IdentGenerator,     // 本机发出的 IP 包的标识
+    reassembler: Reassembler,  // 收到的分片
+    stats: StackStats,
+    rx_buf: Vec<u8>,
+}
+
+impl Stack {
+    pub fn new(interface: NetworkInterface) -> Self {
+        let rarp = RarpModule::new(interface.mac, interface.ip);
+        let mut sockets = SocketManager::new(interface.ip);
+        sockets.tcp_mut().set_mtu(interface.mtu);
+        let mut routes = RoutingTable::new();
+        routes.add(Route::subnet(interface.ip, interface.netmask, PRIMARY));
+        Self {
+            ports: vec![Port::new(interface)],
+            rarp,
+            routes,
+            sockets,
+            forwarding: false,
+            outgoing: Vec::new(),
+            outgoing_v6: Vec::new(),
+            loopback: VecDeque::new(),
+            router_alerts: Vec::new(),
+            ident: IdentGenerator::new(),
+            reassembler: Reassembler::new(),
+            stats: StackStats::default(),
+            rx_buf: vec![0; FRAME_BUFFER_SIZE],
+        }
+    }
+
+    /// 添加一个接口并生成它的网段路由，返回接口编号
+    pub fn add_interface(&mut self, interface: NetworkInterface) -> usize {
+        let id = self.ports.len();
+        self.routes
+            .add(Route::subnet(interface.ip, interface.netmask, id));
+        self.ports.push(Port::new(interface));
+        id
+    }
+
+    /// 主接口
+    pub fn interface(&self) -> &NetworkInterface {
+        &self.ports[PRIMARY].interface
+    }
+
+    pub fn interface_at(&self, id: usize) -> Option<&NetworkInterface> {
+        self.ports.get(id).map(|port| &port.interface)
+    }
+
+    pub fn interface_count(&self) -> usize {
+        self.ports.len()
+    }
+
+    /// 启用所有接口：对接口地址做冲突检测（RFC 5227），通过后发送免费 ARP 通告；
+    /// 同时自动配置 IPv6 链路本地地址，并发送路由器请求获取全局前缀
+    pub fn up(&mut self, now: Instant) {
+        for port in &mut self.ports {
+            port.arp.probe_address(now);
+            port.ndp.autoconfigure(now);
+            port.ndp.solicit_routers(now);
+        }
+    }
+
+    /// 给接口手动添加 IPv6 地址，不做重复地址检测
+    pub fn add_ipv6_address(&mut self, id: usize, addr: Ipv6Addr) {
+        if let Some(port) = self.ports.get_mut(id) {
+            port.ndp.add_address(addr);
+        }
+    }
+
+    /// 修改主接口地址，新地址同样要先通过冲突检测
+    pub fn set_ip(&mut self, ip: Ipv4Addr, now: Instant) {
+        self.set_interface_ip(PRIMARY, ip, now);
+        self.sockets.set_local_ip(ip);
+        self.rarp.set_ip(ip);
+    }
+
+    /// 修改接口地址，网段路由跟着地址变化
+    pub fn set_interface_ip(&mut self, id: usize, ip: Ipv4Addr, now: Instant) {
+        let Some(port) = self.ports.get_mut(id) else {
+            return;
+        };
+        self.routes
+            .remove(port.interface.ip, port.interface.netmask);
+        self.routes
+            .add(Route::subnet(ip, port.interface.netmask, id));
+        port.interface.ip = ip;
+        port.arp.set_ip(ip, now);
+    }
+
+    /// 通过 RARP 获取本机地址，收到响应后自动设置接口地址
+    pub fn request_address(&mut self, now: Instant) {
+        self.rarp.start_client(now);
+    }
+
+    /// 设置默认网关，网段外的包都从网关所在的接口发给网关
+    pub fn set_default_gateway(&mut self, gateway: Ipv4Addr) {
+        let id = self
+            .ports
+            .iter()
+            .position(|port| port.on_link(gateway))
+            .unwrap_or(PRIMARY);
+        self.routes.set_default_gateway(gateway, id);
+    }
+
+    /// 开启后转发不是发给本机的包
+    pub fn set_forwarding(&mut self, forwarding: bool) {
+        self.forwarding = forwarding;
+    }
+
+    pub fn forwarding(&self) -> bool {
+        self.forwarding
+    }
+
+    pub fn routes(&self) -> &RoutingTable {
+        &self.routes
+    }
+
+    pub fn routes_mut(&mut self) -> &mut RoutingTable {
+        &mut self.routes
+    }
+
+    /// 主接口的 ARP 模块
+    pub fn arp(&self) -> &ArpModule {
+        &self.ports[PRIMARY].arp
+    }
+
+    pub fn arp_mut(&mut self) -> &mut ArpModule {
+        &mut self.ports[PRIMARY].arp
+    }
+
+    pub fn arp_at(&self, id: usize) -> Option<&ArpModule> {
+        self.ports.get(id).map(|port| &port.arp)
+    }
+
+    pub fn arp_at_mut(&mut self, id: usize) -> Option<&mut ArpModule> {
+        self.ports.get_mut(id).map(|port| &mut port.arp)
+    }
+
+    /// 主接口的 NDP 模块
+    pub fn ndp(&self) -> &NdpModule {
+        &self.ports[PRIMARY].ndp
+    }
+
+    pub fn ndp_mut(&mut self) -> &mut NdpModule {
+        &mut self.ports[PRIMARY].ndp
+    }
+
+    pub fn ndp_at(&self, id: usize) -> Option<&NdpModule> {
+        self.ports.get(id).map(|port| &port.ndp)
+    }
+
+    pub fn ndp_at_mut(&mut self, id: usize) -> Option<&mut NdpModule> {
+        self.ports.get_mut(id).map(|port| &mut port.ndp)
+    }
+
+    pub fn rarp_mut(&mut self) -> &mut RarpModule {
+        &mut self.rarp
+    }
+
+    pub fn sockets(&self) -> &SocketManager {
+        &self.sockets
+    }
+
+    pub fn sockets_mut(&mut self) -> &mut SocketManager {
+        &mut self.sockets
+    }
+
+    /// 取出转发时遇到的带路由器警告选项的包（RFC 2113），交给 IGMP、RSVP 等处理，
+    /// 这些包本身照常转发
+    pub fn take_router_alerts(&mut self) -> Vec<(usize, Ipv4Packet)> {
+        std::mem::take(&mut self.router_alerts)
+    }
+
+    pub fn stats(&self) -> &StackStats {
+        &self.stats
+    }
+
+    /// 驱动协议栈一次：先发出应用积压的数据，再从每个接口读取并处理一帧，
+    /// 最后处理定时器并发送产生的包。
+    /// 返回是否读到了帧；非阻塞设备没有数据时返回 false，只有设备本身出错才返回错误
+    pub fn poll(&mut self, now: Instant) -> Result<bool> {
+        self.flush(now);
+        let mut buf = std::mem::take(&mut self.rx_buf);
+        let mut received = false;
+        for id in 0..self.ports.len() {
+            match self.ports[id].interface.recv_frame(&mut buf) {
+                Ok(len) => {
+                    self.receive_frame_on(id, &buf[..len], now);
+                    received = true;
+                }
+                Err(StackError::Io(e)) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => {
+                    self.rx_buf = buf;
+                    return Err(e);
+                }
+            }
+        }
+        self.rx_buf = buf;
+        self.flush(now);
+        Ok(received)
+    }
+
+    /// 循环驱动协议栈，每处理完一帧调用一次 app 处理应用逻辑
+    pub fn run<F>(&mut self, mut app: F) -> Result<()>
+    where
+        F: FnMut(&mut SocketManager),
+    {
+        loop {
+            let received = self.poll(Instant::now())?;
+            app(&mut self.sockets);
+            if !received {
+                std::thread::sleep(IDLE_SLEEP);
+            }
+        }
+    }
+
+    /// 处理主接口收到的以太网帧
+    pub fn receive_frame(&mut self, data: &[u8], now: Instant) {
+        self.receive_frame_on(PRIMARY, data, now);
+    }
+
+    /// 处理接口 id 收到的以太网帧，出错时只记录日志和计数
+    pub fn receive_frame_on(&mut self, id: usize, data: &[u8], now: Instant) {
+        self.stats.rx_frames += 1;
+        if let Err(e) = self.process_frame(id, data, now) {
+            self.stats.rx_errors += 1;
+            warn!("Drop frame of {} bytes: {}", data.len(), e);
+        }
+    }
+
+    /// 处理定时器，把所有待发送的包交给设备（发给本机的包直接回环）
+    pub fn flush(&mut self, now: Instant) {
+        let mut budget = LOOPBACK_BUDGET;
+        loop {
+            self.sockets.poll(now);
+            for ip in self.sockets.tcp_mut().take_confirmed() {
+                for port in &mut self.ports {
+                    port.arp.confirm(ip, now);
+                }
+            }
+            self.rarp.poll(now);
+            for request in self.rarp.take_outgoing() {
+                self.send_frame(PRIMARY, BROADCAST_MAC, EtherType::RARP, request.to_bytes());
+            }
+            if let Some(ip) = self.rarp.take_assigned() {
+                self.set_ip(ip, now);
+            }
+            for id in 0..self.ports.len() {
+                self.ports[id].arp.poll(now);
+                for packet in self.ports[id].arp.take_unreachable() {
+                    self.stats.tx_dropped += 1;
+                    if let Ok(ipv4) = Ipv4Packet::parse(&packet) {
+                        self.send_unreachable(UnreachableCode::HostUnreachable, &ipv4, &packet);
+                    }
+                }
+                self.ports[id].ndp.poll(now);
+                for packet in self.ports[id].ndp.take_unreachable() {
+                    self.stats.tx_dropped += 1;
+                    if let Ok(ipv6) = Ipv6Packet::parse(&packet) {
+                        let code =
+                            Icmpv6UnreachableCode::to_u8(Icmpv6UnreachableCode::AddressUnreachable);
+                        let icmp = IcmpPacket::build_error_v6(
+                            Icmpv6Type::DestinationUnreachable,
+                            code,
+                            0,
+                            &packet,
+                        );
+                        self.send_error_v6(id, &ipv6, icmp);
+                    }
+                }
+            }
+            self.reassembler.poll(now);
+            for first in self.reassembler.take_timed_out() {
+                self.stats.rx_dropped += 1;
+                self.send_time_exceeded(TimeExceededCode::FragmentReassembly, &first);
+            }
+
+            let mut packets = std::mem::take(&mut self.outgoing);
+            packets.extend(self.sockets.take_outgoing());
+            for mut packet in packets {
+                packet.identification = self.ident.next(packet.dst_addr, packet.protocol);
+                self.transmit(packet, now);
+            }
+            for (id, packet) in std::mem::take(&mut self.outgoing_v6) {
+                self.transmit_v6(id, packet, now);
+            }
+            for id in 0..self.ports.len() {
+                for (dst_mac, request) in self.ports[id].arp.take_outgoing() {
+                    self.send_frame(id, dst_mac, EtherType::ARP, request.to_bytes());
+                }
+                for (dst_mac, packet) in self.ports[id].arp.take_resolved() {
+                    self.send_frame(id, dst_mac, EtherType::IPv4, packet);
+                }
+                for (dst_mac, packet) in self.ports[id].ndp.take_outgoing() {
+                    self.send_frame(id, dst_mac, EtherType::IPv6, packet.to_bytes());
+                }
+                for (dst_mac, packet) in self.ports[id].ndp.take_resolved() {
+                    self.send_frame(id, dst_mac, EtherType::IPv6, packet);
+                }
+            }
+            if self.loopback.is_empty() || budget == 0 {
+                break;
+            }
+            while budget > 0
+                && let Some(packet) = self.loopback.pop_front()
+            {
+                budget -= 1;
+                let data = packet.to_bytes();
+                if let Err(e) = self.process_ipv4(PRIMARY, &data, false, now) {
+                    self.stats.rx_errors += 1;
+                    warn!("Drop loopback packet: {}", e);
+                }
+            }
+        }
+    }
+
+    fn process_frame(&mut self, id: usize, data: &[u8], now: Instant) -> Result<()> {
+        let frame = EthernetFrame::parse(data)?;
+        if !self.ports[id].accepts_mac(frame.dst_mac) {
+            self.stats.rx_dropped += 1;
+            return Ok(());
+        }
+        match frame.classify_payload() {
+            FramePayload::Arp(payload) => self.process_arp(id, &payload, now),
+            FramePayload::Rarp(payload) if id == PRIMARY => self.process_rarp(&payload),
+            FramePayload::Ipv4(payload) => {
+                // 链路层广播和组播帧不转发，也不回复差错（RFC 1812 第 5.3.4 节）
+                let link_broadcast = frame.dst_mac[0] & 0x01 != 0;
+                self.process_ipv4(id, &payload, link_broadcast, now)
+            }
+            FramePayload::Ipv6(payload) => self.process_ipv6(id, &payload, now),
+            _ => {
+                self.stats.rx_dropped += 1;
+                debug!("Unsupported EtherType {:#06x}", frame.ether_type);
+                Ok(())
+            }
+        }
+    }
+
+    fn process_arp(&mut self, id: usize, data: &[u8], now: Instant) -> Result<()> {
+        let arp = ArpPacket::parse(data)?;
+        if let Some(reply) = self.ports[id].arp.handle_packet(&arp, now) {
+            self.send_frame(id, arp.sender_mac, EtherType::ARP, reply);
+            info!("Send ARP reply to {}", arp.sender_ip);
+        }
+        Ok(())
+    }
+
+    fn process_rarp(&mut self, data: &[u8]) -> Result<()> {
+        let rarp = ArpPacket::parse(data)?;
+        if let Some(reply) = self.rarp.handle_packet(&rarp) {
+            self.send_frame(PRIMARY, rarp.sender_mac, EtherType::RARP, reply.to_bytes());
+        }
+        Ok(())
+    }
+
+    /// 处理接口 id 收到的 IP 包，不是发给本机的包在开启转发时转发出去。
+    /// link_broadcast 表示包装在链路层广播帧里
+    fn process_ipv4(
+        &mut self,
+        id: usize,
+        data: &[u8],
+        link_broadcast: bool,
+        now: Instant,
+    ) -> Result<()> {
+        let packet = Ipv4Packet::parse(data)?;
+        if !self.is_local(packet.dst_addr) {
+            if self.forwarding {
+                self.router_alert(id, &packet);
+            }
+            if self.forwarding && !link_broadcast {
+                self.forward(id, packet, data, now);
+            } else {
+                self.stats.rx_dropped += 1;
+                debug!("Ipv4 packet to {} is not for us", packet.dst_addr);
+            }
+            return Ok(());
+        }
+        if self.source_route(id, &packet, now) {
+            return Ok(());
+        }
+        let reassembled;
+        let (packet, data) = if packet.is_fragment() {
+            let Some(packet) = self.reassembler.push(packet, now)? else {
+                return Ok(());
+            };
+            reassembled = packet.to_bytes();
+            (packet, reassembled.as_slice())
+        } else {
+            (packet, data)
+        };
+        match packet.protocol {
+            1 => self.process_icmp(&packet),
+            6 => {
+                let segment = TcpSegment::parse(&packet.payload)?;
+                self.sockets
+                    .handle_tcp_segment(packet.src_addr, packet.dst_addr, &segment, now)
+            }
+            17 => {
+                let datagram = UdpDatagram::parse(&packet.payload)?;
+                if !self
+                    .sockets
+                    .handle_udp_datagram(packet.src_addr, packet.dst_addr, &datagram)?
+                    && !link_broadcast
+                {
+                    self.send_unreachable(UnreachableCode::PortUnreachable, &packet, data);
+                }
+                Ok(())
+            }
+            protocol => {
+                self.stats.rx_dropped += 1;
+                debug!("Unsupported IP protocol {}", protocol);
+                if !link_broadcast {
+                    self.send_unreachable(UnreachableCode::ProtocolUnreachable, &packet, data);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn process_icmp(&mut self, packet: &Ipv4Packet) -> Result<()> {
+        let icmp = IcmpPacket::parse(&packet.payload)?;
+        if IcmpType::from_u8(icmp.icmp_type) == Some(IcmpType::EchoRequest) {
+            // 发给某个接口地址的 ping 从这个地址回复，广播 ping 从出接口回复
+            let src = if self.is_interface_addr(packet.dst_addr) {
+                packet.dst_addr
+            } else {
+                self.source_addr(packet.src_addr)
+            };
+            let reply = IcmpPacket::build_reply(&icmp);
+            let mut reply =
+                Ipv4Packet::build(src, packet.src_addr, 1, DEFAULT_TTL, reply.to_bytes());
+            // ping -R/-T：带回请求中的路由记录和时间戳并记录本机（RFC 1122 第 3.2.2.6 节）
+            let mut options: Vec<Ipv4Option> = packet
+                .parse_options()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|option| {
+                    matches!(
+                        option,
+                        Ipv4Option::RecordRoute(_) | Ipv4Option::Timestamp(_)
+                    )
+                })
+                .collect();
+            if !options.is_empty() {
+                let timestamp = ip_timestamp();
+                for option in &mut options {
+                    option.record(src, timestamp);
+                }
+                reply.set_options(&options);
+            }
+            self.outgoing.push(reply);
+            debug!("Send ping reply to {}", packet.src_addr);
+        }
+        Ok(())
+    }
+
+    /// 处理接口 id 收到的 IPv6 包：只接收发给接口地址和已加入组播组的包，
+    /// 扩展头部要求丢弃的包和分片不交给上层，不认识的上层协议回复参数问题
+    fn process_ipv6(&mut self, id: usize, data: &[u8], now: Instant) -> Result<()> {
+        let packet = Ipv6Packet::parse(data)?;
+        let ndp = &self.ports[id].ndp;
+        if !ndp.has_address(packet.dst_addr) && !ndp.is_member(packet.dst_addr) {
+            self.stats.rx_dropped += 1;
+            debug!("Ipv6 packet to {} is not for us", packet.dst_addr);
+            return Ok(());
+        }
+        let chain = packet.extensions()?;
+        if let Some(problem) = chain.problem {
+            self.stats.rx_dropped += 1;
+            debug!("Drop Ipv6 packet from {}: {:?}", packet.src_addr, problem);
+            let (code, pointer) = match problem {
+                ExtensionProblem::UnrecognizedOption { pointer } => {
+                    (ParameterProblemCode::UnrecognizedOption, pointer)
+                }
+                ExtensionProblem::UnrecognizedRouting { pointer } => {
+                    (ParameterProblemCode::ErroneousHeader, pointer)
+                }
+                ExtensionProblem::Discard | ExtensionProblem::Fragmented => return Ok(()),
+            };
+            let icmp = IcmpPacket::build_error_v6(
+                Icmpv6Type::ParameterProblem,
+                ParameterProblemCode::to_u8(code),
+                pointer as u32,
+                data,
+            );
+            self.send_error_v6(id, &packet, icmp);
+            return Ok(());
+        }
+        let upper = &packet.payload[chain.offset..];
+        match chain.protocol {
+            IPV6_NEXT_ICMPV6 => self.process_icmpv6(id, &packet, upper, now),
+            // 还没有 IPv6 socket，所有端口都不可达
+            IPV6_NEXT_TCP | IPV6_NEXT_UDP => {
+                if chain.protocol == IPV6_NEXT_UDP {
+                    UdpDatagram::parse(upper)?
+                        .verify_checksum_ipv6(packet.src_addr, packet.dst_addr)?;
+                }
+                self.stats.rx_dropped += 1;
+                let code = Icmpv6UnreachableCode::to_u8(Icmpv6UnreachableCode::PortUnreachable);
+                let icmp =
+                    IcmpPacket::build_error_v6(Icmpv6Type::DestinationUnreachable, code, 0, data);
+                self.send_error_v6(id, &packet, icmp);
+                Ok(())
+            }
+            IPV6_NEXT_NONE => Ok(()),
+            protocol => {
+                self.stats.rx_dropped += 1;
+                debug!("Unsupported IPv6 next header {}", protocol);
+                let code =
+                    ParameterProblemCode::to_u8(ParameterProblemCode::UnrecognizedNextHeader);
+                let icmp = IcmpPacket::build_error_v6(
+                    Icmpv6Type::ParameterProblem,
+                    code,
+                    chain.pointer as u32,
+                    data,
+                );
+                self.send_error_v6(id, &packet, icmp);
+                Ok(())
+            }
+        }
+    }
+
+    /// 处理 ICMPv6 报文：回复 ping，邻居发现报文交给接口的 NDP 模块
+    fn process_icmpv6(
+        &mut self,
+        id: usize,
+        packet: &Ipv6Packet,
+        data: &[u8],
+        now: Instant,
+    ) -> Result<()> {
+        let icmp = IcmpPacket::parse_v6(data, packet.src_addr, packet.dst_addr)?;
+        match Icmpv6Type::from_u8(icmp.icmp_type) {
+            Some(Icmpv6Type::EchoRequest) => {
+                let ndp = &self.ports[id].ndp;
+                // 发给组播组的 ping 从出接口的地址回复
+                let src = if packet.dst_addr.is_multicast() {
+                    match ndp.source_address(packet.src_addr) {
+                        Some(src) => src,
+                        None => return Ok(()),
+                    }
+                } else {
+                    packet.dst_addr
+                };
+                let mut reply = IcmpPacket::build_reply(&icmp);
+                reply.icmp_type = Icmpv6Type::to_u8(Icmpv6Type::EchoReply);
+                let reply = Ipv6Packet::build(
+                    src,
+                    packet.src_addr,
+                    IPV6_NEXT_ICMPV6,
+                    ndp.hop_limit(),
+                    reply.to_bytes_v6(src, packet.src_addr),
+                );
+                self.outgoing_v6.push((id, reply));
+                debug!("Send ping6 reply to {}", packet.src_addr);
+            }
+            Some(
+                Icmpv6Type::RouterSolicitation
+                | Icmpv6Type::RouterAdvertisement
+                | Icmpv6Type::NeighborSolicitation
+                | Icmpv6Type::NeighborAdvertisement
+                | Icmpv6Type::Redirect,
+            ) => {
+                let message = NdpMessage::parse(&icmp)?;
+                self.ports[id].ndp.handle_message(packet, &message, now);
+            }
+            _ => debug!("Ignore ICMPv6 type {}", icmp.icmp_type),
+        }
+        Ok(())
+    }
+
+    /// 转发从接口 in_port 收到的包（RFC 1812）：TTL 减一并增量更新校验和，
+    /// TTL 耗尽时回复超时；包从收到它的接口转发回去时，告诉源主机更好的下一跳
+    fn forward(&mut self, in_port: usize, packet: Ipv4Packet, data: &[u8], now: Instant) {
+        let (src, dst) = (packet.src_addr, packet.dst_addr);
+        if !Self::forwardable(src) || !Self::forwardable(dst) {
+            self.stats.rx_dropped += 1;
+            debug!("Not forwarding packet from {} to {}", src, dst);
+            return;
+        }
+        if packet.ttl <= 1 {
+            self.stats.rx_dropped += 1;
+            self.send_time_exceeded(TimeExceededCode::TtlExceeded, &packet);
+            return;
+        }
+        let Some((out_port, next_hop)) = self.route(dst) else {
+            self.stats.rx_dropped += 1;
+            debug!("No route to forward packet to {}", dst);
+            self.send_unreachable(UnreachableCode::NetUnreachable, &packet, data);
+            return;
+        };
+        let mut options = match packet.parse_options() {
+            Ok(options) => options,
+            Err(e) => {
+                self.stats.rx_dropped += 1;
+                debug!("Not forwarding packet to {}: {}", dst, e);
+                return;
+            }
+        };
+        // 源主机和下一跳在同一网段，可以直接发给下一跳（RFC 1812 第 5.2.7.2 节），
+        // 源路由指定了路径，不发送重定向
+        let source_routed = options.iter().any(|option| {
+            matches!(
+                option,
+                Ipv4Option::LooseSourceRoute(_) | Ipv4Option::StrictSourceRoute(_)
+            )
+        });
+        if out_port == in_port
+            && next_hop != src
+            && !source_routed
+            && self.ports[in_port].on_link(src)
+        {
+            self.send_redirect(next_hop, &packet, data);
+        }
+        self.stats.forwarded += 1;
+        let mut forwarded = data[..packet.total_length as usize].to_vec();
+        if options.is_empty() && forwarded.len() <= self.ports[out_port].interface.mtu {
+            Ipv4Packet::decrement_ttl(&mut forwarded);
+            self.output(out_port, next_hop, forwarded, now);
+            return;
+        }
+        // 有选项时在路由记录和时间戳中记录出接口地址，超过出接口 MTU 时分片，
+        // 这两种情况都重新构造头部
+        let addr = self.ports[out_port].interface.ip;
+        let timestamp = ip_timestamp();
+        for option in &mut options {
+            option.record(addr, timestamp);
+        }
+        let mut packet = packet;
+        packet.ttl -= 1;
+        if !options.is_empty() {
+            packet.set_options(&options);
+        }
+        self.send_fragments(out_port, next_hop, packet, now);
+    }
+
+    /// 路由器模式下，带路由器警告选项的包除了转发还要交给本机检查（RFC 2113），
+    /// 发给组播组、TTL 为 1 的 IGMP 报文也不例外
+    fn router_alert(&mut self, in_port: usize, packet: &Ipv4Packet) {
+        let alert = packet.parse_options().is_ok_and(|options| {
+            options
+                .iter()
+                .any(|option| matches!(option, Ipv4Option::RouterAlert(_)))
+        });
+        if !alert {
+            return;
+        }
+        if self.router_alerts.len() >= ROUTER_ALERT_QUEUE_LEN {
+            debug!(
+                "Router alert queue full, not delivering packet to {}",
+                packet.dst_addr
+            );
+            return;
+        }
+        self.router_alerts.push((in_port, packet.clone()));
+    }
+
+    /// 处理发给本机的源路由包（RFC 791 第 3.1 节）：路由还没走完时，
+    /// 把目标地址换成下一跳，在槽位中记录出接口地址后继续转发。
+    /// 返回 true 表示包已经转发或丢弃，不再交给上层
+    fn source_route(&mut self, in_port: usize, packet: &Ipv4Packet, now: Instant) -> bool {
+        let Ok(mut options) = packet.parse_options() else {
+            return false;
+        };
+        let Some((route, strict)) = options.iter_mut().find_map(|option| match option {
+            Ipv4Option::LooseSourceRoute(route) if route.next().is_some() => Some((route, false)),
+            Ipv4Option::StrictSourceRoute(route) if route.next().is_some() => Some((route, true)),
+            _ => None,
+        }) else {
+            return false;
+        };
+        if !self.forwarding {
+            self.stats.rx_dropped += 1;
+            debug!(
+                "Not forwarding source routed packet from {}",
+                packet.src_addr
+            );
+            return true;
+        }
+        let Some(next) = route.next() else {
+            return false;
+        };
+        // 严格源路由的下一跳必须直接相连
+        let out = self
+            .route(next)
+            .filter(|&(_, next_hop)| !strict || next_hop == next);
+        let Some((out_port, _)) = out else {
+            self.stats.rx_dropped += 1;
+            let original = packet.to_bytes();
+            self.send_unreachable(UnreachableCode::SourceRouteFailed, packet, &original);
+            return true;
+        };
+        route.record(self.ports[out_port].interface.ip);
+        let mut packet = packet.clone();
+        packet.dst_addr = next;
+        packet.set_options(&options);
+        let data = packet.to_bytes();
+        self.forward(in_port, packet, &data, now);
+        true
+    }
+
+    /// 不转发的地址：未指定、广播、多播、回环和链路本地地址
+    fn forwardable(addr: Ipv4Addr) -> bool {
+        !(addr.is_unspecified()
+            || addr.is_broadcast()
+            || addr.is_multicast()
+            || addr.is_loopback()
+            || addr.is_link_local())
+    }
+
+    /// 回复目标不可达，original 是收到的完整 IP 数据报
+    fn send_unreachable(&mut self, code: UnreachableCode, packet: &Ipv4Packet, original: &[u8]) {
+        if self.may_send_error(packet) {
+            let icmp = IcmpPacket::build_unreachable(code, original);
+            self.send_error(packet.src_addr, icmp);
+        }
+    }
+
+    /// 回复超时，original 是 TTL 耗尽的包或超时数据报的第一片
+    fn send_time_exceeded(&mut self, code: TimeExceededCode, original: &Ipv4Packet) {
+        if self.may_send_error(original) {
+            let icmp = IcmpPacket::build_time_exceeded(code, &original.to_bytes());
+            self.send_error(original.src_addr, icmp);
+        }
+    }
+
+    /// 告诉源主机发往这个目标的包应该直接交给 gateway
+    fn send_redirect(&mut self, gateway: Ipv4Addr, packet: &Ipv4Packet, original: &[u8]) {
+        if self.may_send_error(packet) {
+            let icmp = IcmpPacket::build_redirect(RedirectCode::Host, gateway, original);
+            self.send_error(packet.src_addr, icmp);
+        }
+    }
+
+    /// 在 IcmpPacket::may_send_error 之外，发给任一接口网段定向广播的包也不回复差错
+    fn may_send_error(&self, packet: &Ipv4Packet) -> bool {
+        IcmpPacket::may_send_error(packet)
+            && !self
+                .ports
+                .iter()
+                .any(|port| port.directed_broadcast() == packet.dst_addr)
+    }
+
+    fn send_error(&mut self, dst: Ipv4Addr, icmp: IcmpPacket) {
+        self.outgoing.push(Ipv4Packet::build(
+            self.source_addr(dst),
+            dst,
+            1,
+            DEFAULT_TTL,
+            icmp.to_bytes(),
+        ));
+    }
+
+    /// 回复 ICMPv6 差错报文，从收到原始包的接口发回去
+    fn send_error_v6(&mut self, id: usize, packet: &Ipv6Packet, icmp: IcmpPacket) {
+        if !IcmpPacket::may_send_error_v6(packet, &icmp) {
+            return;
+        }
+        let ndp = &self.ports[id].ndp;
+        let Some(src) = ndp.source_address(packet.src_addr) else {
+            return;
+        };
+        let dst = packet.src_addr;
+        let error = Ipv6Packet::build(
+            src,
+            dst,
+            IPV6_NEXT_ICMPV6,
+            ndp.hop_limit(),
+            icmp.to_bytes_v6(src, dst),
+        );
+        self.outgoing_v6.push((id, error));
+    }
+
+    /// 是否是发给本机的地址：接口地址、受限广播或接口网段的定向广播
+    fn is_local(&self, addr: Ipv4Addr) -> bool {
+        addr.is_broadcast()
+            || self
+                .ports
+                .iter()
+                .any(|port| port.interface.ip == addr || port.directed_broadcast() == addr)
+    }
+
+    fn is_interface_addr(&self, addr: Ipv4Addr) -> bool {
+        self.ports.iter().any(|port| port.interface.ip == addr)
+    }
+
+    /// 查路由表得到出接口和下一跳
+    fn route(&self, dst: Ipv4Addr) -> Option<(usize, Ipv4Addr)> {
+        self.routes
+            .lookup(dst)
+            .filter(|route| route.interface < self.ports.len())
+            .map(|route| (route.interface, route.next_hop(dst)))
+    }
+
+    /// 发往 dst 的包使用出接口的地址作为源地址，没有路由时用主接口的地址
+    fn source_addr(&self, dst: Ipv4Addr) -> Ipv4Addr {
+        let id = self.route(dst).map_or(PRIMARY, |(id, _)| id);
+        self.ports[id].interface.ip
+    }
+
+    /// 发送本机产生的 IP 包：发给本机的放进回环队列，其余查路由表得到出接口和下一跳
+    fn transmit(&mut self, packet: Ipv4Packet, now: Instant) {
+        let dst = packet.dst_addr;
+        if self.is_interface_addr(dst) || dst.is_loopback() {
+            self.loopback.push_back(packet);
+            return;
+        }
+        let route = if dst.is_broadcast() {
+            Some((PRIMARY, dst))
+        } else {
+            self.route(dst)
+        };
+        let Some((port, next_hop)) = route else {
+            self.stats.tx_dropped += 1;
+            warn!("No route to {}", dst);
+            let original = packet.to_bytes();
+            self.send_unreachable(UnreachableCode::NetUnreachable, &packet, &original);
+            return;
+        };
+        self.send_fragments(port, next_hop, packet, now);
+    }
+
+    /// 按出接口的 MTU 分片后发送，设置了 DF 时丢弃并回复需要分片
+    fn send_fragments(
+        &mut self,
+        port: usize,
+        next_hop: Ipv4Addr,
+        packet: Ipv4Packet,
+        now: Instant,
+    ) {
+        let mtu = self.ports[port].interface.mtu;
+        let fragments = match packet.fragment(mtu) {
+            Ok(fragments) => fragments,
+            Err(e) => {
+                self.stats.tx_dropped += 1;
+                warn!("Drop packet to {}: {}", packet.dst_addr, e);
+                if self.may_send_error(&packet) {
+                    let icmp =
+                        IcmpPacket::build_fragmentation_needed(mtu as u16, &packet.to_bytes());
+                    self.send_error(packet.src_addr, icmp);
+                }
+                return;
+            }
+        };
+        for fragment in fragments {
+            self.output(port, next_hop, fragment.to_bytes(), now);
+        }
+    }
+
+    /// 从接口 port 发出本机产生的 IPv6 包：由 NDP 模块选择下一跳并解析 MAC，
+    /// 缓存未命中时排队等待邻居通告
+    fn transmit_v6(&mut self, port: usize, packet: Ipv6Packet, now: Instant) {
+        let ndp = &mut self.ports[port].ndp;
+        let Some(next_hop) = ndp.next_hop(packet.dst_addr) else {
+            self.stats.tx_dropped += 1;
+            warn!("No route to {}", packet.dst_addr);
+            return;
+        };
+        let bytes = packet.to_bytes();
+        match ndp.resolve(next_hop, now) {
+            Some(dst_mac) => self.send_frame(port, dst_mac, EtherType::IPv6, bytes),
+            None => ndp.queue_packet(next_hop, bytes, now),
+        }
+    }
+
+    /// 从接口 port 发出一个 IP 包：广播直接发送，其余查 ARP 缓存得到下一跳 MAC，
+    /// 缓存未命中时交给 ARP 模块排队等待解析
+    fn output(&mut self, port: usize, next_hop: Ipv4Addr, packet: Vec<u8>, now: Instant) {
+        let dst_mac =
+            if next_hop.is_broadcast() || next_hop == self.ports[port].directed_broadcast() {
+                Some(BROADCAST_MAC)
+            } else {
+                self.ports[port].arp.resolve(next_hop, now)
+            };
+        match dst_mac {
+            Some(dst_mac) => self.send_frame(port, dst_mac, EtherType::IPv4, packet),
+            None => self.ports[port].arp.queue_packet(next_hop, packet, now),
+        }
+    }
+
+    fn send_frame(
+        &mut self,
+        port: usize,
+        dst_mac: MacAddr,
+        ether_type: EtherType,
+        payload: Vec<u8>,
+    ) {
+        let interface = &mut self.ports[port].interface;
+        let frame = EthernetFrame::build(
+            dst_mac,
+            interface.mac,
+            EtherType::to_u16(ether_type),
+            payload,
+        );
+        match interface.send_frame(&frame.to_bytes()) {
+            Ok(_) => self.stats.tx_frames += 1,
+            Err(e) => {
+                self.stats.tx_errors += 1;
+                warn!("Failed to send frame: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::NetworkDevice;
+    use crate::ip::options::RouteOption;
+    use crate::ipv6::{ExtensionHeader, Ipv6TlvOption};
+    use crate::socket::SocketType;
+    use std::cell::RefCell;
+    use std::net::SocketAddrV4;
+    use std::rc::Rc;
+
+    const OUR_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 10, 2);
+    const OUR_MAC: MacAddr = [0x42; 6];
+    const PEER_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 10, 1);
+    const PEER_MAC: MacAddr = [0x02, 0, 0, 0, 0, 1];
+
+    /// 记录发出帧的设备，没有数据可读
+    #[derive(Clone, Default)]
+    struct MockDevice {
+        sent: Rc<RefCell<Vec<Vec<u8>>>>,
+    }
+
+    impl NetworkDevice for MockDevice {
+        fn recv(&mut self, _buf: &mut [u8]) -> Result<usize> {
+            Err(StackError::Io(std::io::Error::from(ErrorKind::WouldBlock)))
+        }
+        fn send(&mut self, buf: &[u8]) -> Result<usize> {
+            self.sent.borrow_mut().push(buf.to_vec());
+            Ok(buf.len())
+        }
+    }
+
+    /// 点对点链路的一端，发出的帧进入对端的接收队列
+    #[derive(Clone, Default)]
+    struct Wire {
+        rx: Rc<RefCell<VecDeque<Vec<u8>>>>,
+        tx: Rc<RefCell<VecDeque<Vec<u8>>>>,
+    }
+
+    impl Wire {
+        fn pair() -> (Wire, Wire) {
+            let a = Wire::default();
+            let b = Wire {
+                rx: a.tx.clone(),
+                tx: a.rx.clone(),
+            };
+            (a, b)
+        }
+    }
+
+    impl NetworkDevice for Wire {
+        fn recv(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let frame = self
+                .rx
+                .borrow_mut()
+                .pop_front()
+                .ok_or_else(|| StackError::Io(std::io::Error::from(ErrorKind::WouldBlock)))?;
+            buf[..frame.len()].copy_from_slice(&frame);
+            Ok(frame.len())
+        }
+        fn send(&mut self, buf: &[u8]) -> Result<usize> {
+            self.tx.borrow_mut().push_back(buf.to_vec());
+            Ok(buf.len())
+        }
+    }
+
+    fn wired(wire: Wire, ip: [u8; 4], mac: u8) -> NetworkInterface {
+        NetworkInterface::new(
+            Box::new(wire),
+            Ipv4Addr::from(ip),
+            [0x02, 0, 0, 0, 0, mac],
+            Ipv4Addr::new(255, 255, 255, 0),
+            1500,
+        )
+    }
+
+    fn stack() -> (Stack, MockDevice) {
+        let device = MockDevice::default();
+        let interface = NetworkInterface::new(
+            Box::new(device.clone()),
+            OUR_IP,
+            OUR_MAC,
+            Ipv4Addr::new(255, 255, 255, 0),
+            1500,
+        );
+        (Stack::new(interface), device)
+    }
+
+    fn ipv4_frame(packet: Ipv4Packet) -> Vec<u8> {
+        EthernetFrame::build(
+            OUR_MAC,
+            PEER_MAC,
+            EtherType::to_u16(EtherType::IPv4),
+            packet.to_bytes(),
+        )
+        .to_bytes()
+    }
+
+    #[test]
+    fn test_malformed_frames_are_counted() {
+        let (mut stack, device) = stack();
+        let now = Instant::now();
+        stack.receive_frame(&[0; 5], now);
+        let truncated = EthernetFrame::build(
+            OUR_MAC,
+            PEER_MAC,
+            EtherType::to_u16(EtherType::IPv4),
+            vec![0x45, 0],
+        );
+        stack.receive_frame(&truncated.to_bytes(), now);
+        assert_eq!(stack.stats().rx_frames, 2);
+        assert_eq!(stack.stats().rx_errors, 2);
+
+        // 之后仍然正常处理 ARP 请求和 ping
+        let request = ArpPacket::build_request(PEER_MAC, PEER_IP, OUR_IP);
+        let frame = EthernetFrame::build(
+            BROADCAST_MAC,
+            PEER_MAC,
+            EtherType::to_u16(EtherType::ARP),
+            request.to_bytes(),
+        );
+        stack.receive_frame(&frame.to_bytes(), now);
+        let ping = IcmpPacket {
+            icmp_type: IcmpType::to_u8(IcmpType::EchoRequest),
+            code: 0,
+            checksum: 0,
+            identifier: 1,
+            sequence: 1,
+            payload: b"ping".to_vec(),
+        };
+        stack.receive_frame(
+            &ipv4_frame(Ipv4Packet::build(PEER_IP, OUR_IP, 1, 64, ping.to_bytes())),
+            now,
+        );
+        assert!(!stack.poll(now).unwrap());
+
+        let sent = device.sent.borrow();
+        assert_eq!(sent.len(), 2);
+        let reply = EthernetFrame::parse(&sent[1]).unwrap();
+        assert_eq!(reply.dst_mac, PEER_MAC);
+        let reply = Ipv4Packet::parse(&reply.payload).unwrap();
+        let icmp = IcmpPacket::parse(&reply.payload).unwrap();
+        assert_eq!(icmp.icmp_type, IcmpType::to_u8(IcmpType::EchoReply));
+        assert_eq!(icmp.payload, b"ping");
+    }
+
+    #[test]
+    fn test_loopback_udp() {
+        let (mut stack, device) = stack();
+        let now = Instant::now();
+        let sockets = stack.sockets_mut();
+        let server = sockets.socket(SocketType::Udp);
+        sockets.bind(server, SocketAddrV4::new(OUR_IP, 7)).unwrap();
+        let client = sockets.socket(SocketType::Udp);
+        sockets
+            .sendto(client, b"hello", SocketAddrV4::new(OUR_IP, 7))
+            .unwrap();
+        // 发往没有 socket 的端口，端口不可达报文同样回环给本机，不会发到设备上
+        sockets
+            .sendto(client, b"lost", SocketAddrV4::new(OUR_IP, 9))
+            .unwrap();
+        stack.flush(now);
+
+        let mut buf = [0u8; 16];
+        let (len, from) = stack.sockets_mut().recvfrom(server, &mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hello");
+        assert_eq!(*from.ip(), OUR_IP);
+        assert!(device.sent.borrow().is_empty());
+        assert_eq!(stack.sockets().udp_stats().no_ports, 1);
+    }
+
+    #[test]
+    fn test_no_port_unreachable_for_broadcasts() {
+        let (mut stack, device) = stack();
+        let now = Instant::now();
+        stack.arp_mut().cache_mut().insert(PEER_IP, PEER_MAC, now);
+        let udp_frame = |dst_mac: MacAddr, dst: Ipv4Addr| {
+            let datagram = UdpDatagram::build(5000, 9, b"x".to_vec(), PEER_IP, dst);
+            let packet = Ipv4Packet::build(PEER_IP, dst, 17, 64, datagram.to_bytes());
+            EthernetFrame::build(
+                dst_mac,
+                PEER_MAC,
+                EtherType::to_u16(EtherType::IPv4),
+                packet.to_bytes(),
+            )
+            .to_bytes()
+        };
+
+        // 发给网段定向广播和装在链路层广播帧里的数据报都不回复端口不可达
+        let directed = Ipv4Addr::new(192, 168, 10, 255);
+        stack.receive_frame(&udp_frame(BROADCAST_MAC, directed), now);
+        stack.receive_frame(&udp_frame(OUR_MAC, directed), now);
+        stack.receive_frame(&udp_frame(BROADCAST_MAC, OUR_IP), now);
+        stack.flush(now);
+        assert!(device.sent.borrow().is_empty());
+
+        // 单播数据报照常回复
+        stack.receive_frame(&udp_frame(OUR_MAC, OUR_IP), now);
+        stack.flush(now);
+        let sent = device.sent.borrow();
+        assert_eq!(sent.len(), 1);
+        let reply = EthernetFrame::parse(&sent[0]).unwrap();
+        let reply = Ipv4Packet::parse(&reply.payload).unwrap();
+        let icmp = IcmpPacket::parse(&reply.payload).unwrap();
+        assert_eq!(
+            icmp.icmp_type,
+            IcmpType::to_u8(IcmpType::DestinationUnreachable)
+        );
+    }
+
+    #[test]
+    fn test_arp_resolution_before_send() {
+        let (mut stack, device) = stack();
+        let now = Instant::now();
+        let sockets = stack.sockets_mut();
+        let client = sockets.socket(SocketType::Udp);
+        sockets
+            .sendto(client, b"hello", SocketAddrV4::new(PEER_IP, 7))
+            .unwrap();
+        stack.flush(now);
+
+        // 先广播 ARP 请求，数据报等待解析
+        let request = EthernetFrame::parse(&device.sent.borrow()[0]).unwrap();
+        assert_eq!(request.dst_mac, BROADCAST_MAC);
+        let request = ArpPacket::parse(&request.payload).unwrap();
+        assert_eq!(request.target_ip, PEER_IP);
+        assert_eq!(device.sent.borrow().len(), 1);
+
+        let reply = EthernetFrame::build(
+            OUR_MAC,
+            PEER_MAC,
+            EtherType::to_u16(EtherType::ARP),
+            ArpPacket::build_reply(&request, PEER_MAC).to_bytes(),
+        );
+        stack.receive_frame(&reply.to_bytes(), now);
+        stack.flush(now);
+
+        let sent = device.sent.borrow();
+        assert_eq!(sent.len(), 2);
+        let frame = EthernetFrame::parse(&sent[1]).unwrap();
+        assert_eq!(frame.dst_mac, PEER_MAC);
+        let packet = Ipv4Packet::parse(&frame.payload).unwrap();
+        assert_eq!((packet.dst_addr, packet.protocol), (PEER_IP, 17));
+    }
+
+    #[test]
+    fn test_off_link_via_gateway() {
+        let (mut stack, device) = stack();
+        let now = Instant::now();
+        let remote = SocketAddrV4::new(Ipv4Addr::new(8, 8, 8, 8), 53);
+        let sockets = stack.sockets_mut();
+        let client = sockets.socket(SocketType::Udp);
+        sockets.sendto(client, b"query", remote).unwrap();
+        stack.flush(now);
+
+        // 没有默认路由时网段外的地址不可达
+        assert!(device.sent.borrow().is_empty());
+        assert_eq!(stack.stats().tx_dropped, 1);
+
+        // 有默认网关后解析网关的 MAC 地址
+        stack.set_default_gateway(PEER_IP);
+        stack
+            .sockets_mut()
+            .sendto(client, b"query", remote)
+            .unwrap();
+        stack.flush(now);
+        let request = EthernetFrame::parse(&device.sent.borrow()[0]).unwrap();
+        let request = ArpPacket::parse(&request.payload).unwrap();
+        assert_eq!(request.target_ip, PEER_IP);
+    }
+
+    #[test]
+    fn test_forward_between_interfaces() {
+        let now = Instant::now();
+        let (a_wire, r_wire0) = Wire::pair();
+        let (r_wire1, b_wire) = Wire::pair();
+        let mut a = Stack::new(wired(a_wire, [10, 0, 1, 2], 1));
+        a.set_default_gateway(Ipv4Addr::new(10, 0, 1, 1));
+        let mut router = Stack::new(wired(r_wire0, [10, 0, 1, 1], 2));
+        router.add_interface(wired(r_wire1, [10, 0, 2, 1], 3));
+        router.set_forwarding(true);
+        let mut b = Stack::new(wired(b_wire, [10, 0, 2, 2], 4));
+        b.set_default_gateway(Ipv4Addr::new(10, 0, 2, 1));
+
+        let server = b.sockets_mut().socket(SocketType::Udp);
+        let b_addr = SocketAddrV4::new(Ipv4Addr::new(10, 0, 2, 2), 7);
+        b.sockets_mut().bind(server, b_addr).unwrap();
+        let client = a.sockets_mut().socket(SocketType::Udp);
+        a.sockets_mut().sendto(client, b"hello", b_addr).unwrap();
+        for _ in 0..10 {
+            a.poll(now).unwrap();
+            router.poll(now).unwrap();
+            b.poll(now).unwrap();
+        }
+
+        let mut buf = [0u8; 16];
+        let (len, from) = b.sockets_mut().recvfrom(server, &mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hello");
+        assert_eq!(*from.ip(), Ipv4Addr::new(10, 0, 1, 2));
+        assert_eq!(router.stats().forwarded, 1);
+        // 网段外的目标只解析网关的 MAC 地址
+        assert!(a.arp().cache().mac(&Ipv4Addr::new(10, 0, 1, 1)).is_some());
+        assert!(a.arp().cache().mac(b_addr.ip()).is_none());
+    }
+
+    #[test]
+    fn test_forward_ttl_exceeded_and_redirect() {
+        let (mut router, device) = stack();
+        let now = Instant::now();
+        router.set_forwarding(true);
+        router.arp_mut().add_static(PEER_IP, PEER_MAC, now);
+        let gateway = Ipv4Addr::new(192, 168, 10, 254);
+        router.routes_mut().add(Route::new(
+            Ipv4Addr::new(10, 9, 0, 0),
+            Ipv4Addr::new(255, 255, 0, 0),
+            Some(gateway),
+            0,
+            0,
+        ));
+        let remote = Ipv4Addr::new(10, 9, 9, 9);
+        let icmp_sent = |device: &MockDevice| -> Vec<IcmpPacket> {
+            device
+                .sent
+                .borrow_mut()
+                .drain(..)
+                .filter_map(|frame| {
+                    let frame = EthernetFrame::parse(&frame).ok()?;
+                    let packet = Ipv4Packet::parse(&frame.payload).ok()?;
+                    (packet.protocol == 1).then(|| IcmpPacket::parse(&packet.payload).unwrap())
+                })
+                .collect()
+        };
+
+        // TTL 耗尽
+        let mut packet = Ipv4Packet::build(PEER_IP, remote, 17, 1, vec![0; 8]);
+        router.receive_frame(&ipv4_frame(packet.clone()), now);
+        router.flush(now);
+        let icmp = icmp_sent(&device);
+        assert_eq!(icmp.len(), 1);
+        assert_eq!(icmp[0].icmp_type, IcmpType::to_u8(IcmpType::TimeExceeded));
+        assert_eq!(router.stats().forwarded, 0);
+
+        // 从收到的接口转发回去，告诉源主机直接发给网关
+        packet.ttl = 64;
+        router.receive_frame(&ipv4_frame(packet.clone()), now);
+        router.flush(now);
+        let icmp = icmp_sent(&device);
+        assert_eq!(icmp.len(), 1);
+        assert_eq!(icmp[0].icmp_type, IcmpType::to_u8(IcmpType::Redirect));
+        let octets = gateway.octets();
+        assert_eq!(
+            icmp[0].identifier,
+            u16::from_be_bytes([octets[0], octets[1]])
+        );
+        assert_eq!(router.stats().forwarded, 1);
+        assert!(router.arp().neighbor_state(gateway).is_some());
+
+        // 装在链路层广播帧里的单播包不转发（RFC 1812 第 5.3.4 节）
+        let broadcast = EthernetFrame::build(
+            BROADCAST_MAC,
+            PEER_MAC,
+            EtherType::to_u16(EtherType::IPv4),
+            packet.to_bytes(),
+        );
+        router.receive_frame(&broadcast.to_bytes(), now);
+        router.flush(now);
+        assert_eq!(router.stats().forwarded, 1);
+        assert!(icmp_sent(&device).is_empty());
+    }
+
+    #[test]
+    fn test_forward_router_alert() {
+        let (mut router, device) = stack();
+        let now = Instant::now();
+        router.set_forwarding(true);
+        let gateway = Ipv4Addr::new(192, 168, 10, 254);
+        router
+            .arp_mut()
+            .add_static(gateway, [0x02, 0, 0, 0, 0, 0xfe], now);
+        router.set_default_gateway(gateway);
+        let remote = Ipv4Addr::new(10, 9, 9, 9);
+
+        // 普通的过路包只转发
+        let packet = Ipv4Packet::build(PEER_IP, remote, 46, 64, vec![0; 8]);
+        router.receive_frame(&ipv4_frame(packet.clone()), now);
+        assert!(router.take_router_alerts().is_empty());
+
+        // 带路由器警告的包照常转发，同时交给本机
+        let mut alert = packet;
+        alert.set_options(&[Ipv4Option::RouterAlert(0)]);
+        router.receive_frame(&ipv4_frame(alert.clone()), now);
+        router.flush(now);
+        let alerts = router.take_router_alerts();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].0, PRIMARY);
+        assert_eq!(alerts[0].1.dst_addr, remote);
+        assert_eq!(router.stats().forwarded, 2);
+        let forwarded = device
+            .sent
+            .borrow()
+            .iter()
+            .filter_map(|frame| Ipv4Packet::parse(&EthernetFrame::parse(frame).ok()?.payload).ok())
+            .rfind(|packet| packet.protocol == 46 && packet.dst_addr == remote)
+            .unwrap();
+        assert_eq!(
+            forwarded.parse_options().unwrap(),
+            vec![Ipv4Option::RouterAlert(0)]
+        );
+
+        // TTL 为 1 不能转发，本机仍然要检查
+        alert.ttl = 1;
+        router.receive_frame(&ipv4_frame(alert), now);
+        assert_eq!(router.take_router_alerts().len(), 1);
+        assert_eq!(router.stats().forwarded, 2);
+    }
+
+    #[test]
+    fn test_echo_reply_records_route() {
+        let (mut stack, device) = stack();
+        let now = Instant::now();
+        stack.arp_mut().add_static(PEER_IP, PEER_MAC, now);
+        let ping = IcmpPacket {
+            icmp_type: IcmpType::to_u8(IcmpType::EchoRequest),
+            code: 0,
+            checksum: 0,
+            identifier: 1,
+            sequence: 1,
+            payload: Vec::new(),
+        };
+        let mut request = Ipv4Packet::build(PEER_IP, OUR_IP, 1, 64, ping.to_bytes());
+        let mut route = RouteOption::new(9);
+        route.record(PEER_IP);
+        request.set_options(&[Ipv4Option::RecordRoute(route), Ipv4Option::EndOfList]);
+        stack.receive_frame(&ipv4_frame(request), now);
+        stack.flush(now);
+
+        let sent = device.sent.borrow();
+        let reply = EthernetFrame::parse(&sent[0]).unwrap();
+        let reply = Ipv4Packet::parse(&reply.payload).unwrap();
+        let options = reply.parse_options().unwrap();
+        let Ipv4Option::RecordRoute(route) = &options[0] else {
+            panic!("reply without record route");
+        };
+        assert_eq!(route.recorded(), &[PEER_IP, OUR_IP]);
+    }
+
+    #[test]
+    fn test_ipv6_neighbor_discovery_and_ping() {
+        let (mut stack, device) = stack();
+        let our_ip: Ipv6Addr = "fe80::42".parse().unwrap();
+        let peer_ip: Ipv6Addr = "fe80::1".parse().unwrap();
+        stack.add_ipv6_address(PRIMARY, our_ip);
+        let now = Instant::now();
+        let ipv6_frame = |dst_mac: MacAddr, packet: Ipv6Packet| {
+            EthernetFrame::build(
+                dst_mac,
+                PEER_MAC,
+                EtherType::to_u16(EtherType::IPv6),
+                packet.to_bytes(),
+            )
+            .to_bytes()
+        };
+        let take_icmp = || {
+            let frame = EthernetFrame::parse(&device.sent.borrow_mut().pop().unwrap()).unwrap();
+            let packet = Ipv6Packet::parse(&frame.payload).unwrap();
+            let icmp = IcmpPacket::parse_v6(&packet.payload, packet.src_addr, packet.dst_addr);
+            (frame.dst_mac, packet, icmp.unwrap())
+        };
+
+        // 发往被请求节点组播地址的邻居请求
+        let group = crate::ipv6::solicited_node(our_ip);
+        let solicitation = NdpMessage::NeighborSolicitation {
+            target: our_ip,
+            source_lladdr: Some(PEER_MAC),
+        };
+        let frame = ipv6_frame(
+            crate::ipv6::multicast_mac(group),
+            solicitation.to_packet(peer_ip, group),
+        );
+        stack.receive_frame(&frame, now);
+        stack.flush(now);
+        let (dst_mac, packet, icmp) = take_icmp();
+        assert_eq!((dst_mac, packet.dst_addr), (PEER_MAC, peer_ip));
+        let NdpMessage::NeighborAdvertisement {
+            solicited,
+            target,
+            target_lladdr,
+            ..
+        } = NdpMessage::parse(&icmp).unwrap()
+        else {
+            panic!("expected neighbor advertisement");
+        };
+        assert!(solicited);
+        assert_eq!((target, target_lladdr), (our_ip, Some(OUR_MAC)));
+
+        // ping 直接用学到的 MAC 回复
+        let request = IcmpPacket {
+            icmp_type: Icmpv6Type::to_u8(Icmpv6Type::EchoRequest),
+            code: 0,
+            checksum: 0,
+            identifier: 7,
+            sequence: 1,
+            payload: b"ping6".to_vec(),
+        };
+        let ping = Ipv6Packet::build(
+            peer_ip,
+            our_ip,
+            IPV6_NEXT_ICMPV6,
+            64,
+            request.to_bytes_v6(peer_ip, our_ip),
+        );
+        stack.receive_frame(&ipv6_frame(OUR_MAC, ping), now);
+        stack.flush(now);
+        let (dst_mac, packet, icmp) = take_icmp();
+        assert_eq!((dst_mac, packet.src_addr), (PEER_MAC, our_ip));
+        assert_eq!(
+            Icmpv6Type::from_u8(icmp.icmp_type),
+            Some(Icmpv6Type::EchoReply)
+        );
+        assert_eq!(
+            (icmp.identifier, icmp.payload.as_slice()),
+            (7, &b"ping6"[..])
+        );
+
+        // 不认识的 Next Header 回复参数问题，指针指向 IPv6 头部的 Next Header 字段
+        let unknown = Ipv6Packet::build(peer_ip, our_ip, 253, 64, vec![0; 8]);
+        stack.receive_frame(&ipv6_frame(OUR_MAC, unknown), now);
+        stack.flush(now);
+        let (_, _, icmp) = take_icmp();
+        assert_eq!(
+            Icmpv6Type::from_u8(icmp.icmp_type),
+            Some(Icmpv6Type::ParameterProblem)
+        );
+        assert_eq!((icmp.code, icmp.sequence), (1, 6));
+
+        // 还有剩余段数的路由头部回复参数问题，指针指向路由类型
+        let echo = request.to_bytes_v6(peer_ip, our_ip);
+        let routed = Ipv6Packet::build_with_extensions(
+            peer_ip,
+            our_ip,
+            &[ExtensionHeader::Routing {
+                routing_type: 0,
+                segments_left: 1,
+                data: vec![0; 20],
+            }],
+            IPV6_NEXT_ICMPV6,
+            64,
+            &echo,
+        );
+        stack.receive_frame(&ipv6_frame(OUR_MAC, routed), now);
+        stack.flush(now);
+        let (_, _, icmp) = take_icmp();
+        assert_eq!((icmp.icmp_type, icmp.code), (4, 0));
+        assert_eq!(icmp.sequence, 42);
+
+        // 分片和要求静默丢弃的选项都不交给上层
+        let fragment = ExtensionHeader::Fragment {
+            offset: 0,
+            more_fragments: true,
+            identification: 1,
+        };
+        let option = ExtensionHeader::DestinationOptions(vec![Ipv6TlvOption {
+            kind: 0x7f,
+            data: Vec::new(),
+        }]);
+        for header in [fragment, option] {
+            let packet = Ipv6Packet::build_with_extensions(
+                peer_ip,
+                our_ip,
+                &[header],
+                IPV6_NEXT_ICMPV6,
+                64,
+                &echo,
+            );
+            stack.receive_frame(&ipv6_frame(OUR_MAC, packet), now);
+        }
+        stack.flush(now);
+        assert!(device.sent.borrow().is_empty());
+    }
+}
@@ -0,0 +1,715 @@
+//! TCP 协议实现
+//!
+//! TCP（Transmission Control Protocol）是面向连接的可靠传输层协议
+
+pub mod congestion;
+pub mod options;
+pub mod sack;
+pub mod tcb;
+pub mod timer;
+pub mod window;
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::{BuildHasher, RandomState};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::Instant;
+
+use tracing::{debug, info};
+
+use crate::error::{Result, StackError};
+use crate::icmp::internet_checksum;
+use crate::ip::Ipv4Packet;
+use congestion::CongestionAlgorithm;
+use options::TcpOption;
+use tcb::{Tcb, TcpConfig, TcpState};
+
+const TCP_HEADER_MIN_LEN: usize = 20;
+
+/// 选项区最长 40 字节（数据偏移最大 15）
+const TCP_OPTIONS_MAX_LEN: usize = 40;
+
+/// 发送 TCP 段时使用的 TTL
+const DEFAULT_TTL: u8 = 64;
+
+/// 每个监听端口最多排队的未 accept 连接数
+const LISTEN_BACKLOG: usize = 128;
+
+// TCP 标志位（字节 13）
+pub const TCP_FIN: u8 = 0x01; // 结束连接
+pub const TCP_SYN: u8 = 0x02; // 同步序列号
+pub const TCP_RST: u8 = 0x04; // 重置连接
+pub const TCP_PSH: u8 = 0x08; // 推送数据
+pub const TCP_ACK: u8 = 0x10; // 确认号有效
+pub const TCP_URG: u8 = 0x20; // 紧急指针有效
+pub const TCP_ECE: u8 = 0x40; // ECN 回显
+pub const TCP_CWR: u8 = 0x80; // 拥塞窗口减小
+
+/// TCP 段结构
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpSegment {
+    pub src_port: u16,       // 源端口
+    pub dst_port: u16,       // 目标端口
+    pub seq_number: u32,     // 序列号
+    pub ack_number: u32,     // 确认号
+    pub data_offset: u8,     // 数据偏移（头部长度，单位 4 字节）
+    pub reserved: u8,        // 保留位（字节 12 的低四位），原样保留
+    pub flags: u8,           // 标志位（CWR ECE URG ACK PSH RST SYN FIN）
+    pub window: u16,         // 窗口大小
+    pub checksum: u16,       // 校验和
+    pub urgent_pointer: u16, // 紧急指针
+    pub options: Vec<u8>,    // 选项（已按 4 字节对齐）
+    pub payload: Vec<u8>,    // 数据负载
+}
+
+impl TcpSegment {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < TCP_HEADER_MIN_LEN {
+            return Err(StackError::InvalidPacket(String::from(
+                "Tcp segment too short",
+            )));
+        }
+        let src_port = u16::from_be_bytes([data[0], data[1]]);
+        let dst_port = u16::from_be_bytes([data[2], data[3]]);
+        let seq_number = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+        let ack_number = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+
+        // 字节 12： 数据偏移(高四位) + 保留位(低四位)
+        let data_offset = data[12] >> 4;
+        let reserved = data[12] & 0x0f;
+        // 字节 13： 标志位
+        let flags = data[13];
+
+        let window = u16::from_be_bytes([data[14], data[15]]);
+        let checksum = u16::from_be_bytes([data[16], data[17]]);
+        let urgent_pointer = u16::from_be_bytes([data[18], data[19]]);
+
+        // 头部长度 = 数据偏移 * 4，至少 20 字节且不能超过数据长度
+        let header_len = (data_offset as usize) * 4;
+        if header_len < TCP_HEADER_MIN_LEN || header_len > data.len() {
+            return Err(StackError::InvalidPacket(format!(
+                "Tcp data offset {} out of range",
+                data_offset
+            )));
+        }
+
+        let options = data[TCP_HEADER_MIN_LEN..header_len].to_vec();
+        let payload = data[header_len..].to_vec();
+        Ok(Self {
+            src_port,
+            dst_port,
+            seq_number,
+            ack_number,
+            data_offset,
+            reserved,
+            flags,
+            window,
+            checksum,
+            urgent_pointer,
+            options,
+            payload,
+        })
+    }
+
+    /// 构造不带选项的 TCP 段，校验和需要通过 `fill_checksum` 计算
+    pub fn build(
+        src_port: u16,
+        dst_port: u16,
+        seq_number: u32,
+        ack_number: u32,
+        flags: u8,
+        window: u16,
+        payload: Vec<u8>,
+    ) -> Self {
+        Self {
+            src_port,
+            dst_port,
+            seq_number,
+            ack_number,
+            data_offset: 5, // 5 * 4 = 20 字节（无选项）
+            reserved: 0,
+            flags,
+            window,
+            checksum: 0,
+            urgent_pointer: 0,
+            options: Vec::new(),
+            payload,
+        }
+    }
+
+    /// 设置选项，不足 4 字节的部分用 EOL(0) 填充，并更新数据偏移。
+    /// 超过 40 字节时丢弃放不下的完整选项，不会把一个选项截断一半
+    pub fn with_options(mut self, mut options: Vec<u8>) -> Self {
+        if options.len() > TCP_OPTIONS_MAX_LEN {
+            let mut end = 0;
+            while end < options.len() {
+                let len = match options[end] {
+                    0 | 1 => 1,
+                    _ => options.get(end + 1).map_or(0, |&len| len as usize).max(2),
+                };
+                if end + len > TCP_OPTIONS_MAX_LEN {
+                    break;
+                }
+                end += len;
+            }
+            debug!(
+                "Tcp options too long, truncate {} bytes to {}",
+                options.len(),
+                end
+            );
+            options.truncate(end);
+        }
+        while !options.len().is_multiple_of(4) {
+            options.push(0);
+        }
+        self.data_offset = ((TCP_HEADER_MIN_LEN + options.len()) / 4) as u8;
+        self.options = options;
+        self
+    }
+
+    /// 设置紧急指针，同时置上 URG 标志
+    pub fn with_urgent_pointer(mut self, urgent_pointer: u16) -> Self {
+        self.urgent_pointer = urgent_pointer;
+        self.flags |= TCP_URG;
+        self
+    }
+
+    /// 解析选项区
+    pub fn tcp_options(&self) -> Result<Vec<TcpOption>> {
+        TcpOption::parse_all(&self.options)
+    }
+
+    pub fn has_flag(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    pub fn header_len(&self) -> usize {
+        (self.data_offset as usize) * 4
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.header_len() + self.payload.len());
+        bytes.extend_from_slice(&self.src_port.to_be_bytes());
+        bytes.extend_from_slice(&self.dst_port.to_be_bytes());
+        bytes.extend_from_slice(&self.seq_number.to_be_bytes());
+        bytes.extend_from_slice(&self.ack_number.to_be_bytes());
+        bytes.push(self.data_offset << 4 | self.reserved & 0x0f);
+        bytes.push(self.flags);
+        bytes.extend_from_slice(&self.window.to_be_bytes());
+        bytes.extend_from_slice(&self.checksum.to_be_bytes());
+        bytes.extend_from_slice(&self.urgent_pointer.to_be_bytes());
+        bytes.extend_from_slice(&self.options);
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    /// 根据伪头部计算并填写校验和
+    pub fn fill_checksum(&mut self, src_addr: Ipv4Addr, dst_addr: Ipv4Addr) {
+        self.checksum = Self::calculate_tcp_checksum(self, src_addr, dst_addr);
+    }
+
+    /// 校验收到的 TCP 段
+    pub fn verify_checksum(&self, src_addr: Ipv4Addr, dst_addr: Ipv4Addr) -> Result<()> {
+        let expected = Self::calculate_tcp_checksum(self, src_addr, dst_addr);
+        if expected != self.checksum {
+            return Err(StackError::ChecksumMismatch(format!(
+                "Tcp checksum mismatch: expected {:#06x}, got {:#06x}",
+                expected, self.checksum
+            )));
+        }
+        Ok(())
+    }
+
+    fn calculate_tcp_checksum(segment: &TcpSegment, src_addr: Ipv4Addr, dst_addr: Ipv4Addr) -> u16 {
+        let mut bytes = segment.to_bytes();
+        bytes[16..18].copy_from_slice(&[0, 0]); // checksum 占位
+        let tcp_length = bytes.len() as u16;
+
+        let mut data = Vec::with_capacity(12 + bytes.len());
+
+        // 1. TCP 伪头部
+        data.extend_from_slice(&src_addr.octets());
+        data.extend_from_slice(&dst_addr.octets());
+        data.push(0);
+        data.push(6); // TCP 协议号
+        data.extend_from_slice(&tcp_length.to_be_bytes());
+
+        // 2. TCP 头部和数据
+        data.extend_from_slice(&bytes);
+
+        // 3.计算校验和
+        internet_checksum(&data)
+    }
+}
+
+// 序列号比较（模 2^32，RFC 9293 第 3.4 节）
+pub fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+pub fn seq_le(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) <= 0
+}
+
+pub fn seq_gt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+pub fn seq_ge(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) >= 0
+}
+
+/// 连接标识：本地地址 + 远端地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TcpConnKey {
+    pub local: SocketAddrV4,
+    pub remote: SocketAddrV4,
+}
+
+/// 监听端口，收到 SYN 后按它的配置创建连接
+#[derive(Debug)]
+struct Listener {
+    backlog: VecDeque<TcpConnKey>, // 尚未 accept 的连接
+    config: TcpConfig,
+}
+
+/// TCP 模块
+/// 负责把收到的段分发给对应的连接，并处理监听端口上的新连接
+#[derive(Debug)]
+pub struct TcpModule {
+    connections: HashMap<TcpConnKey, Tcb>,
+    listeners: HashMap<SocketAddrV4, Listener>, // 监听地址 -> 尚未 accept 的连接
+    released: Vec<TcpConnKey>,                  // 应用已关闭，状态机结束后即可删除
+    outgoing: Vec<(Ipv4Addr, Ipv4Addr, TcpSegment)>, // 不属于任何连接的待发送段（RST 等）
+    config: TcpConfig,                          // 新连接使用的 MSS 和选项
+    confirmed: Vec<Ipv4Addr>,                   // 确认了新数据的对端，用于邻居可达性确认
+    iss_secret: RandomState,
+    epoch: Instant,
+}
+
+impl TcpModule {
+    pub fn new() -> Self {
+        Self {
+            connections: HashMap::new(),
+            listeners: HashMap::new(),
+            released: Vec::new(),
+            outgoing: Vec::new(),
+            config: TcpConfig::default(),
+            confirmed: Vec::new(),
+            iss_secret: RandomState::new(),
+            epoch: Instant::now(),
+        }
+    }
+
+    /// 按网卡 MTU 设置通告的 MSS，只影响之后建立的连接
+    pub fn set_mtu(&mut self, mtu: usize) {
+        self.config.mss = TcpConfig::from_mtu(mtu).mss;
+    }
+
+    pub fn set_config(&mut self, config: TcpConfig) {
+        self.config = config;
+    }
+
+    pub fn config(&self) -> &TcpConfig {
+        &self.config
+    }
+
+    /// 在本地地址上监听，IP 为 0.0.0.0 时匹配所有地址
+    pub fn listen(&mut self, local: SocketAddrV4) -> Result<()> {
+        self.listen_with(local, self.config.clone())
+    }
+
+    /// 使用指定配置监听，accept 得到的连接继承这个配置
+    pub fn listen_with(&mut self, local: SocketAddrV4, config: TcpConfig) -> Result<()> {
+        if self.listeners.contains_key(&local) {
+            return Err(StackError::ConnectionFailed(format!(
+                "{} already listening",
+                local
+            )));
+        }
+        self.listeners.insert(
+            local,
+            Listener {
+                backlog: VecDeque::new(),
+                config,
+            },
+        );
+        info!("TCP listening on {}", local);
+        Ok(())
+    }
+
+    /// 停止监听，尚未 accept 的连接会被重置
+    pub fn unlisten(&mut self, local: SocketAddrV4) {
+        if let Some(listener) = self.listeners.remove(&local) {
+            for key in listener.backlog {
+                if let Some(tcb) = self.connections.get_mut(&key) {
+                    tcb.abort();
+                }
+                self.released.push(key);
+            }
+        }
+    }
+
+    /// 主动发起连接
+    pub fn connect(
+        &mut self,
+        local: SocketAddrV4,
+        remote: SocketAddrV4,
+        now: Instant,
+    ) -> Result<TcpConnKey> {
+        self.connect_with(local, remote, &self.config.clone(), now)
+    }
+
+    /// 使用指定配置发起连接
+    pub fn connect_with(
+        &mut self,
+        local: SocketAddrV4,
+        remote: SocketAddrV4,
+        config: &TcpConfig,
+        now: Instant,
+    ) -> Result<TcpConnKey> {
+        let key = TcpConnKey { local, remote };
+        if self.connections.contains_key(&key) {
+            return Err(StackError::ConnectionFailed(format!(
+                "{} -> {} already exists",
+                local, remote
+            )));
+        }
+        let iss = self.generate_iss(&key, now);
+        self.connections
+            .insert(key, Tcb::connect(local, remote, iss, config, now));
+        Ok(key)
+    }
+
+    /// 取出一个已经完成握手的连接
+    pub fn accept(&mut self, local: SocketAddrV4) -> Option<TcpConnKey> {
+        let backlog = &mut self.listeners.get_mut(&local)?.backlog;
+        let index = backlog.iter().position(|key| {
+            self.connections
+                .get(key)
+                .is_some_and(|tcb| tcb.state().is_synchronized())
+        })?;
+        backlog.remove(index)
+    }
+
+    pub fn send(&mut self, key: &TcpConnKey, data: &[u8]) -> Result<usize> {
+        self.connection_mut(key)?.send(data)
+    }
+
+    pub fn recv(&mut self, key: &TcpConnKey, buf: &mut [u8]) -> Result<usize> {
+        self.connection_mut(key)?.recv(buf)
+    }
+
+    /// 关闭连接，状态机走完后连接会被删除
+    pub fn close(&mut self, key: &TcpConnKey) -> Result<()> {
+        self.connection_mut(key)?.close();
+        self.released.push(*key);
+        Ok(())
+    }
+
+    /// 修改监听端口之后收到的连接使用的拥塞控制算法
+    pub fn set_listener_congestion_control(
+        &mut self,
+        local: SocketAddrV4,
+        algorithm: CongestionAlgorithm,
+    ) -> Result<()> {
+        let listener = self
+            .listeners
+            .get_mut(&local)
+            .ok_or_else(|| StackError::ConnectionFailed(format!("{} is not listening", local)))?;
+        listener.config.congestion = algorithm;
+        Ok(())
+    }
+
+    /// 为单个连接切换拥塞控制算法
+    pub fn set_congestion_control(
+        &mut self,
+        key: &TcpConnKey,
+        algorithm: CongestionAlgorithm,
+    ) -> Result<()> {
+        self.connection_mut(key)?.set_congestion_control(algorithm);
+        Ok(())
+    }
+
+    pub fn state(&self, key: &TcpConnKey) -> Option<TcpState> {
+        if self.listeners.contains_key(&key.local) && key.remote == unspecified() {
+            return Some(TcpState::Listen);
+        }
+        self.connections.get(key).map(|tcb| tcb.state())
+    }
+
+    pub fn connection(&self, key: &TcpConnKey) -> Option<&Tcb> {
+        self.connections.get(key)
+    }
+
+    /// 处理收到的 TCP 段
+    pub fn handle_segment(
+        &mut self,
+        src_addr: Ipv4Addr,
+        dst_addr: Ipv4Addr,
+        seg: &TcpSegment,
+        now: Instant,
+    ) -> Result<()> {
+        seg.verify_checksum(src_addr, dst_addr)?;
+        let key = TcpConnKey {
+            local: SocketAddrV4::new(dst_addr, seg.dst_port),
+            remote: SocketAddrV4::new(src_addr, seg.src_port),
+        };
+
+        if let Some(tcb) = self.connections.get_mut(&key) {
+            let una = tcb.snd_una();
+            tcb.on_segment(seg, now);
+            // 对方确认了新数据，说明双向可达（RFC 4861 第 7.3.1 节）
+            if tcb.snd_una() != una && !self.confirmed.contains(&src_addr) {
+                self.confirmed.push(src_addr);
+            }
+            return Ok(());
+        }
+
+        // LISTEN 状态（RFC 9293 第 3.10.7.2 节）
+        if let Some(listen_addr) = self.find_listener(&key.local) {
+            if seg.has_flag(TCP_RST) {
+                return Ok(());
+            }
+            if seg.has_flag(TCP_ACK) {
+                self.send_reset(&key, seg);
+                return Ok(());
+            }
+            if seg.has_flag(TCP_SYN) {
+                let backlog_len = self.listeners[&listen_addr].backlog.len();
+                if backlog_len >= LISTEN_BACKLOG {
+                    debug!(
+                        "Backlog of {} full, drop SYN from {}",
+                        listen_addr, key.remote
+                    );
+                    return Ok(());
+                }
+                let iss = self.generate_iss(&key, now);
+                let Some(listener) = self.listeners.get_mut(&listen_addr) else {
+                    return Ok(());
+                };
+                let tcb = Tcb::accept(key.local, key.remote, seg, iss, &listener.config, now);
+                listener.backlog.push_back(key);
+                self.connections.insert(key, tcb);
+                info!("TCP SYN from {} on {}", key.remote, key.local);
+            }
+            return Ok(());
+        }
+
+        // CLOSED 状态：回复 RST
+        debug!("No TCP connection for {} -> {}", key.remote, key.local);
+        self.send_reset(&key, seg);
+        Ok(())
+    }
+
+    /// 处理定时器并清理已经结束的连接
+    pub fn poll(&mut self, now: Instant) {
+        for (key, tcb) in self.connections.iter_mut() {
+            if let Err(e) = tcb.poll(now) {
+                info!(
+                    "TCP connection {} -> {} failed: {}",
+                    key.local, key.remote, e
+                );
+            }
+        }
+
+        // 未被 accept 就关闭的连接（例如握手阶段被重置）
+        for listener in self.listeners.values_mut() {
+            listener.backlog.retain(|key| {
+                let closed = self
+                    .connections
+                    .get(key)
+                    .is_none_or(|tcb| tcb.state() == TcpState::Closed);
+                if closed {
+                    self.released.push(*key);
+                }
+                !closed
+            });
+        }
+
+        let connections = &mut self.connections;
+        let outgoing = &mut self.outgoing;
+        self.released.retain(|key| match connections.get(key) {
+            Some(tcb) if tcb.state() == TcpState::Closed => {
+                // 删除前保留最后要发出的段（例如 abort 产生的 RST）
+                if let Some(mut tcb) = connections.remove(key) {
+                    for seg in tcb.take_outgoing() {
+                        outgoing.push((*key.local.ip(), *key.remote.ip(), seg));
+                    }
+                }
+                debug!("TCP connection {} -> {} removed", key.local, key.remote);
+                false
+            }
+            Some(_) => true,
+            None => false,
+        });
+    }
+
+    /// 取出最近确认了新数据的对端地址
+    pub fn take_confirmed(&mut self) -> Vec<Ipv4Addr> {
+        std::mem::take(&mut self.confirmed)
+    }
+
+    /// 取出所有待发送的 IP 数据包
+    pub fn take_outgoing(&mut self) -> Vec<Ipv4Packet> {
+        let mut segments: Vec<(Ipv4Addr, Ipv4Addr, TcpSegment)> = self.outgoing.drain(..).collect();
+        for (key, tcb) in self.connections.iter_mut() {
+            for seg in tcb.take_outgoing() {
+                segments.push((*key.local.ip(), *key.remote.ip(), seg));
+            }
+        }
+        segments
+            .into_iter()
+            .map(|(src, dst, mut seg)| {
+                seg.fill_checksum(src, dst);
+                Ipv4Packet::build(src, dst, 6, DEFAULT_TTL, seg.to_bytes())
+            })
+            .collect()
+    }
+
+    fn connection_mut(&mut self, key: &TcpConnKey) -> Result<&mut Tcb> {
+        self.connections.get_mut(key).ok_or_else(|| {
+            StackError::ConnectionFailed(format!("no connection {} -> {}", key.local, key.remote))
+        })
+    }
+
+    fn find_listener(&self, local: &SocketAddrV4) -> Option<SocketAddrV4> {
+        let wildcard = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, local.port());
+        [*local, wildcard]
+            .into_iter()
+            .find(|addr| self.listeners.contains_key(addr))
+    }
+
+    fn send_reset(&mut self, key: &TcpConnKey, seg: &TcpSegment) {
+        if let Some(rst) = tcb::reset_for(seg) {
+            self.outgoing.push((*key.local.ip(), *key.remote.ip(), rst));
+        }
+    }
+
+    /// 生成初始序列号：4 微秒时钟 + 连接四元组的哈希（RFC 6528）
+    fn generate_iss(&self, key: &TcpConnKey, now: Instant) -> u32 {
+        let clock = (now.duration_since(self.epoch).as_micros() / 4) as u32;
+        let hash = self.iss_secret.hash_one(key) as u32;
+        clock.wrapping_add(hash)
+    }
+}
+
+impl Default for TcpModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn unspecified() -> SocketAddrV4 {
+    SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tcp_segment_roundtrip() {
+        let src = Ipv4Addr::new(192, 168, 10, 1);
+        let dst = Ipv4Addr::new(192, 168, 10, 2);
+        // MSS = 1460
+        let mut segment = TcpSegment::build(40000, 8080, 1000, 0, TCP_SYN, 64240, Vec::new())
+            .with_options(vec![2, 4, 0x05, 0xb4, 1]);
+        segment.fill_checksum(src, dst);
+
+        let bytes = segment.to_bytes();
+        assert_eq!(bytes.len(), 28);
+        assert_eq!(bytes[12] >> 4, 7);
+
+        let parsed = TcpSegment::parse(&bytes).unwrap();
+        assert_eq!(parsed, segment);
+        assert!(parsed.has_flag(TCP_SYN));
+        assert!(!parsed.has_flag(TCP_ACK));
+        assert_eq!(parsed.options, vec![2, 4, 0x05, 0xb4, 1, 0, 0, 0]);
+        parsed.verify_checksum(src, dst).unwrap();
+
+        // 保留位在解析和序列化之间保持不变
+        let mut bytes = bytes;
+        bytes[12] |= 0x05;
+        assert_eq!(TcpSegment::parse(&bytes).unwrap().to_bytes(), bytes);
+
+        // 超过 40 字节的选项只保留放得下的完整选项
+        let mut options = vec![1; 36];
+        options.extend_from_slice(&[8, 10, 0, 0, 0, 1, 0, 0, 0, 2]);
+        let segment = TcpSegment::build(40000, 8080, 1000, 0, TCP_SYN, 64240, Vec::new())
+            .with_options(options);
+        assert_eq!(segment.data_offset, 14);
+        assert_eq!(segment.options, vec![1; 36]);
+    }
+
+    #[test]
+    fn test_tcp_checksum_mismatch() {
+        let src = Ipv4Addr::new(192, 168, 10, 1);
+        let dst = Ipv4Addr::new(192, 168, 10, 2);
+        let mut segment = TcpSegment::build(
+            40000,
+            8080,
+            1,
+            1,
+            TCP_ACK | TCP_PSH,
+            1024,
+            b"hello".to_vec(),
+        );
+        segment.fill_checksum(src, dst);
+
+        let mut bytes = segment.to_bytes();
+        bytes[20] ^= 0xff;
+        let corrupted = TcpSegment::parse(&bytes).unwrap();
+        assert!(matches!(
+            corrupted.verify_checksum(src, dst),
+            Err(StackError::ChecksumMismatch(_))
+        ));
+
+        // 数据偏移小于 5 的段是非法的
+        bytes[12] = 4 << 4;
+        assert!(TcpSegment::parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_tcp_module_listen_accept_and_reset() {
+        let now = Instant::now();
+        let server_ip = Ipv4Addr::new(192, 168, 10, 2);
+        let client_ip = Ipv4Addr::new(192, 168, 10, 1);
+        let listen_addr = SocketAddrV4::new(server_ip, 8080);
+        let mut module = TcpModule::new();
+        module.listen(listen_addr).unwrap();
+
+        let client_addr = SocketAddrV4::new(client_ip, 40000);
+        let mut client = Tcb::connect(client_addr, listen_addr, 1000, &TcpConfig::default(), now);
+        let deliver = |module: &mut TcpModule, client: &mut Tcb| {
+            for mut seg in client.take_outgoing() {
+                seg.fill_checksum(client_ip, server_ip);
+                module
+                    .handle_segment(client_ip, server_ip, &seg, now)
+                    .unwrap();
+            }
+            for packet in module.take_outgoing() {
+                let seg = TcpSegment::parse(&packet.payload).unwrap();
+                client.on_segment(&seg, now);
+            }
+        };
+        deliver(&mut module, &mut client);
+        deliver(&mut module, &mut client);
+        assert_eq!(client.state(), TcpState::Established);
+
+        let key = module.accept(listen_addr).unwrap();
+        assert_eq!(key.remote, client_addr);
+        assert_eq!(module.state(&key), Some(TcpState::Established));
+
+        // 没有监听的端口回复 RST
+        let mut syn = TcpSegment::build(40001, 9999, 7, 0, TCP_SYN, 1024, Vec::new());
+        syn.fill_checksum(client_ip, server_ip);
+        module
+            .handle_segment(client_ip, server_ip, &syn, now)
+            .unwrap();
+        let packets = module.take_outgoing();
+        assert_eq!(packets.len(), 1);
+        let rst = TcpSegment::parse(&packets[0].payload).unwrap();
+        assert!(rst.has_flag(TCP_RST));
+        assert_eq!(rst.ack_number, 8);
+    }
+}
@@ -0,0 +1,351 @@
+//! TCP 拥塞控制
+//!
+//! `CongestionControl` trait 定义算法需要响应的事件，连接只负责检测事件：
+//! 新确认、三个重复 ACK（快速重传）和重传超时。
+//! 提供 NewReno（RFC 5681 / RFC 6582）和 CUBIC（RFC 9438）两种实现
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// 可选的拥塞控制算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CongestionAlgorithm {
+    #[default]
+    NewReno,
+    Cubic,
+}
+
+impl CongestionAlgorithm {
+    pub fn build(self, mss: usize) -> Box<dyn CongestionControl> {
+        match self {
+            CongestionAlgorithm::NewReno => Box::new(NewReno::new(mss)),
+            CongestionAlgorithm::Cubic => Box::new(Cubic::new(mss)),
+        }
+    }
+}
+
+/// 各算法共用的窗口状态（单位：字节）
+#[derive(Debug, Clone)]
+pub struct Window {
+    pub cwnd: usize,     // 拥塞窗口
+    pub ssthresh: usize, // 慢启动阈值
+    pub mss: usize,
+}
+
+impl Window {
+    fn new(mss: usize) -> Self {
+        Self {
+            cwnd: initial_window(mss),
+            ssthresh: usize::MAX,
+            mss,
+        }
+    }
+
+    fn in_slow_start(&self) -> bool {
+        self.cwnd < self.ssthresh
+    }
+}
+
+/// 初始窗口（RFC 6928）：min(10*MSS, max(2*MSS, 14600))
+fn initial_window(mss: usize) -> usize {
+    (10 * mss).min((2 * mss).max(14600))
+}
+
+/// 拥塞控制算法
+///
+/// 快速恢复期间的窗口膨胀/收缩（RFC 6582）对所有算法都一样，由默认方法实现
+pub trait CongestionControl: fmt::Debug {
+    fn name(&self) -> &'static str;
+
+    fn window(&self) -> &Window;
+
+    fn window_mut(&mut self) -> &mut Window;
+
+    /// 恢复期之外收到新的确认，acked 为新确认的字节数，rtt 为当前平滑 RTT
+    fn on_ack(&mut self, acked: usize, rtt: Duration, now: Instant);
+
+    /// 检测到丢包（三个重复 ACK），进入快速恢复
+    fn on_loss(&mut self, flight_size: usize, now: Instant);
+
+    /// 重传超时
+    fn on_timeout(&mut self, flight_size: usize, now: Instant);
+
+    fn cwnd(&self) -> usize {
+        self.window().cwnd
+    }
+
+    fn ssthresh(&self) -> usize {
+        self.window().ssthresh
+    }
+
+    /// MSS 协商完成后更新
+    fn set_mss(&mut self, mss: usize) {
+        let window = self.window_mut();
+        if window.cwnd == initial_window(window.mss) {
+            window.cwnd = initial_window(mss);
+        }
+        window.mss = mss;
+    }
+
+    /// 快速恢复期间每个额外的重复 ACK 让窗口膨胀一个 MSS
+    fn on_dup_ack(&mut self) {
+        let window = self.window_mut();
+        window.cwnd += window.mss;
+    }
+
+    /// 快速恢复期间的部分确认：减去新确认的数据，再加回一个 MSS
+    fn on_partial_ack(&mut self, acked: usize) {
+        let window = self.window_mut();
+        window.cwnd = window.cwnd.saturating_sub(acked).max(window.mss);
+        if acked >= window.mss {
+            window.cwnd += window.mss;
+        }
+    }
+
+    /// 恢复点之前的数据全部确认，窗口收缩到 ssthresh
+    fn on_recovery_exit(&mut self) {
+        let window = self.window_mut();
+        window.cwnd = window.ssthresh.max(window.mss);
+    }
+}
+
+/// NewReno：慢启动、拥塞避免、快速重传和快速恢复
+#[derive(Debug, Clone)]
+pub struct NewReno {
+    window: Window,
+    bytes_acked: usize, // 拥塞避免阶段累计确认的字节数（RFC 5681 第 3.1 节）
+}
+
+impl NewReno {
+    pub fn new(mss: usize) -> Self {
+        Self {
+            window: Window::new(mss),
+            bytes_acked: 0,
+        }
+    }
+}
+
+impl CongestionControl for NewReno {
+    fn name(&self) -> &'static str {
+        "newreno"
+    }
+
+    fn window(&self) -> &Window {
+        &self.window
+    }
+
+    fn window_mut(&mut self) -> &mut Window {
+        &mut self.window
+    }
+
+    fn on_ack(&mut self, acked: usize, _rtt: Duration, _now: Instant) {
+        let window = &mut self.window;
+        if window.in_slow_start() {
+            // 慢启动：每个 ACK 最多增加一个 MSS
+            window.cwnd += acked.min(window.mss);
+            return;
+        }
+        // 拥塞避免：每确认一个窗口的数据增加一个 MSS
+        self.bytes_acked += acked;
+        if self.bytes_acked >= window.cwnd {
+            self.bytes_acked -= window.cwnd;
+            window.cwnd += window.mss;
+        }
+    }
+
+    fn on_loss(&mut self, flight_size: usize, _now: Instant) {
+        let window = &mut self.window;
+        window.ssthresh = (flight_size / 2).max(2 * window.mss);
+        window.cwnd = window.ssthresh + 3 * window.mss;
+        self.bytes_acked = 0;
+    }
+
+    fn on_timeout(&mut self, flight_size: usize, _now: Instant) {
+        let window = &mut self.window;
+        window.ssthresh = (flight_size / 2).max(2 * window.mss);
+        window.cwnd = window.mss;
+        self.bytes_acked = 0;
+    }
+}
+
+/// CUBIC 常数 C
+const CUBIC_C: f64 = 0.4;
+/// CUBIC 乘性减小因子
+const CUBIC_BETA: f64 = 0.7;
+
+/// CUBIC：拥塞避免阶段按距上次拥塞事件时间的三次函数增长窗口
+#[derive(Debug, Clone)]
+pub struct Cubic {
+    window: Window,
+    w_max: f64,                   // 上次拥塞事件前的窗口（MSS 为单位）
+    k: f64,                       // 窗口回到 w_max 所需的时间（秒）
+    epoch_start: Option<Instant>, // 本轮拥塞避免开始的时间
+    w_est: f64,                   // 与 Reno 公平的估计窗口（MSS 为单位）
+}
+
+impl Cubic {
+    pub fn new(mss: usize) -> Self {
+        Self {
+            window: Window::new(mss),
+            w_max: 0.0,
+            k: 0.0,
+            epoch_start: None,
+            w_est: 0.0,
+        }
+    }
+
+    /// W_cubic(t) = C * (t - K)^3 + W_max（RFC 9438 第 4.2 节）
+    fn w_cubic(&self, t: f64) -> f64 {
+        CUBIC_C * (t - self.k).powi(3) + self.w_max
+    }
+
+    /// 拥塞事件：记录 W_max 并乘性减小（含快速收敛，RFC 9438 第 4.6、4.7 节）
+    fn reduce(&mut self) -> usize {
+        let mss = self.window.mss as f64;
+        let cwnd = self.window.cwnd as f64 / mss;
+        self.w_max = if cwnd < self.w_max {
+            cwnd * (1.0 + CUBIC_BETA) / 2.0
+        } else {
+            cwnd
+        };
+        self.epoch_start = None;
+        let ssthresh = ((cwnd * CUBIC_BETA * mss) as usize).max(2 * self.window.mss);
+        self.window.ssthresh = ssthresh;
+        ssthresh
+    }
+}
+
+impl CongestionControl for Cubic {
+    fn name(&self) -> &'static str {
+        "cubic"
+    }
+
+    fn window(&self) -> &Window {
+        &self.window
+    }
+
+    fn window_mut(&mut self) -> &mut Window {
+        &mut self.window
+    }
+
+    fn on_ack(&mut self, acked: usize, rtt: Duration, now: Instant) {
+        if self.window.in_slow_start() {
+            self.window.cwnd += acked.min(self.window.mss);
+            return;
+        }
+
+        let mss = self.window.mss as f64;
+        let cwnd = self.window.cwnd as f64 / mss;
+        let epoch_start = *self.epoch_start.get_or_insert_with(|| {
+            // 新一轮拥塞避免：计算 K，当前窗口已超过 W_max 时从这里重新开始
+            if cwnd < self.w_max {
+                self.k = ((self.w_max - cwnd) / CUBIC_C).cbrt();
+            } else {
+                self.k = 0.0;
+                self.w_max = cwnd;
+            }
+            self.w_est = cwnd;
+            now
+        });
+
+        // Reno 友好区域（RFC 9438 第 4.3 节）
+        let alpha = 3.0 * (1.0 - CUBIC_BETA) / (1.0 + CUBIC_BETA);
+        self.w_est += alpha * (acked as f64 / mss) / cwnd;
+
+        let t = now.duration_since(epoch_start).as_secs_f64();
+        let w_cubic = self.w_cubic(t);
+        if w_cubic < self.w_est {
+            self.window.cwnd = (self.w_est * mss) as usize;
+            return;
+        }
+
+        // 凹/凸区域：向 W_cubic(t + RTT) 增长，每个 RTT 最多增长 50%
+        let target = self.w_cubic(t + rtt.as_secs_f64()).clamp(cwnd, 1.5 * cwnd);
+        let increase = (target - cwnd) / cwnd * (acked as f64 / mss);
+        self.window.cwnd = ((cwnd + increase) * mss) as usize;
+    }
+
+    fn on_loss(&mut self, _flight_size: usize, _now: Instant) {
+        let ssthresh = self.reduce();
+        self.window.cwnd = ssthresh + 3 * self.window.mss;
+    }
+
+    fn on_timeout(&mut self, _flight_size: usize, _now: Instant) {
+        self.reduce();
+        self.window.cwnd = self.window.mss;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MSS: usize = 1000;
+    const RTT: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn test_newreno_slow_start_and_recovery() {
+        let now = Instant::now();
+        let mut cc = NewReno::new(MSS);
+        assert_eq!(cc.cwnd(), 10 * MSS);
+
+        // 慢启动：每个 ACK 增加一个 MSS
+        for _ in 0..10 {
+            cc.on_ack(MSS, RTT, now);
+        }
+        assert_eq!(cc.cwnd(), 20 * MSS);
+
+        // 三个重复 ACK：ssthresh = flight / 2，cwnd = ssthresh + 3 MSS
+        cc.on_loss(20 * MSS, now);
+        assert_eq!(cc.ssthresh(), 10 * MSS);
+        assert_eq!(cc.cwnd(), 13 * MSS);
+        cc.on_dup_ack();
+        assert_eq!(cc.cwnd(), 14 * MSS);
+        cc.on_recovery_exit();
+        assert_eq!(cc.cwnd(), 10 * MSS);
+
+        // 拥塞避免：确认一整个窗口后只增加一个 MSS
+        for _ in 0..10 {
+            cc.on_ack(MSS, RTT, now);
+        }
+        assert_eq!(cc.cwnd(), 11 * MSS);
+
+        cc.on_timeout(11 * MSS, now);
+        assert_eq!(cc.cwnd(), MSS);
+        assert_eq!(cc.ssthresh(), 5500);
+    }
+
+    #[test]
+    fn test_cubic_growth_after_loss() {
+        let start = Instant::now();
+        let mut cc = Cubic::new(MSS);
+        cc.window.cwnd = 100 * MSS;
+        cc.window.ssthresh = 100 * MSS;
+
+        cc.on_loss(100 * MSS, start);
+        assert_eq!(cc.ssthresh(), 70 * MSS);
+        cc.on_recovery_exit();
+        assert_eq!(cc.cwnd(), 70 * MSS);
+
+        // K = cbrt(100 * 0.3 / 0.4) ≈ 4.2 秒
+        cc.on_ack(MSS, RTT, start);
+        assert!((cc.k - 4.217).abs() < 0.01);
+
+        // 在 K 之前窗口增长逐渐变慢，到 K 附近接近 W_max
+        let mut now = start;
+        while now.duration_since(start) < Duration::from_secs_f64(cc.k) {
+            now += RTT;
+            let acks = cc.cwnd() / MSS;
+            for _ in 0..acks {
+                cc.on_ack(MSS, RTT, now);
+            }
+        }
+        let cwnd = cc.cwnd() as f64 / MSS as f64;
+        assert!((95.0..=105.0).contains(&cwnd), "cwnd {}", cwnd);
+
+        // 第二次丢包发生在 W_max 以下时触发快速收敛
+        cc.window.cwnd = 90 * MSS;
+        cc.on_loss(90 * MSS, now);
+        assert!((cc.w_max - 90.0 * 1.7 / 2.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,267 @@
+//! TCP 选项
+//!
+//! 握手时协商的 MSS（RFC 9293 第 3.7.1 节）、窗口扩大和时间戳（RFC 7323），
+//! 以及 SACK-permitted 和 SACK 块（RFC 2018）
+
+use std::time::{Duration, Instant};
+
+use super::{seq_ge, seq_lt};
+use crate::error::{Result, StackError};
+
+// 选项类型
+pub const TCPOPT_EOL: u8 = 0; // 选项列表结束
+pub const TCPOPT_NOP: u8 = 1; // 填充
+pub const TCPOPT_MSS: u8 = 2; // 最大段长度
+pub const TCPOPT_WINDOW_SCALE: u8 = 3; // 窗口扩大因子
+pub const TCPOPT_SACK_PERMITTED: u8 = 4; // 允许 SACK
+pub const TCPOPT_SACK: u8 = 5; // SACK 块
+pub const TCPOPT_TIMESTAMP: u8 = 8; // 时间戳
+
+/// 窗口扩大因子上限（RFC 7323 第 2.3 节）
+pub const MAX_WINDOW_SCALE: u8 = 14;
+
+/// 一个段里最多携带的 SACK 块数量（40 字节选项空间）
+pub const MAX_SACK_BLOCKS: usize = 4;
+
+/// 时间戳选项占用的字节数（含两个 NOP 填充）
+pub const TIMESTAMP_OPTION_LEN: usize = 12;
+
+/// TS.Recent 超过 24 天未更新视为失效（RFC 7323 第 5.5 节）
+const PAWS_IDLE_TIMEOUT: Duration = Duration::from_secs(24 * 24 * 60 * 60);
+
+/// TCP 选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TcpOption {
+    MaxSegmentSize(u16),
+    WindowScale(u8),
+    SackPermitted,
+    Sack(Vec<(u32, u32)>), // [左边界, 右边界)
+    Timestamp { tsval: u32, tsecr: u32 },
+    Unknown { kind: u8, data: Vec<u8> },
+}
+
+impl TcpOption {
+    /// 解析选项区：EOL 结束解析，NOP 跳过
+    pub fn parse_all(data: &[u8]) -> Result<Vec<TcpOption>> {
+        let mut options = Vec::new();
+        let mut i = 0;
+        while i < data.len() {
+            let kind = data[i];
+            match kind {
+                TCPOPT_EOL => break,
+                TCPOPT_NOP => {
+                    i += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            if i + 1 >= data.len() {
+                return Err(StackError::InvalidPacket(format!(
+                    "Tcp option {} truncated",
+                    kind
+                )));
+            }
+            let len = data[i + 1] as usize;
+            if len < 2 || i + len > data.len() {
+                return Err(StackError::InvalidPacket(format!(
+                    "Tcp option {} has invalid length {}",
+                    kind, len
+                )));
+            }
+            options.push(Self::parse_one(kind, &data[i + 2..i + len])?);
+            i += len;
+        }
+        Ok(options)
+    }
+
+    fn parse_one(kind: u8, value: &[u8]) -> Result<TcpOption> {
+        let invalid = || {
+            StackError::InvalidPacket(format!(
+                "Tcp option {} has invalid length {}",
+                kind,
+                value.len() + 2
+            ))
+        };
+        let option = match kind {
+            TCPOPT_MSS => {
+                let bytes: [u8; 2] = value.try_into().map_err(|_| invalid())?;
+                TcpOption::MaxSegmentSize(u16::from_be_bytes(bytes))
+            }
+            TCPOPT_WINDOW_SCALE => {
+                let [shift] = value else {
+                    return Err(invalid());
+                };
+                // 超过 14 时按 14 处理（RFC 7323 第 2.3 节）
+                TcpOption::WindowScale((*shift).min(MAX_WINDOW_SCALE))
+            }
+            TCPOPT_SACK_PERMITTED => {
+                if !value.is_empty() {
+                    return Err(invalid());
+                }
+                TcpOption::SackPermitted
+            }
+            TCPOPT_SACK => {
+                if value.is_empty() || !value.len().is_multiple_of(8) {
+                    return Err(invalid());
+                }
+                let blocks = value
+                    .chunks(8)
+                    .map(|block| {
+                        (
+                            u32::from_be_bytes([block[0], block[1], block[2], block[3]]),
+                            u32::from_be_bytes([block[4], block[5], block[6], block[7]]),
+                        )
+                    })
+                    .collect();
+                TcpOption::Sack(blocks)
+            }
+            TCPOPT_TIMESTAMP => {
+                if value.len() != 8 {
+                    return Err(invalid());
+                }
+                TcpOption::Timestamp {
+                    tsval: u32::from_be_bytes([value[0], value[1], value[2], value[3]]),
+                    tsecr: u32::from_be_bytes([value[4], value[5], value[6], value[7]]),
+                }
+            }
+            _ => TcpOption::Unknown {
+                kind,
+                data: value.to_vec(),
+            },
+        };
+        Ok(option)
+    }
+
+    pub fn kind(&self) -> u8 {
+        match self {
+            TcpOption::MaxSegmentSize(_) => TCPOPT_MSS,
+            TcpOption::WindowScale(_) => TCPOPT_WINDOW_SCALE,
+            TcpOption::SackPermitted => TCPOPT_SACK_PERMITTED,
+            TcpOption::Sack(_) => TCPOPT_SACK,
+            TcpOption::Timestamp { .. } => TCPOPT_TIMESTAMP,
+            TcpOption::Unknown { kind, .. } => *kind,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut value = Vec::new();
+        match self {
+            TcpOption::MaxSegmentSize(mss) => value.extend_from_slice(&mss.to_be_bytes()),
+            TcpOption::WindowScale(shift) => value.push(*shift),
+            TcpOption::SackPermitted => {}
+            TcpOption::Sack(blocks) => {
+                for (left, right) in blocks.iter().take(MAX_SACK_BLOCKS) {
+                    value.extend_from_slice(&left.to_be_bytes());
+                    value.extend_from_slice(&right.to_be_bytes());
+                }
+            }
+            TcpOption::Timestamp { tsval, tsecr } => {
+                value.extend_from_slice(&tsval.to_be_bytes());
+                value.extend_from_slice(&tsecr.to_be_bytes());
+            }
+            TcpOption::Unknown { data, .. } => value.extend_from_slice(data),
+        }
+        let mut bytes = Vec::with_capacity(value.len() + 2);
+        bytes.push(self.kind());
+        bytes.push((value.len() + 2) as u8);
+        bytes.extend_from_slice(&value);
+        bytes
+    }
+
+    /// 构造选项区，每个选项前用 NOP 填充，使其结尾按 4 字节对齐
+    pub fn build_all(options: &[TcpOption]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for option in options {
+            let option = option.to_bytes();
+            while !(bytes.len() + option.len()).is_multiple_of(4) {
+                bytes.push(TCPOPT_NOP);
+            }
+            bytes.extend_from_slice(&option);
+        }
+        bytes
+    }
+}
+
+/// 防止序列号回绕（PAWS，RFC 7323 第 5 节）
+/// 记录对方最近的时间戳 TS.Recent，时间戳更旧的段视为旧的重复段
+#[derive(Debug, Clone)]
+pub struct Paws {
+    recent: u32,         // TS.Recent
+    recent_age: Instant, // TS.Recent 的更新时间
+}
+
+impl Paws {
+    pub fn new(tsval: u32, now: Instant) -> Self {
+        Self {
+            recent: tsval,
+            recent_age: now,
+        }
+    }
+
+    pub fn recent(&self) -> u32 {
+        self.recent
+    }
+
+    /// 时间戳比 TS.Recent 旧且 TS.Recent 仍然有效时拒绝该段
+    pub fn is_stale(&self, tsval: u32, now: Instant) -> bool {
+        seq_lt(tsval, self.recent) && now.duration_since(self.recent_age) < PAWS_IDLE_TIMEOUT
+    }
+
+    /// 更新 TS.Recent，只会向前推进
+    pub fn update(&mut self, tsval: u32, now: Instant) {
+        if seq_ge(tsval, self.recent) || now.duration_since(self.recent_age) >= PAWS_IDLE_TIMEOUT {
+            self.recent = tsval;
+            self.recent_age = now;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options_roundtrip() {
+        let options = vec![
+            TcpOption::MaxSegmentSize(1460),
+            TcpOption::WindowScale(7),
+            TcpOption::SackPermitted,
+            TcpOption::Timestamp {
+                tsval: 100,
+                tsecr: 0,
+            },
+            TcpOption::Sack(vec![(1000, 2000), (3000, 4000)]),
+        ];
+        let bytes = TcpOption::build_all(&options);
+        assert!(bytes.len().is_multiple_of(4));
+        assert_eq!(&bytes[..8], &[2, 4, 0x05, 0xb4, 1, 3, 3, 7]);
+        assert_eq!(TcpOption::parse_all(&bytes).unwrap(), options);
+
+        // 长度字段错误或越界
+        assert!(TcpOption::parse_all(&[2, 3, 0]).is_err());
+        assert!(TcpOption::parse_all(&[8, 10, 0, 0]).is_err());
+        // EOL 之后的内容被忽略
+        assert_eq!(
+            TcpOption::parse_all(&[1, 4, 2, 0, 0xff]).unwrap(),
+            vec![TcpOption::SackPermitted]
+        );
+    }
+
+    #[test]
+    fn test_paws() {
+        let now = Instant::now();
+        let mut paws = Paws::new(1000, now);
+        assert!(paws.is_stale(999, now));
+        assert!(!paws.is_stale(1000, now));
+        paws.update(2000, now);
+        paws.update(1500, now);
+        assert_eq!(paws.recent(), 2000);
+        // 时间戳跨越 2^32 回绕后仍然是较新的
+        let mut paws = Paws::new(u32::MAX - 10, now);
+        paws.update(5, now);
+        assert_eq!(paws.recent(), 5);
+        assert!(paws.is_stale(u32::MAX, now));
+        // 长时间空闲后 TS.Recent 失效
+        assert!(!paws.is_stale(1, now + PAWS_IDLE_TIMEOUT));
+    }
+}
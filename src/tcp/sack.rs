@@ -0,0 +1,142 @@
+//! SACK 记分板
+//!
+//! 记录对方通过 SACK 块（RFC 2018）确认的乱序数据，
+//! 快速恢复时按 RFC 6675 只重传真正丢失的空洞
+
+use super::{seq_gt, seq_le, seq_lt};
+
+/// 已被对方 SACK 的序列号区间
+#[derive(Debug, Default)]
+pub struct SackScoreboard {
+    blocks: Vec<(u32, u32)>, // 按序列号排序且不重叠的 [start, end)
+    high_rxt: u32,           // 本轮恢复中已重传到的位置（HighRxt）
+}
+
+impl SackScoreboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 合并对方通告的 SACK 块，只接受落在 (una, nxt] 内的块
+    pub fn add(&mut self, blocks: &[(u32, u32)], una: u32, nxt: u32) {
+        for &(start, end) in blocks {
+            if !seq_lt(start, end) || !seq_gt(end, una) || seq_gt(end, nxt) {
+                continue;
+            }
+            let start = if seq_lt(start, una) { una } else { start };
+            self.blocks.push((start, end));
+        }
+        self.blocks
+            .sort_by_key(|&(start, _)| start.wrapping_sub(una));
+
+        let mut merged: Vec<(u32, u32)> = Vec::with_capacity(self.blocks.len());
+        for &(start, end) in &self.blocks {
+            match merged.last_mut() {
+                Some(last) if seq_le(start, last.1) => {
+                    if seq_gt(end, last.1) {
+                        last.1 = end;
+                    }
+                }
+                _ => merged.push((start, end)),
+            }
+        }
+        self.blocks = merged;
+    }
+
+    /// 累计确认推进到 una，删除已经不需要的块
+    pub fn on_ack(&mut self, una: u32) {
+        self.blocks.retain(|&(_, end)| seq_gt(end, una));
+        if let Some(first) = self.blocks.first_mut()
+            && seq_lt(first.0, una)
+        {
+            first.0 = una;
+        }
+        if seq_lt(self.high_rxt, una) {
+            self.high_rxt = una;
+        }
+    }
+
+    /// 进入快速恢复，从 una 开始寻找空洞
+    pub fn start_recovery(&mut self, una: u32) {
+        self.high_rxt = una;
+    }
+
+    /// [start, end) 是否已被完整地 SACK
+    pub fn is_sacked(&self, start: u32, end: u32) -> bool {
+        self.blocks
+            .iter()
+            .any(|&(s, e)| seq_le(s, start) && seq_le(end, e))
+    }
+
+    /// 被 SACK 的最高序列号
+    pub fn high_sacked(&self) -> Option<u32> {
+        self.blocks.last().map(|&(_, end)| end)
+    }
+
+    /// 是否是本轮需要重传的空洞：
+    /// 没有被 SACK，位于最高 SACK 之前，并且本轮还没有重传过
+    pub fn is_hole(&self, start: u32, end: u32) -> bool {
+        let Some(high_sacked) = self.high_sacked() else {
+            return false;
+        };
+        !seq_lt(start, self.high_rxt) && seq_le(end, high_sacked) && !self.is_sacked(start, end)
+    }
+
+    /// 记录已重传到 end
+    pub fn mark_retransmitted(&mut self, end: u32) {
+        if seq_gt(end, self.high_rxt) {
+            self.high_rxt = end;
+        }
+    }
+
+    /// 重传超时后不再信任之前的 SACK 信息（RFC 2018 第 8 节）
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn blocks(&self) -> &[(u32, u32)] {
+        &self.blocks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scoreboard_holes() {
+        let mut board = SackScoreboard::new();
+        // 序列号跨越 2^32 回绕
+        let una = u32::MAX - 999;
+        let seg = |i: u32| {
+            let start = una.wrapping_add(i * 500);
+            (start, start.wrapping_add(500))
+        };
+        let nxt = seg(5).1;
+
+        // 段 1 丢失，段 2、4 被 SACK（乱序到达）；越界的块被忽略
+        board.add(&[seg(2), seg(4), (nxt, nxt.wrapping_add(10))], una, nxt);
+        board.add(&[(seg(2).0, seg(3).1)], una, nxt);
+        assert_eq!(board.blocks(), &[(seg(2).0, seg(4).1)]);
+        assert_eq!(board.high_sacked(), Some(seg(4).1));
+
+        board.start_recovery(una);
+        assert!(board.is_hole(seg(0).0, seg(0).1));
+        assert!(board.is_hole(seg(1).0, seg(1).1));
+        assert!(!board.is_hole(seg(3).0, seg(3).1)); // 已被 SACK
+        assert!(!board.is_hole(seg(5).0, seg(5).1)); // 在最高 SACK 之后
+
+        // 重传过的空洞不再重复重传
+        board.mark_retransmitted(seg(0).1);
+        assert!(!board.is_hole(seg(0).0, seg(0).1));
+        assert!(board.is_hole(seg(1).0, seg(1).1));
+
+        // 累计确认越过 SACK 块后记分板清空
+        board.on_ack(seg(4).1);
+        assert!(board.is_empty());
+    }
+}
@@ -0,0 +1,1471 @@
+//! TCP 控制块（Transmission Control Block）
+//!
+//! 按照 RFC 9293 第 3.10 节实现连接状态机：三次握手、数据收发、四次挥手、
+//! 同时打开/同时关闭以及 RST 处理
+
+use std::collections::VecDeque;
+use std::net::SocketAddrV4;
+use std::time::{Duration, Instant};
+
+use tracing::{debug, info};
+
+use super::congestion::{CongestionAlgorithm, CongestionControl};
+use super::options::{MAX_SACK_BLOCKS, MAX_WINDOW_SCALE, Paws, TIMESTAMP_OPTION_LEN, TcpOption};
+use super::sack::SackScoreboard;
+use super::timer::{DEFAULT_MAX_RETRIES, RetransmitQueue};
+use super::window::{PersistTimer, ReceiveWindow, SendWindow};
+use super::{TCP_ACK, TCP_FIN, TCP_PSH, TCP_RST, TCP_SYN, TcpSegment, seq_gt, seq_le, seq_lt};
+use crate::error::{Result, StackError};
+
+/// 没有协商 MSS 时使用的默认值（RFC 9293 第 3.7.1 节）
+pub const DEFAULT_MSS: usize = 536;
+
+/// 对方通告的 MSS 过小时使用的下限
+const MIN_MSS: usize = 64;
+
+/// IPv4 和 TCP 固定头部的长度，MSS = MTU - 40
+const IPV4_TCP_HEADER_LEN: usize = 40;
+
+/// 收发缓冲区大小，超过 65535 时需要窗口扩大选项才能完整通告
+const BUFFER_SIZE: usize = 256 * 1024;
+
+/// 报文最大生存时间，TIME-WAIT 需要等待 2 * MSL
+const MSL: Duration = Duration::from_secs(30);
+
+/// TCP 连接状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpState {
+    Closed,      // 没有连接
+    Listen,      // 等待连接请求
+    SynSent,     // 已发送 SYN，等待对方 SYN
+    SynReceived, // 已收到 SYN 并回复 SYN-ACK，等待确认
+    Established, // 连接已建立
+    FinWait1,    // 已发送 FIN，等待确认或对方 FIN
+    FinWait2,    // 我方 FIN 已确认，等待对方 FIN
+    CloseWait,   // 已收到对方 FIN，等待应用关闭
+    Closing,     // 同时关闭，等待我方 FIN 的确认
+    LastAck,     // 被动关闭，等待我方 FIN 的确认
+    TimeWait,    // 等待 2MSL 确保对方收到最后的 ACK
+}
+
+impl TcpState {
+    /// 是否已经完成同步（收到过对方的 SYN）
+    pub fn is_synchronized(&self) -> bool {
+        !matches!(
+            self,
+            TcpState::Closed | TcpState::Listen | TcpState::SynSent | TcpState::SynReceived
+        )
+    }
+}
+
+/// 握手时通告的参数和希望启用的选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpConfig {
+    pub mss: usize,                      // 通告给对方的 MSS
+    pub window_scale: bool,              // 窗口扩大（RFC 7323）
+    pub sack: bool,                      // 选择确认（RFC 2018）
+    pub timestamps: bool,                // 时间戳和 PAWS（RFC 7323）
+    pub congestion: CongestionAlgorithm, // 拥塞控制算法
+}
+
+impl TcpConfig {
+    /// 根据网卡 MTU 计算 MSS
+    pub fn from_mtu(mtu: usize) -> Self {
+        Self {
+            mss: mtu.saturating_sub(IPV4_TCP_HEADER_LEN).max(MIN_MSS),
+            window_scale: true,
+            sack: true,
+            timestamps: true,
+            congestion: CongestionAlgorithm::default(),
+        }
+    }
+}
+
+impl Default for TcpConfig {
+    fn default() -> Self {
+        Self::from_mtu(1500)
+    }
+}
+
+/// 丢包恢复状态，恢复点之前的数据全部确认后结束
+#[derive(Debug, Clone, Copy)]
+struct Recovery {
+    recover: u32,        // 进入恢复时的 SND.NXT（RFC 6582）
+    after_timeout: bool, // 由重传超时而不是快速重传进入
+}
+
+/// TCP 控制块
+#[derive(Debug)]
+pub struct Tcb {
+    state: TcpState,
+    local: SocketAddrV4,
+    remote: SocketAddrV4,
+
+    // 发送序列空间
+    iss: u32,         // 初始发送序列号
+    snd: SendWindow,  // SND.UNA/SND.NXT/SND.WND 和发送缓冲区
+    syn_window: bool, // SND.WND 还是 SYN 中未扩大的窗口
+
+    // 接收序列空间
+    irs: u32,           // 对方的初始序列号
+    rcv: ReceiveWindow, // RCV.NXT/RCV.WND 和接收缓冲区
+
+    config: TcpConfig,
+    mss: usize,           // 发送方向的有效 MSS（已扣除时间戳选项）
+    nagle: bool,          // 是否启用 Nagle 算法
+    fin_queued: bool,     // 应用已关闭发送方向，数据发完后发送 FIN
+    fin_seq: Option<u32>, // 已发送 FIN 的序列号
+    ack_pending: bool,    // 需要回复 ACK
+    outgoing: VecDeque<TcpSegment>,
+    retransmit: RetransmitQueue,
+    persist: PersistTimer,
+    cc: Box<dyn CongestionControl>,
+    dup_acks: u32,
+    recovery: Option<Recovery>,
+
+    // 握手时协商的选项
+    wscale_ok: bool,           // 双方都支持窗口扩大
+    snd_wscale: u8,            // 对方的窗口扩大因子
+    rcv_wscale: u8,            // 我方的窗口扩大因子
+    sack_ok: bool,             // 双方都支持 SACK
+    sack: SackScoreboard,      // 对方 SACK 的数据
+    last_ooo_seq: Option<u32>, // 最近收到的乱序段，放在第一个 SACK 块（RFC 2018 第 4 节）
+    paws: Option<Paws>,        // 双方都支持时间戳时记录 TS.Recent
+    ts_base: Instant,          // 时间戳时钟的起点
+    now: Instant,              // 最近一次处理事件的时间，用于生成时间戳
+
+    time_wait_deadline: Option<Instant>,
+    error: Option<String>,
+}
+
+impl Tcb {
+    /// 主动打开：发送 SYN 进入 SYN-SENT
+    pub fn connect(
+        local: SocketAddrV4,
+        remote: SocketAddrV4,
+        iss: u32,
+        config: &TcpConfig,
+        now: Instant,
+    ) -> Self {
+        let mut tcb = Self::new(local, remote, iss, TcpState::SynSent, config, now);
+        tcb.send_syn(now);
+        tcb
+    }
+
+    /// 被动打开：监听者收到 SYN 后创建连接，回复 SYN-ACK 进入 SYN-RECEIVED
+    pub fn accept(
+        local: SocketAddrV4,
+        remote: SocketAddrV4,
+        syn: &TcpSegment,
+        iss: u32,
+        config: &TcpConfig,
+        now: Instant,
+    ) -> Self {
+        let mut tcb = Self::new(local, remote, iss, TcpState::SynReceived, config, now);
+        tcb.irs = syn.seq_number;
+        tcb.rcv.init(syn.seq_number);
+        tcb.negotiate(syn, now);
+        tcb.snd.update(syn.window as u32, syn.seq_number, 0);
+        tcb.send_syn(now);
+        tcb
+    }
+
+    fn new(
+        local: SocketAddrV4,
+        remote: SocketAddrV4,
+        iss: u32,
+        state: TcpState,
+        config: &TcpConfig,
+        now: Instant,
+    ) -> Self {
+        Self {
+            state,
+            local,
+            remote,
+            iss,
+            snd: SendWindow::new(iss, BUFFER_SIZE),
+            syn_window: true,
+            irs: 0,
+            rcv: ReceiveWindow::new(BUFFER_SIZE),
+            config: config.clone(),
+            mss: DEFAULT_MSS,
+            nagle: true,
+            fin_queued: false,
+            fin_seq: None,
+            ack_pending: false,
+            outgoing: VecDeque::new(),
+            retransmit: RetransmitQueue::new(DEFAULT_MAX_RETRIES),
+            persist: PersistTimer::new(),
+            cc: config.congestion.build(DEFAULT_MSS),
+            dup_acks: 0,
+            recovery: None,
+            wscale_ok: false,
+            snd_wscale: 0,
+            rcv_wscale: 0,
+            sack_ok: false,
+            sack: SackScoreboard::new(),
+            last_ooo_seq: None,
+            paws: None,
+            ts_base: now,
+            now,
+            time_wait_deadline: None,
+            error: None,
+        }
+    }
+
+    pub fn state(&self) -> TcpState {
+        self.state
+    }
+
+    pub fn local(&self) -> SocketAddrV4 {
+        self.local
+    }
+
+    pub fn remote(&self) -> SocketAddrV4 {
+        self.remote
+    }
+
+    /// 连接异常终止的原因（被重置等）
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// 当前通告的接收窗口
+    pub fn rcv_wnd(&self) -> u32 {
+        self.rcv.wnd()
+    }
+
+    /// 对方通告的发送窗口
+    pub fn snd_wnd(&self) -> u32 {
+        self.snd.wnd
+    }
+
+    /// 最早的未确认序列号
+    pub fn snd_una(&self) -> u32 {
+        self.snd.una
+    }
+
+    /// 发送方向的有效 MSS
+    pub fn mss(&self) -> usize {
+        self.mss
+    }
+
+    /// 协商得到的窗口扩大因子（对方, 我方）
+    pub fn window_scale(&self) -> Option<(u8, u8)> {
+        self.wscale_ok.then_some((self.snd_wscale, self.rcv_wscale))
+    }
+
+    pub fn sack_permitted(&self) -> bool {
+        self.sack_ok
+    }
+
+    pub fn timestamps_enabled(&self) -> bool {
+        self.paws.is_some()
+    }
+
+    /// 关闭 Nagle 算法后小段会立即发送
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.nagle = !nodelay;
+    }
+
+    /// 切换拥塞控制算法，保留当前的拥塞窗口和慢启动阈值
+    pub fn set_congestion_control(&mut self, algorithm: CongestionAlgorithm) {
+        let window = self.cc.window().clone();
+        self.cc = algorithm.build(self.mss);
+        *self.cc.window_mut() = window;
+        self.config.congestion = algorithm;
+    }
+
+    pub fn congestion_control(&self) -> &dyn CongestionControl {
+        self.cc.as_ref()
+    }
+
+    /// 设置最大重传次数
+    pub fn set_max_retries(&mut self, max_retries: u32) {
+        self.retransmit.set_max_retries(max_retries);
+    }
+
+    pub fn retransmit_queue(&self) -> &RetransmitQueue {
+        &self.retransmit
+    }
+
+    /// 写入待发送数据，返回实际接受的字节数；数据在下一次 `poll` 时发出
+    pub fn send(&mut self, data: &[u8]) -> Result<usize> {
+        match self.state {
+            TcpState::SynSent
+            | TcpState::SynReceived
+            | TcpState::Established
+            | TcpState::CloseWait => {}
+            _ => return Err(self.closed_error("connection closing")),
+        }
+        if self.fin_queued {
+            return Err(self.closed_error("connection closing"));
+        }
+        Ok(self.snd.buffer.enqueue_slice(data))
+    }
+
+    /// 读取已收到的数据；返回 0 表示对方已关闭发送方向
+    pub fn recv(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.rcv.is_empty() {
+            if let Some(reason) = &self.error {
+                return Err(StackError::ConnectionFailed(reason.clone()));
+            }
+            return match self.state {
+                TcpState::CloseWait
+                | TcpState::LastAck
+                | TcpState::Closing
+                | TcpState::TimeWait
+                | TcpState::Closed => Ok(0),
+                _ => Err(StackError::Io(std::io::ErrorKind::WouldBlock.into())),
+            };
+        }
+        let len = self.rcv.read(buf);
+        // 窗口明显变大时通知对方
+        if self.state.is_synchronized() && self.rcv.window_update_due(self.mss) {
+            self.ack_pending = true;
+        }
+        Ok(len)
+    }
+
+    /// 关闭连接的发送方向，FIN 在下一次 `poll` 时发出
+    pub fn close(&mut self) {
+        match self.state {
+            TcpState::Listen | TcpState::SynSent => self.state = TcpState::Closed,
+            TcpState::SynReceived | TcpState::Established => {
+                self.fin_queued = true;
+                self.state = TcpState::FinWait1;
+            }
+            TcpState::CloseWait => {
+                self.fin_queued = true;
+                self.state = TcpState::LastAck;
+            }
+            _ => {}
+        }
+    }
+
+    /// 立即中止连接并发送 RST
+    pub fn abort(&mut self) {
+        if matches!(
+            self.state,
+            TcpState::SynReceived
+                | TcpState::Established
+                | TcpState::FinWait1
+                | TcpState::FinWait2
+                | TcpState::CloseWait
+        ) {
+            let rst = self.make_segment(self.snd.nxt, TCP_RST, Vec::new());
+            self.outgoing.push_back(rst);
+        }
+        self.state = TcpState::Closed;
+        self.retransmit.clear();
+    }
+
+    /// 处理定时器并发送待发数据；重传次数用尽时连接失败
+    pub fn poll(&mut self, now: Instant) -> Result<()> {
+        self.now = now;
+        if let Some(deadline) = self.time_wait_deadline
+            && now >= deadline
+        {
+            debug!("{} -> {} TIME-WAIT expired", self.local, self.remote);
+            self.time_wait_deadline = None;
+            self.state = TcpState::Closed;
+        }
+
+        match self.retransmit.poll(now) {
+            Ok(Some(seg)) => {
+                self.cc.on_timeout(self.snd.in_flight(), now);
+                self.sack.clear();
+                self.dup_acks = 0;
+                self.recovery = Some(Recovery {
+                    recover: self.snd.nxt,
+                    after_timeout: true,
+                });
+                self.resend(seg);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                self.reset("retransmission timeout");
+                return Err(e);
+            }
+        }
+
+        // 对方窗口为 0 且没有在途数据时，用持续定时器发送窗口探测
+        if self.snd.wnd == 0 && self.snd.unsent() > 0 && self.retransmit.is_empty() {
+            self.persist.start(now, self.retransmit.rto().rto());
+            if self.persist.poll(now) {
+                self.send_window_probe();
+            }
+        } else {
+            self.persist.stop();
+        }
+
+        self.output(now);
+        Ok(())
+    }
+
+    /// 取出待发送的段
+    pub fn take_outgoing(&mut self) -> Vec<TcpSegment> {
+        self.outgoing.drain(..).collect()
+    }
+
+    /// 处理到达的段（RFC 9293 第 3.10.7 节）
+    pub fn on_segment(&mut self, seg: &TcpSegment, now: Instant) {
+        self.now = now;
+        match self.state {
+            TcpState::Closed | TcpState::Listen => {}
+            TcpState::SynSent => self.on_syn_sent(seg, now),
+            _ => self.on_synchronized(seg, now),
+        }
+        self.output(now);
+    }
+
+    fn on_syn_sent(&mut self, seg: &TcpSegment, now: Instant) {
+        // 第一步：检查 ACK
+        let ack_ok = if seg.has_flag(TCP_ACK) {
+            if seq_le(seg.ack_number, self.iss) || seq_gt(seg.ack_number, self.snd.nxt) {
+                if !seg.has_flag(TCP_RST) {
+                    self.send_reset(seg.ack_number);
+                }
+                return;
+            }
+            true
+        } else {
+            false
+        };
+
+        // 第二步：检查 RST
+        if seg.has_flag(TCP_RST) {
+            if ack_ok {
+                self.reset("connection refused");
+            }
+            return;
+        }
+
+        // 第四步：检查 SYN
+        if !seg.has_flag(TCP_SYN) {
+            return;
+        }
+        self.irs = seg.seq_number;
+        self.rcv.init(seg.seq_number);
+        self.negotiate(seg, now);
+        if ack_ok {
+            self.on_ack(seg.ack_number, now);
+        }
+        if seq_gt(self.snd.una, self.iss) {
+            // 普通三次握手：对方确认了我们的 SYN
+            self.state = TcpState::Established;
+            self.update_window(seg);
+            self.ack_pending = true;
+            info!("{} -> {} ESTABLISHED", self.local, self.remote);
+        } else {
+            // 同时打开：重新发送 SYN-ACK
+            self.state = TcpState::SynReceived;
+            self.snd.update(seg.window as u32, seg.seq_number, 0);
+            self.send_syn(now);
+        }
+    }
+
+    fn on_synchronized(&mut self, seg: &TcpSegment, now: Instant) {
+        let options = seg.tcp_options().unwrap_or_default();
+        let tsval = options.iter().find_map(|option| match option {
+            TcpOption::Timestamp { tsval, .. } => Some(*tsval),
+            _ => None,
+        });
+
+        // PAWS：时间戳比 TS.Recent 旧的段是旧的重复段（RFC 7323 第 5.3 节）
+        if let Some(paws) = &self.paws
+            && let Some(tsval) = tsval
+            && !seg.has_flag(TCP_RST)
+            && paws.is_stale(tsval, now)
+        {
+            debug!(
+                "{} -> {} PAWS dropped seq {}",
+                self.local, self.remote, seg.seq_number
+            );
+            self.ack_pending = true;
+            return;
+        }
+
+        // 第一步：检查序列号是否在接收窗口内
+        if !self.is_acceptable(seg) {
+            if !seg.has_flag(TCP_RST) {
+                self.ack_pending = true;
+            }
+            return;
+        }
+        if let Some(paws) = &mut self.paws
+            && let Some(tsval) = tsval
+            && seq_le(seg.seq_number, self.rcv.nxt)
+        {
+            paws.update(tsval, now);
+        }
+
+        // 第二步：检查 RST
+        if seg.has_flag(TCP_RST) {
+            match self.state {
+                TcpState::SynReceived => self.reset("connection refused"),
+                TcpState::Closing | TcpState::LastAck | TcpState::TimeWait => {
+                    self.state = TcpState::Closed
+                }
+                _ => self.reset("connection reset by peer"),
+            }
+            return;
+        }
+
+        // 第四步：已同步状态下收到 SYN，回复挑战 ACK（RFC 5961）
+        if seg.has_flag(TCP_SYN) {
+            self.ack_pending = true;
+            return;
+        }
+
+        // 第五步：检查 ACK
+        if !seg.has_flag(TCP_ACK) {
+            return;
+        }
+        if self.state == TcpState::SynReceived {
+            if seq_lt(self.snd.una, seg.ack_number) && seq_le(seg.ack_number, self.snd.nxt) {
+                self.state = TcpState::Established;
+                info!("{} -> {} ESTABLISHED", self.local, self.remote);
+            } else {
+                self.send_reset(seg.ack_number);
+                return;
+            }
+        }
+        if seq_gt(seg.ack_number, self.snd.nxt) {
+            // 确认了尚未发送的数据
+            self.ack_pending = true;
+            return;
+        }
+        if self.sack_ok {
+            for option in &options {
+                if let TcpOption::Sack(blocks) = option {
+                    self.sack.add(blocks, self.snd.una, self.snd.nxt);
+                }
+            }
+        }
+        if seq_lt(self.snd.una, seg.ack_number) {
+            self.on_ack(seg.ack_number, now);
+        } else if self.is_duplicate_ack(seg) {
+            self.on_duplicate_ack(now);
+        }
+        if seq_lt(self.snd.wl1, seg.seq_number)
+            || (self.snd.wl1 == seg.seq_number && seq_le(self.snd.wl2, seg.ack_number))
+        {
+            self.update_window(seg);
+        }
+
+        let fin_acked = self.fin_seq.is_some_and(|fin| seq_gt(self.snd.una, fin));
+        match self.state {
+            TcpState::FinWait1 if fin_acked => self.state = TcpState::FinWait2,
+            TcpState::Closing if fin_acked => self.enter_time_wait(now),
+            TcpState::LastAck if fin_acked => {
+                self.state = TcpState::Closed;
+                return;
+            }
+            _ => {}
+        }
+
+        // 第七步：处理数据
+        if matches!(
+            self.state,
+            TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2
+        ) {
+            self.receive_data(seg);
+        }
+
+        // 第八步：检查 FIN
+        if seg.has_flag(TCP_FIN) {
+            let fin_seq = seg.seq_number.wrapping_add(seg.payload.len() as u32);
+            if fin_seq != self.rcv.nxt && self.state != TcpState::TimeWait {
+                // FIN 之前还有数据没收到
+                return;
+            }
+            if self.state != TcpState::TimeWait {
+                self.rcv.receive_fin();
+            }
+            self.ack_pending = true;
+            match self.state {
+                TcpState::SynReceived | TcpState::Established => {
+                    self.state = TcpState::CloseWait;
+                }
+                TcpState::FinWait1 => {
+                    if fin_acked {
+                        self.enter_time_wait(now);
+                    } else {
+                        self.state = TcpState::Closing;
+                    }
+                }
+                TcpState::FinWait2 | TcpState::TimeWait => self.enter_time_wait(now),
+                _ => {}
+            }
+            debug!(
+                "{} -> {} received FIN, now {:?}",
+                self.local, self.remote, self.state
+            );
+        }
+    }
+
+    /// 段的可接受性检查（RFC 9293 第 3.10.7.4 节的表）
+    fn is_acceptable(&self, seg: &TcpSegment) -> bool {
+        let seg_len = segment_len(seg);
+        let wnd = self.rcv_wnd();
+        let in_window =
+            |seq: u32| seq_le(self.rcv.nxt, seq) && seq_lt(seq, self.rcv.nxt.wrapping_add(wnd));
+        match (seg_len, wnd) {
+            (0, 0) => seg.seq_number == self.rcv.nxt,
+            (0, _) => in_window(seg.seq_number),
+            (_, 0) => false,
+            _ => in_window(seg.seq_number) || in_window(seg.seq_number.wrapping_add(seg_len - 1)),
+        }
+    }
+
+    /// 处理新的确认，释放已确认的数据
+    fn on_ack(&mut self, ack: u32, now: Instant) {
+        let mut acked = ack.wrapping_sub(self.snd.una) as usize;
+        // SYN 和 FIN 各占一个序列号，但不在发送缓冲区里
+        if self.snd.una == self.iss {
+            acked -= 1;
+        }
+        if self.fin_seq.is_some_and(|fin| seq_gt(ack, fin)) {
+            acked -= 1;
+        }
+        self.snd.buffer.discard(acked);
+        self.snd.una = ack;
+        self.retransmit.on_ack(ack, now);
+        self.sack.on_ack(ack);
+        self.dup_acks = 0;
+
+        let rtt = self
+            .retransmit
+            .rto()
+            .srtt()
+            .unwrap_or_else(|| self.retransmit.rto().rto());
+        match self.recovery {
+            Some(recovery) if seq_lt(ack, recovery.recover) => {
+                // 部分确认：下一个空洞也丢了，立即重传（RFC 6582 第 3.2 节）
+                self.retransmit_lost();
+                if recovery.after_timeout {
+                    self.cc.on_ack(acked, rtt, now);
+                } else {
+                    self.cc.on_partial_ack(acked);
+                }
+            }
+            Some(recovery) => {
+                if !recovery.after_timeout {
+                    self.cc.on_recovery_exit();
+                }
+                self.recovery = None;
+            }
+            None => self.cc.on_ack(acked, rtt, now),
+        }
+    }
+
+    /// 重复 ACK 的判定（RFC 5681 第 2 节）
+    fn is_duplicate_ack(&self, seg: &TcpSegment) -> bool {
+        seg.ack_number == self.snd.una
+            && seg.payload.is_empty()
+            && !seg.has_flag(TCP_SYN | TCP_FIN)
+            // 握手后第一次得到扩大后的窗口，不算窗口更新
+            && (self.syn_window || self.seg_window(seg) == self.snd.wnd)
+            && self.snd.in_flight() > 0
+    }
+
+    /// 第三个重复 ACK 触发快速重传，之后的重复 ACK 让窗口膨胀
+    fn on_duplicate_ack(&mut self, now: Instant) {
+        self.dup_acks += 1;
+        match self.recovery {
+            Some(recovery) if !recovery.after_timeout => {
+                self.cc.on_dup_ack();
+                // 有 SACK 信息时每个重复 ACK 都可以补一个空洞
+                if self.sack_ok && !self.sack.is_empty() {
+                    self.retransmit_lost();
+                }
+            }
+            None if self.dup_acks == 3 => {
+                debug!(
+                    "{} -> {} fast retransmit seq {}",
+                    self.local, self.remote, self.snd.una
+                );
+                self.cc.on_loss(self.snd.in_flight(), now);
+                self.recovery = Some(Recovery {
+                    recover: self.snd.nxt,
+                    after_timeout: false,
+                });
+                self.sack.start_recovery(self.snd.una);
+                self.retransmit_lost();
+            }
+            _ => {}
+        }
+    }
+
+    /// 重传丢失的段：有 SACK 信息时重传下一个空洞，否则重传最早的未确认段
+    fn retransmit_lost(&mut self) {
+        let seg = if self.sack_ok && !self.sack.is_empty() {
+            let sack = &self.sack;
+            self.retransmit.retransmit_where(|seg| {
+                sack.is_hole(
+                    seg.seq_number,
+                    seg.seq_number.wrapping_add(segment_len(seg)),
+                )
+            })
+        } else {
+            self.retransmit.retransmit_first()
+        };
+        if let Some(seg) = seg {
+            self.sack
+                .mark_retransmitted(seg.seq_number.wrapping_add(segment_len(&seg)));
+            self.resend(seg);
+        }
+    }
+
+    /// 根据对方 SYN 中的选项确定本连接使用的选项
+    fn negotiate(&mut self, syn: &TcpSegment, now: Instant) {
+        // 没有 MSS 选项时按默认值 536 处理
+        let mut peer_mss = DEFAULT_MSS;
+        for option in syn.tcp_options().unwrap_or_default() {
+            match option {
+                TcpOption::MaxSegmentSize(mss) => peer_mss = mss as usize,
+                TcpOption::WindowScale(shift) if self.config.window_scale => {
+                    self.wscale_ok = true;
+                    self.snd_wscale = shift;
+                    self.rcv_wscale = window_shift(BUFFER_SIZE);
+                }
+                TcpOption::SackPermitted if self.config.sack => self.sack_ok = true,
+                TcpOption::Timestamp { tsval, .. } if self.config.timestamps => {
+                    self.paws = Some(Paws::new(tsval, now));
+                }
+                _ => {}
+            }
+        }
+        self.rcv
+            .set_max_window((u16::MAX as u32) << self.rcv_wscale);
+
+        // 每个段都带时间戳选项，数据要相应减少（RFC 6691）
+        let mut mss = peer_mss.min(self.config.mss);
+        if self.paws.is_some() {
+            mss -= TIMESTAMP_OPTION_LEN.min(mss);
+        }
+        self.mss = mss.max(MIN_MSS);
+        self.cc.set_mss(self.mss);
+        debug!(
+            "{} -> {} negotiated mss {} wscale {:?} sack {} timestamps {}",
+            self.local,
+            self.remote,
+            self.mss,
+            self.window_scale(),
+            self.sack_ok,
+            self.paws.is_some()
+        );
+    }
+
+    /// 对方通告的窗口，SYN 中的窗口不扩大（RFC 7323 第 2.2 节）
+    fn seg_window(&self, seg: &TcpSegment) -> u32 {
+        if seg.has_flag(TCP_SYN) {
+            seg.window as u32
+        } else {
+            (seg.window as u32) << self.snd_wscale
+        }
+    }
+
+    fn update_window(&mut self, seg: &TcpSegment) {
+        self.snd
+            .update(self.seg_window(seg), seg.seq_number, seg.ack_number);
+        self.syn_window = seg.has_flag(TCP_SYN);
+    }
+
+    fn receive_data(&mut self, seg: &TcpSegment) {
+        if seg.payload.is_empty() {
+            return;
+        }
+        // 乱序段暂存在接收窗口里，等空洞补齐后一起交付；
+        // 无论按序与否都立即回复 ACK，乱序时就是重复 ACK
+        self.ack_pending = true;
+        if seq_gt(seg.seq_number, self.rcv.nxt) {
+            self.rcv.receive(seg.seq_number, &seg.payload);
+            self.last_ooo_seq = Some(seg.seq_number);
+            return;
+        }
+        // 丢弃已经收到过的部分
+        let skip = self.rcv.nxt.wrapping_sub(seg.seq_number) as usize;
+        if skip >= seg.payload.len() {
+            return;
+        }
+        self.rcv.receive(self.rcv.nxt, &seg.payload[skip..]);
+    }
+
+    fn enter_time_wait(&mut self, now: Instant) {
+        self.state = TcpState::TimeWait;
+        self.time_wait_deadline = Some(now + 2 * MSL);
+    }
+
+    fn reset(&mut self, reason: &str) {
+        info!("{} -> {} {}", self.local, self.remote, reason);
+        self.error = Some(reason.to_string());
+        self.state = TcpState::Closed;
+        self.outgoing.clear();
+        self.retransmit.clear();
+    }
+
+    fn closed_error(&self, default: &str) -> StackError {
+        StackError::ConnectionFailed(self.error.clone().unwrap_or_else(|| default.to_string()))
+    }
+
+    fn send_syn(&mut self, now: Instant) {
+        let flags = if self.state == TcpState::SynReceived {
+            TCP_SYN | TCP_ACK
+        } else {
+            TCP_SYN
+        };
+        let syn = self.make_segment(self.iss, flags, Vec::new());
+        // 同时打开时用 SYN-ACK 取代之前的 SYN
+        self.retransmit.clear();
+        self.transmit(syn, now);
+        self.snd.nxt = self.iss.wrapping_add(1);
+    }
+
+    fn send_reset(&mut self, seq: u32) {
+        let mut rst = self.make_segment(seq, TCP_RST, Vec::new());
+        rst.ack_number = 0;
+        self.outgoing.push_back(rst);
+    }
+
+    /// 发送一个字节的零窗口探测，不推进 SND.NXT
+    fn send_window_probe(&mut self) {
+        let mut byte = [0u8; 1];
+        if self
+            .snd
+            .buffer
+            .read_allocated(self.snd.in_flight(), &mut byte)
+            == 0
+        {
+            return;
+        }
+        debug!("{} -> {} zero window probe", self.local, self.remote);
+        let probe = self.make_segment(self.snd.nxt, TCP_ACK, byte.to_vec());
+        self.outgoing.push_back(probe);
+        self.ack_pending = false;
+    }
+
+    /// 按窗口发送缓冲区中的数据和 FIN，必要时补发纯 ACK
+    fn output(&mut self, now: Instant) {
+        self.rcv.update_window(self.mss);
+        let can_send = matches!(
+            self.state,
+            TcpState::Established | TcpState::CloseWait | TcpState::FinWait1 | TcpState::LastAck
+        );
+        // 我方 SYN 被确认之前不能发送数据
+        if can_send && seq_gt(self.snd.una, self.iss) && self.fin_seq.is_none() {
+            loop {
+                let len = self.snd.sendable(self.mss, self.cc.cwnd(), self.nagle);
+                if len == 0 {
+                    break;
+                }
+                let mut payload = vec![0u8; len];
+                self.snd
+                    .buffer
+                    .read_allocated(self.snd.in_flight(), &mut payload);
+                let flags = if len == self.snd.unsent() {
+                    TCP_ACK | TCP_PSH
+                } else {
+                    TCP_ACK
+                };
+                let segment = self.make_segment(self.snd.nxt, flags, payload);
+                self.transmit(segment, now);
+                self.snd.nxt = self.snd.nxt.wrapping_add(len as u32);
+                self.ack_pending = false;
+            }
+
+            if self.fin_queued && self.snd.unsent() == 0 {
+                let fin = self.make_segment(self.snd.nxt, TCP_FIN | TCP_ACK, Vec::new());
+                self.transmit(fin, now);
+                self.fin_seq = Some(self.snd.nxt);
+                self.snd.nxt = self.snd.nxt.wrapping_add(1);
+                self.ack_pending = false;
+            }
+        }
+
+        if self.ack_pending
+            && !matches!(
+                self.state,
+                TcpState::Closed | TcpState::Listen | TcpState::SynSent
+            )
+        {
+            let ack = self.make_segment(self.snd.nxt, TCP_ACK, Vec::new());
+            self.outgoing.push_back(ack);
+        }
+        self.ack_pending = false;
+    }
+
+    /// 重传时带上最新的确认号、窗口和选项
+    fn resend(&mut self, mut seg: TcpSegment) {
+        if seg.has_flag(TCP_ACK) {
+            seg.ack_number = self.rcv.nxt;
+        }
+        seg.window = self.window_field(seg.flags);
+        let options = self.options_for(seg.flags, seg.payload.len());
+        self.outgoing
+            .push_back(seg.with_options(TcpOption::build_all(&options)));
+    }
+
+    /// 发送占用序列号的段，同时放入重传队列
+    fn transmit(&mut self, segment: TcpSegment, now: Instant) {
+        self.retransmit.push(segment.clone(), now);
+        self.outgoing.push_back(segment);
+    }
+
+    /// 窗口字段，SYN 中的窗口不扩大
+    fn window_field(&self, flags: u8) -> u16 {
+        let wnd = if flags & TCP_SYN != 0 {
+            self.rcv_wnd()
+        } else {
+            self.rcv_wnd() >> self.rcv_wscale
+        };
+        wnd.min(u16::MAX as u32) as u16
+    }
+
+    /// 时间戳时钟，单位毫秒，以 ISS 作为每个连接不同的起始值
+    fn ts_value(&self) -> u32 {
+        let elapsed = self.now.duration_since(self.ts_base).as_millis() as u32;
+        self.iss.wrapping_add(elapsed)
+    }
+
+    /// 段要携带的选项：SYN 通告本端支持的选项，SYN-ACK 只回应对方提出的选项
+    fn options_for(&self, flags: u8, payload_len: usize) -> Vec<TcpOption> {
+        let mut options = Vec::new();
+        if flags & TCP_RST != 0 {
+            return options;
+        }
+        let offer = |enabled: bool, negotiated: bool| {
+            if self.state == TcpState::SynSent {
+                enabled
+            } else {
+                negotiated
+            }
+        };
+        let syn = flags & TCP_SYN != 0;
+        if syn {
+            let mss = self.config.mss.min(u16::MAX as usize) as u16;
+            options.push(TcpOption::MaxSegmentSize(mss));
+            if offer(self.config.window_scale, self.wscale_ok) {
+                options.push(TcpOption::WindowScale(window_shift(BUFFER_SIZE)));
+            }
+            if offer(self.config.sack, self.sack_ok) {
+                options.push(TcpOption::SackPermitted);
+            }
+        }
+        let timestamps = offer(self.config.timestamps, self.paws.is_some());
+        if timestamps {
+            options.push(TcpOption::Timestamp {
+                tsval: self.ts_value(),
+                tsecr: self.paws.as_ref().map_or(0, |paws| paws.recent()),
+            });
+        }
+        // 只在纯 ACK 里携带 SACK 块，避免数据段超过 MSS
+        if !syn && self.sack_ok && payload_len == 0 {
+            let max = if timestamps {
+                MAX_SACK_BLOCKS - 1
+            } else {
+                MAX_SACK_BLOCKS
+            };
+            let blocks = self.sack_blocks(max);
+            if !blocks.is_empty() {
+                options.push(TcpOption::Sack(blocks));
+            }
+        }
+        options
+    }
+
+    /// 接收方的 SACK 块，包含最近收到的乱序段的块排在最前面
+    fn sack_blocks(&self, max: usize) -> Vec<(u32, u32)> {
+        let mut blocks: Vec<(u32, u32)> = self
+            .rcv
+            .assembler()
+            .ranges()
+            .iter()
+            .map(|&(start, end)| {
+                (
+                    self.rcv.nxt.wrapping_add(start as u32),
+                    self.rcv.nxt.wrapping_add(end as u32),
+                )
+            })
+            .collect();
+        if let Some(seq) = self.last_ooo_seq
+            && let Some(index) = blocks
+                .iter()
+                .position(|&(start, end)| seq_le(start, seq) && seq_lt(seq, end))
+        {
+            let block = blocks.remove(index);
+            blocks.insert(0, block);
+        }
+        blocks.truncate(max);
+        blocks
+    }
+
+    fn make_segment(&self, seq: u32, flags: u8, payload: Vec<u8>) -> TcpSegment {
+        let ack = if flags & TCP_ACK != 0 {
+            self.rcv.nxt
+        } else {
+            0
+        };
+        let window = self.window_field(flags);
+        let options = self.options_for(flags, payload.len());
+        TcpSegment::build(
+            self.local.port(),
+            self.remote.port(),
+            seq,
+            ack,
+            flags,
+            window,
+            payload,
+        )
+        .with_options(TcpOption::build_all(&options))
+    }
+}
+
+/// 通告 capacity 大小的窗口所需的最小窗口扩大因子
+fn window_shift(capacity: usize) -> u8 {
+    let mut shift = 0;
+    while capacity >> shift > u16::MAX as usize && shift < MAX_WINDOW_SCALE {
+        shift += 1;
+    }
+    shift
+}
+
+/// 段占用的序列号数量（数据长度 + SYN + FIN）
+pub fn segment_len(seg: &TcpSegment) -> u32 {
+    let mut len = seg.payload.len() as u32;
+    if seg.has_flag(TCP_SYN) {
+        len += 1;
+    }
+    if seg.has_flag(TCP_FIN) {
+        len += 1;
+    }
+    len
+}
+
+/// 没有连接接收该段时应回复的 RST（RFC 9293 第 3.10.7.1 节）
+pub fn reset_for(seg: &TcpSegment) -> Option<TcpSegment> {
+    if seg.has_flag(TCP_RST) {
+        return None;
+    }
+    let rst = if seg.has_flag(TCP_ACK) {
+        TcpSegment::build(
+            seg.dst_port,
+            seg.src_port,
+            seg.ack_number,
+            0,
+            TCP_RST,
+            0,
+            Vec::new(),
+        )
+    } else {
+        TcpSegment::build(
+            seg.dst_port,
+            seg.src_port,
+            0,
+            seg.seq_number.wrapping_add(segment_len(seg)),
+            TCP_RST | TCP_ACK,
+            0,
+            Vec::new(),
+        )
+    };
+    Some(rst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn addrs() -> (SocketAddrV4, SocketAddrV4) {
+        (
+            SocketAddrV4::new(Ipv4Addr::new(192, 168, 10, 2), 8080),
+            SocketAddrV4::new(Ipv4Addr::new(192, 168, 10, 1), 40000),
+        )
+    }
+
+    /// 把一端发出的段交给另一端，直到双方都没有输出
+    fn exchange(a: &mut Tcb, b: &mut Tcb, now: Instant) {
+        loop {
+            a.poll(now).unwrap();
+            b.poll(now).unwrap();
+            let from_a = a.take_outgoing();
+            let from_b = b.take_outgoing();
+            if from_a.is_empty() && from_b.is_empty() {
+                break;
+            }
+            for seg in &from_a {
+                b.on_segment(seg, now);
+            }
+            for seg in &from_b {
+                a.on_segment(seg, now);
+            }
+        }
+    }
+
+    #[test]
+    fn test_handshake_data_and_close() {
+        let now = Instant::now();
+        let (server_addr, client_addr) = addrs();
+        let mut client = Tcb::connect(client_addr, server_addr, 1000, &TcpConfig::default(), now);
+        assert_eq!(client.state(), TcpState::SynSent);
+
+        let syn = client.take_outgoing().remove(0);
+        assert!(syn.has_flag(TCP_SYN));
+        let mut server = Tcb::accept(
+            server_addr,
+            client_addr,
+            &syn,
+            5000,
+            &TcpConfig::default(),
+            now,
+        );
+        assert_eq!(server.state(), TcpState::SynReceived);
+        exchange(&mut client, &mut server, now);
+        assert_eq!(client.state(), TcpState::Established);
+        assert_eq!(server.state(), TcpState::Established);
+
+        client.send(b"hello").unwrap();
+        exchange(&mut client, &mut server, now);
+        let mut buf = [0u8; 16];
+        assert_eq!(server.recv(&mut buf).unwrap(), 5);
+        assert_eq!(&buf[..5], b"hello");
+
+        // 四次挥手：客户端主动关闭
+        client.close();
+        exchange(&mut client, &mut server, now);
+        assert_eq!(client.state(), TcpState::FinWait2);
+        assert_eq!(server.state(), TcpState::CloseWait);
+        assert_eq!(server.recv(&mut buf).unwrap(), 0);
+
+        server.close();
+        exchange(&mut client, &mut server, now);
+        assert_eq!(server.state(), TcpState::Closed);
+        assert_eq!(client.state(), TcpState::TimeWait);
+
+        client.poll(now + 2 * MSL).unwrap();
+        assert_eq!(client.state(), TcpState::Closed);
+    }
+
+    #[test]
+    fn test_simultaneous_open_and_close() {
+        let now = Instant::now();
+        let (a_addr, b_addr) = addrs();
+        let mut a = Tcb::connect(a_addr, b_addr, 100, &TcpConfig::default(), now);
+        let mut b = Tcb::connect(b_addr, a_addr, 900, &TcpConfig::default(), now);
+
+        exchange(&mut a, &mut b, now);
+        assert_eq!(a.state(), TcpState::Established);
+        assert_eq!(b.state(), TcpState::Established);
+
+        // 双方同时发送 FIN
+        a.close();
+        b.close();
+        a.poll(now).unwrap();
+        b.poll(now).unwrap();
+        let fin_a = a.take_outgoing();
+        let fin_b = b.take_outgoing();
+        for seg in &fin_b {
+            a.on_segment(seg, now);
+        }
+        for seg in &fin_a {
+            b.on_segment(seg, now);
+        }
+        assert_eq!(a.state(), TcpState::Closing);
+        assert_eq!(b.state(), TcpState::Closing);
+
+        exchange(&mut a, &mut b, now);
+        assert_eq!(a.state(), TcpState::TimeWait);
+        assert_eq!(b.state(), TcpState::TimeWait);
+    }
+
+    #[test]
+    fn test_reset_handling() {
+        let now = Instant::now();
+        let (server_addr, client_addr) = addrs();
+        let mut client = Tcb::connect(client_addr, server_addr, 1000, &TcpConfig::default(), now);
+        let syn = client.take_outgoing().remove(0);
+
+        // 端口关闭：对端回复 RST|ACK
+        let rst = reset_for(&syn).unwrap();
+        assert!(rst.has_flag(TCP_RST) && rst.has_flag(TCP_ACK));
+        assert_eq!(rst.ack_number, 1001);
+        client.on_segment(&rst, now);
+        assert_eq!(client.state(), TcpState::Closed);
+        assert!(matches!(
+            client.recv(&mut [0u8; 4]),
+            Err(StackError::ConnectionFailed(_))
+        ));
+
+        // 窗口外的 RST 会被忽略
+        let mut client = Tcb::connect(client_addr, server_addr, 1000, &TcpConfig::default(), now);
+        let syn = client.take_outgoing().remove(0);
+        let mut server = Tcb::accept(
+            server_addr,
+            client_addr,
+            &syn,
+            5000,
+            &TcpConfig::default(),
+            now,
+        );
+        exchange(&mut client, &mut server, now);
+        let mut bogus = reset_for(&syn).unwrap();
+        bogus.flags = TCP_RST;
+        bogus.seq_number = 5000u32.wrapping_add(1 << 20);
+        client.on_segment(&bogus, now);
+        assert_eq!(client.state(), TcpState::Established);
+    }
+
+    #[test]
+    fn test_retransmission_and_failure() {
+        let now = Instant::now();
+        let (server_addr, client_addr) = addrs();
+        let mut client = Tcb::connect(client_addr, server_addr, 1000, &TcpConfig::default(), now);
+        let syn = client.take_outgoing().remove(0);
+        let mut server = Tcb::accept(
+            server_addr,
+            client_addr,
+            &syn,
+            5000,
+            &TcpConfig::default(),
+            now,
+        );
+        exchange(&mut client, &mut server, now);
+
+        // 第一次发送的数据丢失，RTO 到期后重传
+        client.send(b"lost").unwrap();
+        client.poll(now).unwrap();
+        assert_eq!(client.take_outgoing().len(), 1);
+        let rto = client.retransmit_queue().rto().rto();
+        client.poll(now + rto).unwrap();
+        let retransmitted = client.take_outgoing();
+        assert_eq!(retransmitted[0].payload, b"lost");
+        for seg in &retransmitted {
+            server.on_segment(seg, now + rto);
+        }
+        exchange(&mut client, &mut server, now + rto);
+        assert!(client.retransmit_queue().is_empty());
+
+        // 对方不再响应，重传次数用尽后连接失败
+        client.set_max_retries(2);
+        client.send(b"gone").unwrap();
+        let mut now = now + rto;
+        client.poll(now).unwrap();
+        for _ in 0..2 {
+            now = client.retransmit_queue().deadline().unwrap();
+            client.poll(now).unwrap();
+        }
+        now = client.retransmit_queue().deadline().unwrap();
+        assert!(matches!(
+            client.poll(now),
+            Err(StackError::ConnectionFailed(_))
+        ));
+        assert_eq!(client.state(), TcpState::Closed);
+    }
+
+    #[test]
+    fn test_fast_retransmit() {
+        let now = Instant::now();
+        let (server_addr, client_addr) = addrs();
+        // 不带 SACK，只靠重复 ACK 触发 NewReno 快速重传
+        let config = TcpConfig {
+            sack: false,
+            ..TcpConfig::default()
+        };
+        let mut client = Tcb::connect(client_addr, server_addr, 1000, &config, now);
+        let syn = client.take_outgoing().remove(0);
+        let mut server = Tcb::accept(server_addr, client_addr, &syn, 5000, &config, now);
+        exchange(&mut client, &mut server, now);
+
+        // 发出四个满 MSS 的段，第一个丢失
+        let mss = client.mss();
+        client.send(&vec![7; mss * 4]).unwrap();
+        client.poll(now).unwrap();
+        let segments = client.take_outgoing();
+        assert_eq!(segments.len(), 4);
+        let cwnd = client.congestion_control().cwnd();
+
+        // 后三个段各产生一个重复 ACK，第三个触发快速重传，不必等 RTO
+        let now = now + Duration::from_millis(10);
+        for seg in &segments[1..] {
+            server.on_segment(seg, now);
+            server.poll(now).unwrap();
+            for ack in server.take_outgoing() {
+                client.on_segment(&ack, now);
+            }
+        }
+        let retransmitted = client.take_outgoing();
+        assert_eq!(retransmitted.len(), 1);
+        assert_eq!(retransmitted[0].seq_number, segments[0].seq_number);
+        assert!(client.congestion_control().ssthresh() < cwnd);
+
+        // 补上空洞后全部数据被确认，退出快速恢复
+        server.on_segment(&retransmitted[0], now);
+        exchange(&mut client, &mut server, now);
+        assert!(client.retransmit_queue().is_empty());
+        assert_eq!(
+            client.congestion_control().cwnd(),
+            client.congestion_control().ssthresh()
+        );
+        let mut buf = vec![0; mss * 4];
+        assert_eq!(server.recv(&mut buf).unwrap(), mss * 4);
+    }
+
+    #[test]
+    fn test_sack_fast_retransmit() {
+        let now = Instant::now();
+        let (server_addr, client_addr) = addrs();
+        let mut client = Tcb::connect(client_addr, server_addr, 1000, &TcpConfig::default(), now);
+        let syn = client.take_outgoing().remove(0);
+        let mut server = Tcb::accept(
+            server_addr,
+            client_addr,
+            &syn,
+            5000,
+            &TcpConfig::default(),
+            now,
+        );
+        exchange(&mut client, &mut server, now);
+
+        // 发出七个满 MSS 的段，第 0 和第 2 个丢失
+        let mss = client.mss();
+        client.send(&vec![7; mss * 7]).unwrap();
+        client.poll(now).unwrap();
+        let segments = client.take_outgoing();
+        assert_eq!(segments.len(), 7);
+        let cwnd = client.congestion_control().cwnd();
+
+        // 第一个 ACK 带来按扩大因子换算后的窗口，是窗口更新而不是重复 ACK；
+        // 之后每个段产生一个带 SACK 块的重复 ACK：第三个触发快速重传，
+        // 第四个根据 SACK 记分板补上第二个空洞，不必等 RTO
+        let now = now + Duration::from_millis(10);
+        for seg in segments.iter().skip(1).filter(|seg| **seg != segments[2]) {
+            server.on_segment(seg, now);
+            server.poll(now).unwrap();
+            for ack in server.take_outgoing() {
+                client.on_segment(&ack, now);
+            }
+        }
+        let retransmitted = client.take_outgoing();
+        let seqs: Vec<u32> = retransmitted.iter().map(|seg| seg.seq_number).collect();
+        assert_eq!(seqs, [segments[0].seq_number, segments[2].seq_number]);
+        assert!(client.congestion_control().ssthresh() < cwnd);
+
+        // 补上空洞后全部数据被确认，退出快速恢复
+        for seg in &retransmitted {
+            server.on_segment(seg, now);
+        }
+        exchange(&mut client, &mut server, now);
+        assert!(client.retransmit_queue().is_empty());
+        assert_eq!(
+            client.congestion_control().cwnd(),
+            client.congestion_control().ssthresh()
+        );
+        let mut buf = vec![0; mss * 7];
+        assert_eq!(server.recv(&mut buf).unwrap(), mss * 7);
+    }
+
+    #[test]
+    fn test_option_negotiation_and_paws() {
+        let now = Instant::now();
+        let (server_addr, client_addr) = addrs();
+        let mut client = Tcb::connect(client_addr, server_addr, 1000, &TcpConfig::default(), now);
+        let syn = client.take_outgoing().remove(0);
+        let options = syn.tcp_options().unwrap();
+        assert!(options.contains(&TcpOption::MaxSegmentSize(1460)));
+        assert!(options.contains(&TcpOption::SackPermitted));
+
+        // 服务端不支持 SACK 和时间戳时，SYN-ACK 里也不会出现这些选项
+        let config = TcpConfig {
+            sack: false,
+            timestamps: false,
+            ..TcpConfig::from_mtu(1280)
+        };
+        let mut server = Tcb::accept(server_addr, client_addr, &syn, 5000, &config, now);
+        let syn_ack = server.take_outgoing().remove(0);
+        let options = syn_ack.tcp_options().unwrap();
+        assert_eq!(
+            options,
+            [TcpOption::MaxSegmentSize(1240), TcpOption::WindowScale(3)]
+        );
+        client.on_segment(&syn_ack, now);
+        exchange(&mut client, &mut server, now);
+        assert_eq!(client.mss(), 1240);
+        assert_eq!(client.window_scale(), Some((3, 3)));
+        assert!(!client.sack_permitted() && !client.timestamps_enabled());
+        // 窗口超过 65535，按扩大因子通告
+        assert_eq!(server.snd_wnd(), client.rcv_wnd() & !7);
+        assert!(server.snd_wnd() > u16::MAX as u32);
+
+        // 双方都支持时间戳：重放时间戳更旧的段会被 PAWS 丢弃
+        let mut client = Tcb::connect(client_addr, server_addr, 1000, &TcpConfig::default(), now);
+        let syn = client.take_outgoing().remove(0);
+        let mut server = Tcb::accept(
+            server_addr,
+            client_addr,
+            &syn,
+            5000,
+            &TcpConfig::default(),
+            now,
+        );
+        exchange(&mut client, &mut server, now);
+        assert!(server.timestamps_enabled());
+        assert_eq!(server.mss(), 1448);
+
+        client.send(b"old").unwrap();
+        client.poll(now).unwrap();
+        let old = client.take_outgoing().remove(0);
+        let later = now + Duration::from_secs(1);
+        client.send(b"new").unwrap();
+        exchange(&mut client, &mut server, later);
+        let mut buf = [0u8; 8];
+        assert_eq!(server.recv(&mut buf).unwrap(), 6);
+
+        // 伪造一个序列号合法但时间戳过期的段
+        let mut stale = old.clone();
+        stale.seq_number = server.rcv.nxt;
+        server.on_segment(&stale, later);
+        assert!(server.recv(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_zero_window_probe() {
+        let now = Instant::now();
+        let (server_addr, client_addr) = addrs();
+        let mut client = Tcb::connect(client_addr, server_addr, 1000, &TcpConfig::default(), now);
+        let syn = client.take_outgoing().remove(0);
+        let mut server = Tcb::accept(
+            server_addr,
+            client_addr,
+            &syn,
+            5000,
+            &TcpConfig::default(),
+            now,
+        );
+        exchange(&mut client, &mut server, now);
+
+        // 服务端不读取数据，接收缓冲区被填满
+        assert_eq!(client.send(&vec![7u8; BUFFER_SIZE]).unwrap(), BUFFER_SIZE);
+        exchange(&mut client, &mut server, now);
+        assert_eq!(client.snd_wnd(), 0);
+        assert_eq!(server.rcv_wnd(), 0);
+
+        // 还有数据没发出去，持续定时器到期后发送 1 字节探测
+        assert_eq!(client.send(b"more").unwrap(), 4);
+        client.poll(now).unwrap();
+        assert!(client.take_outgoing().is_empty());
+        let rto = client.retransmit_queue().rto().rto();
+        client.poll(now + rto).unwrap();
+        let probe = client.take_outgoing();
+        assert_eq!(probe.len(), 1);
+        assert_eq!(probe[0].payload.len(), 1);
+
+        // 接收方读走数据后窗口重新打开，剩余数据得以发送
+        let mut buf = vec![0u8; BUFFER_SIZE];
+        assert_eq!(server.recv(&mut buf).unwrap(), BUFFER_SIZE);
+        exchange(&mut client, &mut server, now + rto);
+        assert!(client.snd_wnd() > 0);
+        assert_eq!(server.recv(&mut buf).unwrap(), 4);
+        assert_eq!(&buf[..4], b"more");
+    }
+}
@@ -0,0 +1,330 @@
+//! TCP 重传定时器
+//!
+//! 按 RFC 6298 计算 RTO，并用 Karn 算法过滤重传段的 RTT 样本
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use tracing::debug;
+
+use super::tcb::segment_len;
+use super::{TcpSegment, seq_ge};
+use crate::error::{Result, StackError};
+
+/// 初始 RTO（RFC 6298 第 2.1 节）
+const INITIAL_RTO: Duration = Duration::from_secs(1);
+/// RTO 下限（RFC 6298 第 2.4 节）
+const MIN_RTO: Duration = Duration::from_secs(1);
+/// RTO 上限（RFC 6298 第 2.5 节）
+const MAX_RTO: Duration = Duration::from_secs(60);
+/// 时钟粒度 G
+const CLOCK_GRANULARITY: Duration = Duration::from_millis(1);
+
+/// 默认最大重传次数，超过后连接失败
+pub const DEFAULT_MAX_RETRIES: u32 = 12;
+
+/// RTO 估计器
+#[derive(Debug, Clone)]
+pub struct RtoEstimator {
+    srtt: Option<Duration>, // 平滑 RTT，收到第一个样本前为空
+    rttvar: Duration,       // RTT 偏差
+    rto: Duration,          // 不含退避的 RTO
+    backoff: u32,           // 指数退避次数
+}
+
+impl RtoEstimator {
+    pub fn new() -> Self {
+        Self {
+            srtt: None,
+            rttvar: Duration::ZERO,
+            rto: INITIAL_RTO,
+            backoff: 0,
+        }
+    }
+
+    /// 用新的 RTT 样本更新 SRTT/RTTVAR（RFC 6298 第 2.2、2.3 节）
+    pub fn on_rtt_sample(&mut self, rtt: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                // RTTVAR = 3/4 * RTTVAR + 1/4 * |SRTT - R'|
+                let delta = srtt.abs_diff(rtt);
+                self.rttvar = (self.rttvar * 3 + delta) / 4;
+                // SRTT = 7/8 * SRTT + 1/8 * R'
+                self.srtt = Some((srtt * 7 + rtt) / 8);
+            }
+        }
+        let srtt = self.srtt.unwrap_or(rtt);
+        self.rto = (srtt + CLOCK_GRANULARITY.max(self.rttvar * 4)).clamp(MIN_RTO, MAX_RTO);
+        // 拿到新的有效样本后不再退避
+        self.backoff = 0;
+    }
+
+    /// 定时器超时后 RTO 翻倍（RFC 6298 第 5.5 节）
+    pub fn back_off(&mut self) {
+        self.backoff = self.backoff.saturating_add(1);
+    }
+
+    /// 当前 RTO（含退避）
+    pub fn rto(&self) -> Duration {
+        let factor = 1u32.checked_shl(self.backoff).unwrap_or(u32::MAX);
+        self.rto.saturating_mul(factor).min(MAX_RTO)
+    }
+
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    pub fn rttvar(&self) -> Duration {
+        self.rttvar
+    }
+}
+
+impl Default for RtoEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 重传队列中的一个段
+#[derive(Debug, Clone)]
+struct RetransmitEntry {
+    segment: TcpSegment,
+    first_sent: Instant,
+    retransmitted: bool, // 重传过的段不能作为 RTT 样本（Karn 算法）
+}
+
+impl RetransmitEntry {
+    fn end_seq(&self) -> u32 {
+        self.segment
+            .seq_number
+            .wrapping_add(segment_len(&self.segment))
+    }
+}
+
+/// 重传队列
+/// 保存已发送但未确认的段，并维护唯一的重传定时器
+#[derive(Debug)]
+pub struct RetransmitQueue {
+    entries: VecDeque<RetransmitEntry>,
+    rto: RtoEstimator,
+    deadline: Option<Instant>, // 重传定时器到期时间
+    retries: u32,              // 当前连续重传次数
+    max_retries: u32,
+}
+
+impl RetransmitQueue {
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            rto: RtoEstimator::new(),
+            deadline: None,
+            retries: 0,
+            max_retries,
+        }
+    }
+
+    /// 记录一个刚发出的段，定时器未运行时启动它（RFC 6298 第 5.1 节）
+    pub fn push(&mut self, segment: TcpSegment, now: Instant) {
+        self.entries.push_back(RetransmitEntry {
+            segment,
+            first_sent: now,
+            retransmitted: false,
+        });
+        if self.deadline.is_none() {
+            self.deadline = Some(now + self.rto.rto());
+        }
+    }
+
+    /// 处理新的确认号，删除已完全确认的段
+    pub fn on_ack(&mut self, ack: u32, now: Instant) {
+        let mut acked_any = false;
+        while let Some(entry) = self.entries.front() {
+            if !seq_ge(ack, entry.end_seq()) {
+                break;
+            }
+            if !entry.retransmitted {
+                self.rto.on_rtt_sample(now.duration_since(entry.first_sent));
+            }
+            self.entries.pop_front();
+            acked_any = true;
+        }
+        if !acked_any {
+            return;
+        }
+        self.retries = 0;
+        // 全部确认则停止定时器，否则重新启动（RFC 6298 第 5.2、5.3 节）
+        self.deadline = if self.entries.is_empty() {
+            None
+        } else {
+            Some(now + self.rto.rto())
+        };
+    }
+
+    /// 检查定时器；到期时返回需要重传的最早段，重传次数用尽时返回错误
+    pub fn poll(&mut self, now: Instant) -> Result<Option<TcpSegment>> {
+        match self.deadline {
+            Some(deadline) if now >= deadline => {}
+            _ => return Ok(None),
+        }
+        let Some(entry) = self.entries.front_mut() else {
+            self.deadline = None;
+            return Ok(None);
+        };
+        if self.retries >= self.max_retries {
+            self.deadline = None;
+            return Err(StackError::ConnectionFailed(format!(
+                "retransmission timeout after {} retries",
+                self.retries
+            )));
+        }
+        self.retries += 1;
+        entry.retransmitted = true;
+        self.rto.back_off();
+        self.deadline = Some(now + self.rto.rto());
+        debug!(
+            "TCP retransmit seq {} (retry {}, rto {:?})",
+            entry.segment.seq_number,
+            self.retries,
+            self.rto.rto()
+        );
+        Ok(Some(entry.segment.clone()))
+    }
+
+    /// 快速重传：立即重传最早的未确认段，不影响定时器
+    pub fn retransmit_first(&mut self) -> Option<TcpSegment> {
+        let entry = self.entries.front_mut()?;
+        entry.retransmitted = true;
+        Some(entry.segment.clone())
+    }
+
+    /// 选择性重传：立即重传第一个满足条件的段，不影响定时器
+    pub fn retransmit_where(
+        &mut self,
+        mut predicate: impl FnMut(&TcpSegment) -> bool,
+    ) -> Option<TcpSegment> {
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|entry| predicate(&entry.segment))?;
+        entry.retransmitted = true;
+        Some(entry.segment.clone())
+    }
+
+    pub fn set_max_retries(&mut self, max_retries: u32) {
+        self.max_retries = max_retries;
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.deadline = None;
+        self.retries = 0;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    pub fn rto(&self) -> &RtoEstimator {
+        &self.rto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tcp::TCP_ACK;
+
+    fn data_segment(seq: u32, len: usize) -> TcpSegment {
+        TcpSegment::build(1, 2, seq, 0, TCP_ACK, 1024, vec![0; len])
+    }
+
+    #[test]
+    fn test_rto_estimation() {
+        let mut rto = RtoEstimator::new();
+        assert_eq!(rto.rto(), INITIAL_RTO);
+
+        rto.on_rtt_sample(Duration::from_millis(800));
+        // SRTT = 800ms, RTTVAR = 400ms, RTO = 800 + 4 * 400 = 2400ms
+        assert_eq!(rto.srtt(), Some(Duration::from_millis(800)));
+        assert_eq!(rto.rto(), Duration::from_millis(2400));
+
+        rto.on_rtt_sample(Duration::from_millis(400));
+        // RTTVAR = 3/4 * 400 + 1/4 * 400 = 400ms, SRTT = 7/8 * 800 + 1/8 * 400 = 750ms
+        assert_eq!(rto.rttvar(), Duration::from_millis(400));
+        assert_eq!(rto.srtt(), Some(Duration::from_millis(750)));
+        assert_eq!(rto.rto(), Duration::from_millis(2350));
+
+        // 很小的 RTT 也不会低于 1 秒
+        let mut rto = RtoEstimator::new();
+        rto.on_rtt_sample(Duration::from_millis(10));
+        assert_eq!(rto.rto(), MIN_RTO);
+    }
+
+    #[test]
+    fn test_backoff_and_karn() {
+        let start = Instant::now();
+        let mut queue = RetransmitQueue::new(3);
+        queue.push(data_segment(100, 10), start);
+        assert_eq!(queue.deadline(), Some(start + Duration::from_secs(1)));
+
+        // 定时器未到期
+        assert!(
+            queue
+                .poll(start + Duration::from_millis(999))
+                .unwrap()
+                .is_none()
+        );
+
+        // 第一次超时：重传并退避到 2 秒
+        let now = start + Duration::from_secs(1);
+        let seg = queue.poll(now).unwrap().unwrap();
+        assert_eq!(seg.seq_number, 100);
+        assert_eq!(queue.deadline(), Some(now + Duration::from_secs(2)));
+
+        // 确认重传过的段：不采样 RTT（Karn），但停止定时器
+        queue.on_ack(110, now + Duration::from_millis(50));
+        assert!(queue.is_empty());
+        assert_eq!(queue.deadline(), None);
+        assert_eq!(queue.rto().srtt(), None);
+        assert_eq!(queue.retries(), 0);
+
+        // 未重传的段会产生样本并清除退避
+        let now = now + Duration::from_secs(1);
+        queue.push(data_segment(110, 10), now);
+        queue.on_ack(120, now + Duration::from_millis(200));
+        assert_eq!(queue.rto().srtt(), Some(Duration::from_millis(200)));
+        assert_eq!(queue.rto().rto(), MIN_RTO);
+    }
+
+    #[test]
+    fn test_max_retries() {
+        let mut now = Instant::now();
+        let mut queue = RetransmitQueue::new(2);
+        queue.push(data_segment(1, 1), now);
+        for _ in 0..2 {
+            now = queue.deadline().unwrap();
+            assert!(queue.poll(now).unwrap().is_some());
+        }
+        now = queue.deadline().unwrap();
+        assert!(matches!(
+            queue.poll(now),
+            Err(StackError::ConnectionFailed(_))
+        ));
+    }
+}
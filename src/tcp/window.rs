@@ -0,0 +1,504 @@
+//! TCP 滑动窗口
+//!
+//! 发送窗口（SND.UNA/SND.NXT/SND.WND）、接收窗口（RCV.NXT/RCV.WND）、
+//! 乱序重组以及零窗口探测定时器
+
+use std::time::{Duration, Instant};
+
+use super::seq_gt;
+
+/// 乱序段最多保留的空洞数量，防止被碎片化的段耗尽内存
+const MAX_ASSEMBLER_RANGES: usize = 32;
+
+/// 零窗口探测间隔的上限
+const MAX_PERSIST_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 固定容量的环形缓冲区
+#[derive(Debug)]
+pub struct RingBuffer {
+    storage: Vec<u8>,
+    read_at: usize, // 可读数据的起始位置
+    length: usize,  // 可读数据的长度
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            storage: vec![0; capacity],
+            read_at: 0,
+            length: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.storage.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// 剩余空间
+    pub fn window(&self) -> usize {
+        self.capacity() - self.length
+    }
+
+    /// 追加数据，返回实际写入的字节数
+    pub fn enqueue_slice(&mut self, data: &[u8]) -> usize {
+        let written = self.write_unallocated(0, data);
+        self.enqueue_unallocated(written);
+        written
+    }
+
+    /// 读出并移除数据
+    pub fn dequeue_slice(&mut self, buf: &mut [u8]) -> usize {
+        let read = self.read_allocated(0, buf);
+        self.discard(read);
+        read
+    }
+
+    /// 丢弃开头的 n 个字节
+    pub fn discard(&mut self, n: usize) -> usize {
+        let n = n.min(self.length);
+        if self.capacity() > 0 {
+            self.read_at = (self.read_at + n) % self.capacity();
+        }
+        self.length -= n;
+        n
+    }
+
+    /// 从可读区域的 offset 处复制数据（不移除）
+    pub fn read_allocated(&self, offset: usize, buf: &mut [u8]) -> usize {
+        if offset >= self.length {
+            return 0;
+        }
+        let len = buf.len().min(self.length - offset);
+        for (i, byte) in buf[..len].iter_mut().enumerate() {
+            *byte = self.storage[(self.read_at + offset + i) % self.capacity()];
+        }
+        len
+    }
+
+    /// 在可读区域之后的 offset 处写入数据，但不计入可读长度
+    pub fn write_unallocated(&mut self, offset: usize, data: &[u8]) -> usize {
+        if offset >= self.window() {
+            return 0;
+        }
+        let len = data.len().min(self.window() - offset);
+        let start = self.read_at + self.length + offset;
+        for (i, byte) in data[..len].iter().enumerate() {
+            let index = (start + i) % self.capacity();
+            self.storage[index] = *byte;
+        }
+        len
+    }
+
+    /// 把已经写入的 n 个字节计入可读区域
+    pub fn enqueue_unallocated(&mut self, n: usize) {
+        self.length += n.min(self.window());
+    }
+}
+
+/// 乱序数据的区间记录，偏移相对于 RCV.NXT
+#[derive(Debug, Default)]
+pub struct Assembler {
+    ranges: Vec<(usize, usize)>, // 有序且不重叠的 [start, end)
+}
+
+impl Assembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录一段已收到的数据。区间过多时淘汰偏移最大的区间，
+    /// 保证填补空洞的数据总能被接受；新区间本身偏移最大时不记录，返回 false
+    pub fn add(&mut self, offset: usize, len: usize) -> bool {
+        if len == 0 {
+            return true;
+        }
+        let (mut start, mut end) = (offset, offset + len);
+        let mut merged = Vec::with_capacity(self.ranges.len() + 1);
+        let mut inserted = false;
+        for &(s, e) in &self.ranges {
+            if e < start {
+                merged.push((s, e));
+            } else if end < s {
+                if !inserted {
+                    merged.push((start, end));
+                    inserted = true;
+                }
+                merged.push((s, e));
+            } else {
+                start = start.min(s);
+                end = end.max(e);
+            }
+        }
+        if !inserted {
+            merged.push((start, end));
+        }
+        if merged.len() > MAX_ASSEMBLER_RANGES {
+            if merged.last() == Some(&(start, end)) {
+                return false;
+            }
+            merged.pop();
+        }
+        self.ranges = merged;
+        true
+    }
+
+    /// 取出从偏移 0 开始的连续数据长度，并把剩余区间前移
+    pub fn take_front(&mut self) -> usize {
+        match self.ranges.first() {
+            Some(&(0, end)) => {
+                self.ranges.remove(0);
+                for range in self.ranges.iter_mut() {
+                    range.0 -= end;
+                    range.1 -= end;
+                }
+                end
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// 乱序区间列表
+    pub fn ranges(&self) -> &[(usize, usize)] {
+        &self.ranges
+    }
+}
+
+/// 发送窗口
+/// 缓冲区中保存从 SND.UNA 开始的数据（已发送未确认 + 未发送）
+#[derive(Debug)]
+pub struct SendWindow {
+    pub una: u32,     // 最早的未确认序列号
+    pub nxt: u32,     // 下一个要发送的序列号
+    pub wnd: u32,     // 对方通告的窗口
+    pub wl1: u32,     // 上次更新窗口时的段序列号
+    pub wl2: u32,     // 上次更新窗口时的段确认号
+    pub max_wnd: u32, // 对方通告过的最大窗口，用于发送方 SWS 避免
+    pub buffer: RingBuffer,
+}
+
+impl SendWindow {
+    pub fn new(iss: u32, capacity: usize) -> Self {
+        Self {
+            una: iss,
+            nxt: iss,
+            wnd: 0,
+            wl1: 0,
+            wl2: 0,
+            max_wnd: 0,
+            buffer: RingBuffer::new(capacity),
+        }
+    }
+
+    /// 更新对方通告的窗口
+    pub fn update(&mut self, wnd: u32, seq: u32, ack: u32) {
+        self.wnd = wnd;
+        self.wl1 = seq;
+        self.wl2 = ack;
+        self.max_wnd = self.max_wnd.max(wnd);
+    }
+
+    /// 已发送未确认的数据量
+    pub fn in_flight(&self) -> usize {
+        self.nxt.wrapping_sub(self.una) as usize
+    }
+
+    /// 缓冲区中尚未发送的数据量
+    pub fn unsent(&self) -> usize {
+        self.buffer.len().saturating_sub(self.in_flight())
+    }
+
+    /// 发送窗口和拥塞窗口内还能发送的数据量
+    pub fn usable(&self, cwnd: usize) -> usize {
+        (self.wnd as usize)
+            .min(cwnd)
+            .saturating_sub(self.in_flight())
+    }
+
+    /// 发送方 SWS 避免（RFC 9293 第 3.8.6.2.1 节）：
+    /// 满 MSS、或能发完全部数据且允许发小段、或至少达到最大窗口的一半时才发送
+    pub fn sendable(&self, mss: usize, cwnd: usize, nagle: bool) -> usize {
+        let len = self.unsent().min(self.usable(cwnd)).min(mss);
+        if len == 0 {
+            return 0;
+        }
+        let all_data = len == self.unsent();
+        let idle = self.in_flight() == 0;
+        if len == mss
+            || (all_data && (idle || !nagle))
+            || len >= (self.max_wnd as usize).div_ceil(2)
+        {
+            len
+        } else {
+            0
+        }
+    }
+}
+
+/// 接收窗口
+/// 缓冲区中保存等待应用读取的数据，乱序数据暂存在可读区域之后
+#[derive(Debug)]
+pub struct ReceiveWindow {
+    pub nxt: u32,    // 期望收到的下一个序列号
+    right_edge: u32, // 已通告窗口的右边界，窗口不会向左收缩
+    max_wnd: u32,    // 能通告的最大窗口，取决于是否协商了窗口扩大
+    buffer: RingBuffer,
+    assembler: Assembler,
+}
+
+impl ReceiveWindow {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            nxt: 0,
+            right_edge: capacity.min(u16::MAX as usize) as u32,
+            max_wnd: u16::MAX as u32,
+            buffer: RingBuffer::new(capacity),
+            assembler: Assembler::new(),
+        }
+    }
+
+    /// 收到对方 SYN 后初始化 RCV.NXT
+    pub fn init(&mut self, irs: u32) {
+        self.nxt = irs.wrapping_add(1);
+        self.right_edge = self.nxt.wrapping_add(self.free());
+    }
+
+    /// 设置能通告的最大窗口（未协商窗口扩大时为 65535）
+    pub fn set_max_window(&mut self, max_wnd: u32) {
+        self.max_wnd = max_wnd;
+    }
+
+    /// 可以通告给对方的空闲空间
+    fn free(&self) -> u32 {
+        (self.buffer.window() as u32).min(self.max_wnd)
+    }
+
+    /// 当前通告的窗口 RCV.WND
+    pub fn wnd(&self) -> u32 {
+        self.right_edge.wrapping_sub(self.nxt)
+    }
+
+    /// 可读数据量
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn assembler(&self) -> &Assembler {
+        &self.assembler
+    }
+
+    /// 接收方 SWS 避免（RFC 9293 第 3.8.6.2.2 节）：
+    /// 可用空间比已通告窗口多出 min(缓冲区一半, MSS) 时才扩大窗口
+    fn window_increase(&self, mss: usize) -> Option<u32> {
+        let free = self.free();
+        let threshold = (self.buffer.capacity() / 2).min(mss) as u32;
+        let increase = free.saturating_sub(self.wnd());
+        (increase > 0 && increase >= threshold).then_some(free)
+    }
+
+    /// 按 SWS 规则更新通告窗口
+    pub fn update_window(&mut self, mss: usize) {
+        if let Some(free) = self.window_increase(mss) {
+            self.right_edge = self.nxt.wrapping_add(free);
+        }
+    }
+
+    /// 应用读走数据后是否值得发送窗口更新
+    pub fn window_update_due(&self, mss: usize) -> bool {
+        self.window_increase(mss).is_some()
+    }
+
+    /// 接收从 seq 开始的数据，乱序数据暂存，返回新变为可读的字节数
+    pub fn receive(&mut self, seq: u32, data: &[u8]) -> usize {
+        let offset = seq.wrapping_sub(self.nxt) as usize;
+        let wnd = self.wnd() as usize;
+        if offset >= wnd {
+            return 0;
+        }
+        let len = data.len().min(wnd - offset);
+        let written = self.buffer.write_unallocated(offset, &data[..len]);
+        if !self.assembler.add(offset, written) {
+            return 0;
+        }
+        let ready = self.assembler.take_front();
+        self.buffer.enqueue_unallocated(ready);
+        self.nxt = self.nxt.wrapping_add(ready as u32);
+        ready
+    }
+
+    /// 收到按序的 FIN，FIN 占用一个序列号
+    pub fn receive_fin(&mut self) {
+        self.nxt = self.nxt.wrapping_add(1);
+        if seq_gt(self.nxt, self.right_edge) {
+            self.right_edge = self.nxt;
+        }
+    }
+
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        self.buffer.dequeue_slice(buf)
+    }
+}
+
+/// 零窗口探测定时器（persist timer）
+#[derive(Debug, Default)]
+pub struct PersistTimer {
+    deadline: Option<Instant>,
+    interval: Duration,
+}
+
+impl PersistTimer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 定时器未运行时以 rto 为间隔启动
+    pub fn start(&mut self, now: Instant, rto: Duration) {
+        if self.deadline.is_none() {
+            self.interval = rto;
+            self.deadline = Some(now + rto);
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.deadline = None;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.deadline.is_some()
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// 到期时返回 true 并以指数退避重新启动
+    pub fn poll(&mut self, now: Instant) -> bool {
+        match self.deadline {
+            Some(deadline) if now >= deadline => {
+                self.interval = (self.interval * 2).min(MAX_PERSIST_INTERVAL);
+                self.deadline = Some(now + self.interval);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer_wraps() {
+        let mut ring = RingBuffer::new(8);
+        assert_eq!(ring.enqueue_slice(b"abcdef"), 6);
+        let mut buf = [0u8; 4];
+        assert_eq!(ring.dequeue_slice(&mut buf), 4);
+        assert_eq!(&buf, b"abcd");
+        // 写入跨越缓冲区末尾
+        assert_eq!(ring.enqueue_slice(b"ghijklmn"), 6);
+        assert_eq!(ring.window(), 0);
+        let mut buf = [0u8; 8];
+        assert_eq!(ring.dequeue_slice(&mut buf), 8);
+        assert_eq!(&buf, b"efghijkl");
+    }
+
+    #[test]
+    fn test_out_of_order_reassembly() {
+        let mut rcv = ReceiveWindow::new(64);
+        rcv.init(999);
+        assert_eq!(rcv.nxt, 1000);
+
+        // 先收到后面的两段
+        assert_eq!(rcv.receive(1010, b"klmno"), 0);
+        assert_eq!(rcv.receive(1005, b"fghij"), 0);
+        assert_eq!(rcv.assembler().ranges(), &[(5, 15)]);
+        assert_eq!(rcv.nxt, 1000);
+
+        // 空洞补齐后一次性交付
+        assert_eq!(rcv.receive(1000, b"abcde"), 15);
+        assert_eq!(rcv.nxt, 1015);
+        assert!(rcv.assembler().is_empty());
+        let mut buf = [0u8; 32];
+        assert_eq!(rcv.read(&mut buf), 15);
+        assert_eq!(&buf[..15], b"abcdefghijklmno");
+    }
+
+    #[test]
+    fn test_full_assembler_accepts_in_order_data() {
+        let mut rcv = ReceiveWindow::new(1024);
+        rcv.init(999);
+        // 32 个乱序区间，每个前面都有空洞
+        for i in 0..MAX_ASSEMBLER_RANGES as u32 {
+            assert_eq!(rcv.receive(1002 + i * 8, b"xx"), 0);
+        }
+        assert_eq!(rcv.assembler().ranges().len(), MAX_ASSEMBLER_RANGES);
+        // 偏移最大的新区间不再记录
+        assert_eq!(rcv.receive(1300, b"xx"), 0);
+        assert_eq!(rcv.assembler().ranges().len(), MAX_ASSEMBLER_RANGES);
+
+        // 填补 RCV.NXT 处的空洞总是被接受
+        assert_eq!(rcv.receive(1000, b"ab"), 4);
+        assert_eq!(rcv.nxt, 1004);
+        // 区间再次占满后，中间的新区间淘汰偏移最大的区间
+        assert_eq!(rcv.receive(1104, b"z"), 0);
+        assert_eq!(rcv.receive(1110, b"z"), 0);
+        let ranges = rcv.assembler().ranges();
+        assert_eq!(ranges.len(), MAX_ASSEMBLER_RANGES);
+        assert!(ranges.contains(&(106, 107)));
+        assert_eq!(ranges.last(), Some(&(238, 240)));
+    }
+
+    #[test]
+    fn test_receiver_sws_avoidance() {
+        let mut rcv = ReceiveWindow::new(100);
+        rcv.init(0);
+        rcv.receive(1, &[0; 100]);
+        assert_eq!(rcv.wnd(), 0);
+
+        // 只读走 10 字节，小于 MSS，不扩大窗口
+        let mut buf = [0u8; 10];
+        rcv.read(&mut buf);
+        assert!(!rcv.window_update_due(20));
+        rcv.update_window(20);
+        assert_eq!(rcv.wnd(), 0);
+
+        // 累计读走 30 字节后窗口重新打开
+        let mut buf = [0u8; 20];
+        rcv.read(&mut buf);
+        assert!(rcv.window_update_due(20));
+        rcv.update_window(20);
+        assert_eq!(rcv.wnd(), 30);
+    }
+
+    #[test]
+    fn test_sender_sws_avoidance() {
+        let mut snd = SendWindow::new(0, 1024);
+        snd.update(1000, 0, 0);
+        snd.buffer.enqueue_slice(&[0; 10]);
+        // 空闲时可以发送全部小数据
+        assert_eq!(snd.sendable(100, usize::MAX, true), 10);
+        snd.nxt = 10;
+        snd.buffer.enqueue_slice(&[0; 10]);
+        // 有未确认数据时 Nagle 算法会推迟小段
+        assert_eq!(snd.sendable(100, usize::MAX, true), 0);
+        assert_eq!(snd.sendable(100, usize::MAX, false), 10);
+        // 拥塞窗口同样限制发送量
+        assert_eq!(snd.sendable(5, 15, false), 5);
+    }
+}
@@ -2,10 +2,12 @@
 //!
 //! UDP（User Datagram Protocol）是无连接的传输层协议
 
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
 /// UDP 数据报结构
 use crate::error::{Result, StackError};
+use crate::icmp::internet_checksum;
+use crate::ipv6::{IPV6_NEXT_UDP, pseudo_header_checksum};
 
 const UDP_DATA_GRAM_MIN_SIZE: usize = 8;
 
@@ -69,6 +71,57 @@ impl UdpDatagram {
         bytes
     }
 
+    /// 校验收到的 UDP 数据报，校验和为 0 表示发送方没有计算（RFC 768）
+    pub fn verify_checksum(&self, src_addr: Ipv4Addr, dst_addr: Ipv4Addr) -> Result<()> {
+        if self.checksum == 0 {
+            return Ok(());
+        }
+        let expected = Self::calculate_udp_checksum(self, src_addr, dst_addr);
+        if expected != self.checksum {
+            return Err(StackError::ChecksumMismatch(format!(
+                "Udp checksum mismatch: expected {:#06x}, got {:#06x}",
+                expected, self.checksum
+            )));
+        }
+        Ok(())
+    }
+
+    /// 构造 IPv6 上的 UDP 数据报，IPv6 上校验和是必须的（RFC 8200 第 8.1 节）
+    pub fn build_ipv6(
+        src_port: u16,
+        dst_port: u16,
+        payload: Vec<u8>,
+        src_addr: Ipv6Addr,
+        dst_addr: Ipv6Addr,
+    ) -> Self {
+        let mut datagram = Self {
+            src_port,
+            dst_port,
+            length: (8 + payload.len()) as u16,
+            checksum: 0,
+            payload,
+        };
+        datagram.checksum =
+            match pseudo_header_checksum(src_addr, dst_addr, IPV6_NEXT_UDP, &datagram.to_bytes()) {
+                0 => 0xFFFF,
+                checksum => checksum,
+            };
+        datagram
+    }
+
+    /// 校验 IPv6 上收到的 UDP 数据报，校验和为 0 同样是错误
+    pub fn verify_checksum_ipv6(&self, src_addr: Ipv6Addr, dst_addr: Ipv6Addr) -> Result<()> {
+        if self.checksum == 0
+            || pseudo_header_checksum(src_addr, dst_addr, IPV6_NEXT_UDP, &self.to_bytes()) != 0
+        {
+            return Err(StackError::ChecksumMismatch(format!(
+                "Udp checksum {:#06x} mismatch",
+                self.checksum
+            )));
+        }
+        Ok(())
+    }
+
     pub fn build_echo(request: &UdpDatagram, src_addr: Ipv4Addr, dst_addr: Ipv4Addr) -> Self {
         let mut echo = Self {
             src_port: request.dst_port,
@@ -102,24 +155,10 @@ impl UdpDatagram {
         data.extend_from_slice(&[0, 0]); // checksum 占位
         data.extend_from_slice(&datagram.payload);
 
-        // 3.计算校验和
-        Self::calculate_checksum(&data)
-    }
-    fn calculate_checksum(data: &[u8]) -> u16 {
-        let mut sum: u32 = 0;
-
-        for chunk in data.chunks(2) {
-            let word = if chunk.len() == 2 {
-                u16::from_be_bytes([chunk[0], chunk[1]]) as u32
-            } else {
-                (chunk[0] as u32) << 8
-            };
-            sum += word;
-        }
-
-        while sum >> 16 != 0 {
-            sum = (sum & 0xFFFF) + (sum >> 16);
+        // 3.计算校验和，结果为 0 时发送全 1，因为 0 表示没有校验和（RFC 768）
+        match internet_checksum(&data) {
+            0 => 0xFFFF,
+            checksum => checksum,
         }
-        !sum as u16
     }
 }